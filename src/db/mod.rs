@@ -1,9 +1,32 @@
-use crate::models::{application::Application, organization::Organization};
+use crate::models::{
+    api_key::ApiKey,
+    application::{AppDeletion, Application},
+    inference_job::{InferenceJob, JobStatus},
+    invitation::{Invitation, InvitationStatus},
+    issue::{Issue, IssueSample, IssueStatus},
+    log::LogPayload,
+    log_signature::LogSignature,
+    membership::{Membership, OrgRole},
+    organization::{Organization, RetiredSecret},
+    rag_inference::{InferenceFeedback, RagInferenceVersion},
+    rollup::{RollupCount, RollupGranularity, RollupKey},
+    saved_search::SavedSearch,
+    user::User,
+};
+use crate::services::{credential_service, fingerprint_service};
+use chrono::{DateTime, TimeZone, Utc};
+use futures_util::stream::TryStreamExt;
 use mongodb::{
-    bson::{doc, oid::ObjectId},
-    options::{ClientOptions, IndexOptions, ServerApi, ServerApiVersion},
-    Client, Database, IndexModel,
+    bson::{self, doc, oid::ObjectId, Bson, Document},
+    error::{BulkWriteFailure, ErrorKind, WriteFailure},
+    options::{
+        AggregateOptions, ClientOptions, FindOneAndUpdateOptions, FindOneOptions, FindOptions,
+        IndexOptions, InsertManyOptions, ReturnDocument, ServerApi, ServerApiVersion,
+        UpdateOptions,
+    },
+    Client, Collection, Database, IndexModel,
 };
+use serde::Serialize;
 use std::env;
 
 #[derive(Clone)]
@@ -41,6 +64,172 @@ impl MongoRepo {
             .await
             .expect("Failed to create unique index on email");
 
+        // Organizations are looked up by CD-ID and authenticated by its secret
+        let organizations_collection = db.collection::<mongodb::bson::Document>("organizations");
+        let index_model = IndexModel::builder()
+            .keys(doc! { "cd_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        organizations_collection
+            .create_index(index_model, None)
+            .await
+            .expect("Failed to create unique index on cd_id");
+
+        // API keys are looked up by their public prefix
+        let api_keys_collection = db.collection::<mongodb::bson::Document>("api_keys");
+        let index_model = IndexModel::builder()
+            .keys(doc! { "key_prefix": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        api_keys_collection
+            .create_index(index_model, None)
+            .await
+            .expect("Failed to create unique index on key_prefix");
+
+        // A user has at most one membership per organization
+        let memberships_collection = db.collection::<mongodb::bson::Document>("memberships");
+        let index_model = IndexModel::builder()
+            .keys(doc! { "organization_id": 1, "user_id": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        memberships_collection
+            .create_index(index_model, None)
+            .await
+            .expect("Failed to create unique index on memberships");
+
+        // At most one pending invitation per email and organization
+        let invitations_collection = db.collection::<mongodb::bson::Document>("invitations");
+        let index_model = IndexModel::builder()
+            .keys(doc! { "organization_id": 1, "email": 1 })
+            .options(
+                IndexOptions::builder()
+                    .unique(true)
+                    .partial_filter_expression(doc! { "status": "pending" })
+                    .build(),
+            )
+            .build();
+        invitations_collection
+            .create_index(index_model, None)
+            .await
+            .expect("Failed to create unique index on invitations");
+
+        // One issue per fingerprint and application
+        let issues_collection = db.collection::<mongodb::bson::Document>("issues");
+        let index_model = IndexModel::builder()
+            .keys(doc! { "application_id": 1, "fingerprint": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        issues_collection
+            .create_index(index_model, None)
+            .await
+            .expect("Failed to create unique index on issues");
+
+        // Events of an issue are listed newest first, as are logs filtered by their fields
+        let logs_collection = db.collection::<mongodb::bson::Document>("logs");
+        let log_indexes = [
+            doc! { "issue_id": 1, "_id": -1 },
+            // Retention purges and storage reports scan an application's logs by age
+            doc! { "application_id": 1, "_id": 1 },
+            doc! { "application_id": 1, "level": 1, "_id": -1 },
+            doc! { "application_id": 1, "environment": 1, "_id": -1 },
+            doc! { "application_id": 1, "release": 1, "_id": -1 },
+            doc! { "application_id": 1, "server_name": 1, "_id": -1 },
+            doc! { "application_id": 1, "tags": 1, "_id": -1 },
+            doc! { "application_id": 1, "context.user.id": 1, "_id": -1 },
+            doc! { "application_id": 1, "method": 1, "_id": -1 },
+            doc! { "application_id": 1, "url": 1, "_id": -1 },
+            // Full-text search; queries always pin the application, which the prefix requires
+            doc! { "application_id": 1, "error": "text", "traceback": "text" },
+        ]
+        .into_iter()
+        .map(|keys| IndexModel::builder().keys(keys).build());
+        logs_collection
+            .create_indexes(log_indexes, None)
+            .await
+            .expect("Failed to create indexes on logs");
+
+        // Names are unique per owner and application; shared searches are listed per application
+        let saved_searches_collection = db.collection::<mongodb::bson::Document>("saved_searches");
+        let index_model = IndexModel::builder()
+            .keys(doc! { "application_id": 1, "owner_id": 1, "name": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        saved_searches_collection
+            .create_index(index_model, None)
+            .await
+            .expect("Failed to create unique index on saved searches");
+
+        // One job per log; workers claim the oldest available job of their application
+        let inference_jobs_collection = db.collection::<mongodb::bson::Document>("inference_jobs");
+        let job_indexes = [
+            IndexModel::builder()
+                .keys(doc! { "log_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            IndexModel::builder()
+                .keys(doc! { "application_id": 1, "status": 1, "_id": 1 })
+                .build(),
+        ];
+        inference_jobs_collection
+            .create_indexes(job_indexes, None)
+            .await
+            .expect("Failed to create indexes on inference jobs");
+
+        // Versions are numbered per log; retention and deletions go by application and log
+        let rag_inferences_collection = db.collection::<mongodb::bson::Document>("rag_inferences");
+        let index_model = IndexModel::builder()
+            .keys(doc! { "application_id": 1, "log_id": 1, "version": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        rag_inferences_collection
+            .create_index(index_model, None)
+            .await
+            .expect("Failed to create unique index on RAG inferences");
+
+        // One feedback per member and inference version; the export pages by revision
+        let feedback_collection = db.collection::<mongodb::bson::Document>("inference_feedback");
+        let feedback_indexes = [
+            IndexModel::builder()
+                .keys(doc! { "application_id": 1, "log_id": 1, "version": 1, "user_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            IndexModel::builder()
+                .keys(doc! { "application_id": 1, "revision": 1 })
+                .build(),
+        ];
+        feedback_collection
+            .create_indexes(feedback_indexes, None)
+            .await
+            .expect("Failed to create indexes on inference feedback");
+
+        // Similar logs are looked up by the bands of their signature
+        let signatures_collection = db.collection::<mongodb::bson::Document>("log_signatures");
+        let index_model = IndexModel::builder()
+            .keys(doc! { "application_id": 1, "bands": 1 })
+            .build();
+        signatures_collection
+            .create_index(index_model, None)
+            .await
+            .expect("Failed to create index on log signatures");
+
+        // One counter per application, bucket, message hash, level and release
+        for granularity in [RollupGranularity::Minute, RollupGranularity::Hour] {
+            let index_model = IndexModel::builder()
+                .keys(doc! {
+                    "application_id": 1,
+                    "bucket": 1,
+                    "message_hash": 1,
+                    "level": 1,
+                    "release": 1,
+                })
+                .options(IndexOptions::builder().unique(true).build())
+                .build();
+            db.collection::<mongodb::bson::Document>(granularity.collection())
+                .create_index(index_model, None)
+                .await
+                .expect("Failed to create unique index on log rollups");
+        }
+
         MongoRepo { db }
     }
 
@@ -69,36 +258,188 @@ impl MongoRepo {
         // Clean the incoming credentials
         let clean_cd_id = cd_id.trim().trim_matches('"');
         let clean_cd_secret = cd_secret.trim().trim_matches('"');
-        println!("CD-ID: {}, CD-Secret: {}", clean_cd_id, clean_cd_secret);
 
         log::debug!(
-            "Attempting to find organization with CD-ID: {} and CD-Secret: {}",
-            clean_cd_id,
-            clean_cd_secret
+            "Attempting to find organization with CD-ID: {}",
+            clean_cd_id
         );
 
-        // Use a more flexible query with $regex for exact matching
-        let filter = doc! {
-            "cd_id": clean_cd_id.to_string(),
-            "cd_secret": clean_cd_secret.to_string()
+        // Look the organization up by its public id only; the secret is checked against the stored hash
+        let org = match collection
+            .find_one(
+                doc! { "cd_id": clean_cd_id, "deleted_at": { "$exists": false } },
+                None,
+            )
+            .await?
+        {
+            Some(org) => org,
+            None => {
+                log::warn!("No organization found for CD-ID: {}", clean_cd_id);
+                return Ok(None);
+            }
         };
 
-        log::debug!("Query filter: {:?}", filter);
-        println!("Query filter: {:?}", filter);
-        let result = collection.find_one(filter, None).await?;
-
-        if let Some(ref org) = result {
+        if org_secret_matches(&org, clean_cd_secret) {
             log::info!("Found organization: {}", org.org_name);
-            log::debug!(
-                "Stored CD-ID: {}, CD-Secret: {}",
-                org.cd_id.trim_matches('"'),
-                org.cd_secret.trim_matches('"')
-            );
+            Ok(Some(org))
         } else {
-            log::warn!("No organization found for the provided credentials");
+            log::warn!("Invalid CD-Secret for CD-ID: {}", clean_cd_id);
+            Ok(None)
+        }
+    }
+
+    pub async fn get_organization_by_id(
+        &self,
+        org_id: ObjectId,
+    ) -> Result<Option<Organization>, mongodb::error::Error> {
+        let collection = self.db.collection::<Organization>("organizations");
+        collection
+            .find_one(
+                doc! { "_id": org_id, "deleted_at": { "$exists": false } },
+                None,
+            )
+            .await
+    }
+
+    /// Replaces the organization's CD-Secret hash, keeping the old one valid until `grace_period` elapses.
+    /// Returns `false` if the secret was rotated concurrently.
+    pub async fn rotate_organization_secret(
+        &self,
+        org: &Organization,
+        new_secret_hash: String,
+        grace_period: chrono::Duration,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Organization>("organizations");
+        let now = Utc::now();
+
+        // Drop secrets whose grace window already ended and retire the current one
+        let mut previous_secrets: Vec<RetiredSecret> = org
+            .previous_secrets
+            .iter()
+            .filter(|s| s.expires_at > now)
+            .cloned()
+            .collect();
+        if grace_period > chrono::Duration::zero() {
+            previous_secrets.push(RetiredSecret {
+                secret_hash: org.cd_secret_hash.clone(),
+                expires_at: now + grace_period,
+            });
         }
 
-        Ok(result)
+        let previous_secrets = bson::to_bson(&previous_secrets)?;
+        let result = collection
+            .update_one(
+                doc! { "_id": org.id, "cd_secret_hash": &org.cd_secret_hash },
+                doc! {
+                    "$set": {
+                        "cd_secret_hash": new_secret_hash,
+                        "previous_secrets": previous_secrets,
+                        "secret_rotated_at": now.to_rfc3339(),
+                    }
+                },
+                None,
+            )
+            .await?;
+
+        Ok(result.modified_count > 0)
+    }
+
+    /// Immediately revokes every rotated-out secret of an organization.
+    pub async fn revoke_previous_secrets(
+        &self,
+        org_id: ObjectId,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<Organization>("organizations");
+        let org = collection.find_one(doc! { "_id": org_id }, None).await?;
+        let revoked = org.map(|o| o.previous_secrets.len() as u64).unwrap_or(0);
+
+        collection
+            .update_one(
+                doc! { "_id": org_id },
+                doc! { "$set": { "previous_secrets": [] } },
+                None,
+            )
+            .await?;
+
+        Ok(revoked)
+    }
+
+    /// Applies `set` to an organization that is not deleted. Returns `false` if there is none.
+    pub async fn update_organization(
+        &self,
+        org_id: ObjectId,
+        set: Document,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Organization>("organizations");
+        let result = collection
+            .update_one(
+                doc! { "_id": org_id, "deleted_at": { "$exists": false } },
+                doc! { "$set": set },
+                None,
+            )
+            .await?;
+        Ok(result.matched_count > 0)
+    }
+
+    /// Marks an organization deleted, which stops its credentials and memberships from
+    /// authenticating. Returns `false` if it was already.
+    pub async fn mark_organization_deleted(
+        &self,
+        org_id: ObjectId,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Organization>("organizations");
+        let result = collection
+            .update_one(
+                doc! { "_id": org_id, "deleted_at": { "$exists": false } },
+                doc! { "$set": { "deleted_at": Utc::now().to_rfc3339() } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    pub async fn list_deleted_organizations(
+        &self,
+    ) -> Result<Vec<Organization>, mongodb::error::Error> {
+        let collection = self.db.collection::<Organization>("organizations");
+        let cursor = collection
+            .find(doc! { "deleted_at": { "$exists": true } }, None)
+            .await?;
+        cursor.try_collect().await
+    }
+
+    /// Counts the applications of an organization whose data is not purged yet.
+    pub async fn count_unpurged_applications(
+        &self,
+        org_id: ObjectId,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<Application>("applications");
+        collection
+            .count_documents(
+                doc! { "organization_id": org_id, "deletion.status": { "$ne": "purged" } },
+                None,
+            )
+            .await
+    }
+
+    /// Removes what is left of a deleted organization once its applications are purged:
+    /// memberships, invitations, application records and the organization itself.
+    pub async fn delete_organization_records(
+        &self,
+        org_id: ObjectId,
+    ) -> Result<(), mongodb::error::Error> {
+        let by_org = doc! { "organization_id": org_id };
+        for name in ["memberships", "invitations", "applications"] {
+            self.db
+                .collection::<Document>(name)
+                .delete_many(by_org.clone(), None)
+                .await?;
+        }
+        self.db
+            .collection::<Organization>("organizations")
+            .delete_one(doc! { "_id": org_id }, None)
+            .await?;
+        Ok(())
     }
 
     pub async fn get_application_by_id(
@@ -106,7 +447,8 @@ impl MongoRepo {
         app_id: ObjectId,
     ) -> Result<Option<Application>, mongodb::error::Error> {
         let collection = self.db.collection::<Application>("applications");
-        let filter = doc! { "_id": app_id };
+        // Deleted applications are only reachable through the deletion operations
+        let filter = doc! { "_id": app_id, "deletion": { "$exists": false } };
 
         match collection.find_one(filter, None).await {
             Ok(Some(app)) => {
@@ -124,18 +466,1922 @@ impl MongoRepo {
         }
     }
 
-    pub async fn setup_otp_ttl_index(db: &Database) {
-        let collection = db.collection::<mongodb::bson::Document>("otps");
+    // User operations
+    pub async fn get_user_by_email(
+        &self,
+        email: &str,
+    ) -> Result<Option<User>, mongodb::error::Error> {
+        let collection = self.db.collection::<User>("users");
+        collection.find_one(doc! { "email": email }, None).await
+    }
 
-        let index_options = IndexOptions::builder()
-            .expire_after(std::time::Duration::from_secs(600)) // 10-minute expiry
+    pub async fn get_users_by_ids(
+        &self,
+        user_ids: Vec<ObjectId>,
+    ) -> Result<Vec<User>, mongodb::error::Error> {
+        let collection = self.db.collection::<User>("users");
+        let cursor = collection
+            .find(doc! { "_id": { "$in": user_ids } }, None)
+            .await?;
+        cursor.try_collect().await
+    }
+
+    // Membership operations
+    pub async fn create_membership(
+        &self,
+        membership: Membership,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<Membership>("memberships");
+        collection.insert_one(membership, None).await?;
+        Ok(())
+    }
+
+    pub async fn get_membership(
+        &self,
+        org_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Option<Membership>, mongodb::error::Error> {
+        let collection = self.db.collection::<Membership>("memberships");
+        collection
+            .find_one(doc! { "organization_id": org_id, "user_id": user_id }, None)
+            .await
+    }
+
+    pub async fn list_memberships_for_user(
+        &self,
+        user_id: ObjectId,
+    ) -> Result<Vec<Membership>, mongodb::error::Error> {
+        let collection = self.db.collection::<Membership>("memberships");
+        let cursor = collection.find(doc! { "user_id": user_id }, None).await?;
+        cursor.try_collect().await
+    }
+
+    pub async fn list_memberships_for_org(
+        &self,
+        org_id: ObjectId,
+    ) -> Result<Vec<Membership>, mongodb::error::Error> {
+        let collection = self.db.collection::<Membership>("memberships");
+        let find_options = FindOptions::builder()
+            .sort(doc! { "created_at": 1 })
             .build();
+        let cursor = collection
+            .find(doc! { "organization_id": org_id }, find_options)
+            .await?;
+        cursor.try_collect().await
+    }
 
-        let index_model = IndexModel::builder()
-            .keys(doc! { "created_at": 1 })
-            .options(index_options)
+    pub async fn update_membership_role(
+        &self,
+        org_id: ObjectId,
+        user_id: ObjectId,
+        role: OrgRole,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Membership>("memberships");
+        let result = collection
+            .update_one(
+                doc! { "organization_id": org_id, "user_id": user_id },
+                doc! { "$set": { "role": role.as_str() } },
+                None,
+            )
+            .await?;
+        Ok(result.matched_count > 0)
+    }
+
+    pub async fn delete_membership(
+        &self,
+        org_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Membership>("memberships");
+        let result = collection
+            .delete_one(doc! { "organization_id": org_id, "user_id": user_id }, None)
+            .await?;
+        Ok(result.deleted_count > 0)
+    }
+
+    /// Demotes every owner of an organization but `user_id` to admin.
+    pub async fn demote_other_owners(
+        &self,
+        org_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<Membership>("memberships");
+        let result = collection
+            .update_many(
+                doc! {
+                    "organization_id": org_id,
+                    "user_id": { "$ne": user_id },
+                    "role": OrgRole::Owner.as_str(),
+                },
+                doc! { "$set": { "role": OrgRole::Admin.as_str() } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count)
+    }
+
+    pub async fn count_org_owners(&self, org_id: ObjectId) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<Membership>("memberships");
+        collection
+            .count_documents(
+                doc! { "organization_id": org_id, "role": OrgRole::Owner.as_str() },
+                None,
+            )
+            .await
+    }
+
+    // Invitation operations
+    pub async fn create_invitation(
+        &self,
+        invitation: Invitation,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<Invitation>("invitations");
+        collection.insert_one(invitation, None).await?;
+        Ok(())
+    }
+
+    pub async fn get_invitation_by_id(
+        &self,
+        invitation_id: ObjectId,
+    ) -> Result<Option<Invitation>, mongodb::error::Error> {
+        let collection = self.db.collection::<Invitation>("invitations");
+        collection
+            .find_one(doc! { "_id": invitation_id }, None)
+            .await
+    }
+
+    pub async fn get_invitation(
+        &self,
+        org_id: ObjectId,
+        invitation_id: ObjectId,
+    ) -> Result<Option<Invitation>, mongodb::error::Error> {
+        let collection = self.db.collection::<Invitation>("invitations");
+        collection
+            .find_one(
+                doc! { "_id": invitation_id, "organization_id": org_id },
+                None,
+            )
+            .await
+    }
+
+    pub async fn list_pending_invitations(
+        &self,
+        org_id: ObjectId,
+    ) -> Result<Vec<Invitation>, mongodb::error::Error> {
+        let collection = self.db.collection::<Invitation>("invitations");
+        let find_options = FindOptions::builder()
+            .sort(doc! { "created_at": -1 })
             .build();
+        let cursor = collection
+            .find(
+                doc! {
+                    "organization_id": org_id,
+                    "status": InvitationStatus::Pending.as_str(),
+                },
+                find_options,
+            )
+            .await?;
+        cursor.try_collect().await
+    }
 
-        collection.create_index(index_model, None).await.unwrap();
+    /// Gives a pending invitation a new nonce and expiry, invalidating previously sent tokens.
+    pub async fn refresh_invitation(
+        &self,
+        invitation_id: ObjectId,
+        nonce: &str,
+        expires_at: chrono::DateTime<Utc>,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Invitation>("invitations");
+        let result = collection
+            .update_one(
+                doc! {
+                    "_id": invitation_id,
+                    "status": InvitationStatus::Pending.as_str(),
+                },
+                doc! { "$set": { "nonce": nonce, "expires_at": expires_at.to_rfc3339() } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    pub async fn revoke_invitation(
+        &self,
+        org_id: ObjectId,
+        invitation_id: ObjectId,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Invitation>("invitations");
+        let result = collection
+            .update_one(
+                doc! {
+                    "_id": invitation_id,
+                    "organization_id": org_id,
+                    "status": InvitationStatus::Pending.as_str(),
+                },
+                doc! { "$set": { "status": InvitationStatus::Revoked.as_str() } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    /// Marks a pending invitation as accepted. Filters on the nonce so a token can only be used
+    /// once and only if it is the latest one sent. Returns `false` if it no longer matched.
+    pub async fn mark_invitation_accepted(
+        &self,
+        invitation_id: ObjectId,
+        nonce: &str,
+        user_id: ObjectId,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Invitation>("invitations");
+        let result = collection
+            .update_one(
+                doc! {
+                    "_id": invitation_id,
+                    "nonce": nonce,
+                    "status": InvitationStatus::Pending.as_str(),
+                },
+                doc! { "$set": {
+                    "status": InvitationStatus::Accepted.as_str(),
+                    "accepted_by": user_id,
+                    "accepted_at": Utc::now().to_rfc3339(),
+                } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    // Issue operations
+    /// Counts `events` occurrences of `log`'s fingerprint against its issue, creating the issue
+    /// on the first event. `log.id` must already be set; the first event is kept as the sample.
+    pub async fn record_issue_event(
+        &self,
+        log: &LogPayload,
+        fingerprint: &str,
+        events: i64,
+    ) -> Result<Issue, mongodb::error::Error> {
+        let collection = self.db.collection::<Issue>("issues");
+        let now = Utc::now().to_rfc3339();
+        let sample = IssueSample {
+            log_id: log.id.unwrap_or_default(),
+            error: log.error.clone(),
+            traceback: log.traceback.clone(),
+            url: log.url.clone(),
+            method: log.method.clone(),
+        };
+
+        let filter = doc! { "application_id": log.application_id, "fingerprint": fingerprint };
+        let mut set = doc! { "last_seen": &now };
+        if let Some(release) = &log.release {
+            set.insert("last_release", release);
+        }
+        let update = doc! {
+            "$setOnInsert": {
+                "organization_id": log.organization_id,
+                "title": fingerprint_service::issue_title(&log.error),
+                "culprit": fingerprint_service::route_template(&log.url),
+                "first_seen": &now,
+                "sample": bson::to_bson(&sample)?,
+                "status": IssueStatus::Unresolved.as_str(),
+                "is_regression": false,
+            },
+            "$set": set,
+            "$inc": { "event_count": events },
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+
+        // Two first events racing on the upsert: the loser retries and updates the winner's issue
+        let result = match collection
+            .find_one_and_update(filter.clone(), update.clone(), options.clone())
+            .await
+        {
+            Err(e) if is_duplicate_key(&e) => {
+                collection
+                    .find_one_and_update(filter, update, options)
+                    .await?
+            }
+            result => result?,
+        };
+        Ok(result.expect("upsert returns the document"))
+    }
+
+    pub async fn list_issues(
+        &self,
+        app_id: ObjectId,
+        status: Option<IssueStatus>,
+        limit: i64,
+        skip: u64,
+    ) -> Result<Vec<Issue>, mongodb::error::Error> {
+        let collection = self.db.collection::<Issue>("issues");
+        let mut filter = doc! { "application_id": app_id };
+        match status {
+            // Issues grouped before the workflow existed have no status yet
+            Some(IssueStatus::Unresolved) => {
+                filter.insert("status", doc! { "$in": ["unresolved", null] });
+            }
+            Some(status) => {
+                filter.insert("status", status.as_str());
+            }
+            None => {}
+        }
+        let find_options = FindOptions::builder()
+            .sort(doc! { "last_seen": -1 })
+            .limit(limit)
+            .skip(skip)
+            .build();
+        let cursor = collection.find(filter, find_options).await?;
+        cursor.try_collect().await
+    }
+
+    pub async fn get_issue(
+        &self,
+        app_id: ObjectId,
+        issue_id: ObjectId,
+    ) -> Result<Option<Issue>, mongodb::error::Error> {
+        let collection = self.db.collection::<Issue>("issues");
+        collection
+            .find_one(doc! { "_id": issue_id, "application_id": app_id }, None)
+            .await
+    }
+
+    /// Sets the workflow status of an issue and clears the settings of the previous status.
+    /// `fields` carries the settings of the new status (e.g. `ignore_until`).
+    pub async fn update_issue_status(
+        &self,
+        app_id: ObjectId,
+        issue_id: ObjectId,
+        status: IssueStatus,
+        mut fields: Document,
+    ) -> Result<Option<Issue>, mongodb::error::Error> {
+        let collection = self.db.collection::<Issue>("issues");
+        fields.insert("status", status.as_str());
+        let mut unset = doc! {
+            "resolved_at": "",
+            "resolved_in_release": "",
+            "ignore_until_count": "",
+            "ignore_until": "",
+        };
+        for key in fields.keys() {
+            unset.remove(key);
+        }
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        collection
+            .find_one_and_update(
+                doc! { "_id": issue_id, "application_id": app_id },
+                doc! { "$set": fields, "$unset": unset },
+                options,
+            )
+            .await
+    }
+
+    /// Reopens an issue that is still in status `from`. Returns `false` if another event or a
+    /// user changed the status first.
+    pub async fn reopen_issue(
+        &self,
+        issue_id: ObjectId,
+        from: IssueStatus,
+        regression: bool,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Issue>("issues");
+        let mut set = doc! { "status": IssueStatus::Unresolved.as_str() };
+        if regression {
+            set.insert("is_regression", true);
+            set.insert("regressed_at", Utc::now().to_rfc3339());
+        }
+        let result = collection
+            .update_one(
+                doc! { "_id": issue_id, "status": from.as_str() },
+                doc! {
+                    "$set": set,
+                    "$unset": {
+                        "resolved_at": "",
+                        "resolved_in_release": "",
+                        "ignore_until_count": "",
+                        "ignore_until": "",
+                    },
+                },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    pub async fn assign_issue(
+        &self,
+        app_id: ObjectId,
+        issue_id: ObjectId,
+        assignee_id: Option<ObjectId>,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Issue>("issues");
+        let update = match assignee_id {
+            Some(user_id) => doc! { "$set": { "assignee_id": user_id } },
+            None => doc! { "$unset": { "assignee_id": "" } },
+        };
+        let result = collection
+            .update_one(
+                doc! { "_id": issue_id, "application_id": app_id },
+                update,
+                None,
+            )
+            .await?;
+        Ok(result.matched_count > 0)
+    }
+
+    /// Logs matching `filter`, newest first.
+    pub async fn list_logs(
+        &self,
+        filter: Document,
+        limit: i64,
+    ) -> Result<Vec<LogPayload>, mongodb::error::Error> {
+        let collection = self.db.collection::<LogPayload>("logs");
+        let find_options = FindOptions::builder()
+            .sort(doc! { "_id": -1 })
+            .limit(limit)
+            .build();
+        let cursor = collection.find(filter, find_options).await?;
+        cursor.try_collect().await
+    }
+
+    /// Runs an aggregation pipeline over the `logs` collection.
+    pub async fn aggregate_logs(
+        &self,
+        pipeline: Vec<Document>,
+    ) -> Result<Vec<Document>, mongodb::error::Error> {
+        let collection = self.db.collection::<LogPayload>("logs");
+        let options = AggregateOptions::builder().allow_disk_use(true).build();
+        let cursor = collection.aggregate(pipeline, options).await?;
+        cursor.try_collect().await
+    }
+
+    pub async fn list_issue_logs(
+        &self,
+        app_id: ObjectId,
+        issue_id: ObjectId,
+        limit: i64,
+        skip: u64,
+    ) -> Result<Vec<LogPayload>, mongodb::error::Error> {
+        let collection = self.db.collection::<LogPayload>("logs");
+        let find_options = FindOptions::builder()
+            .sort(doc! { "_id": -1 })
+            .limit(limit)
+            .skip(skip)
+            .build();
+        let cursor = collection
+            .find(
+                doc! { "issue_id": issue_id, "application_id": app_id },
+                find_options,
+            )
+            .await?;
+        cursor.try_collect().await
+    }
+
+    /// Links stored logs to the issue their events were recorded on.
+    pub async fn set_logs_issue(
+        &self,
+        app_id: ObjectId,
+        log_ids: &[ObjectId],
+        issue_id: ObjectId,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<LogPayload>("logs");
+        collection
+            .update_many(
+                doc! { "_id": { "$in": log_ids }, "application_id": app_id },
+                doc! { "$set": { "issue_id": issue_id } },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    // Application deletion operations
+    /// Marks an application deleted. Returns `false` if it was already.
+    pub async fn soft_delete_application(
+        &self,
+        app_id: ObjectId,
+        deletion: &AppDeletion,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Application>("applications");
+        let result = collection
+            .update_one(
+                doc! { "_id": app_id, "deletion": { "$exists": false } },
+                doc! { "$set": { "deletion": bson::to_bson(deletion)? } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    /// Undoes the deletion of an application whose purge has not started.
+    pub async fn undo_application_deletion(
+        &self,
+        org_id: ObjectId,
+        app_id: ObjectId,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Application>("applications");
+        let result = collection
+            .update_one(
+                doc! {
+                    "_id": app_id,
+                    "organization_id": org_id,
+                    "deletion.status": "pending",
+                },
+                doc! { "$unset": { "deletion": "" } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    pub async fn get_deleted_application(
+        &self,
+        org_id: ObjectId,
+        app_id: ObjectId,
+    ) -> Result<Option<Application>, mongodb::error::Error> {
+        let collection = self.db.collection::<Application>("applications");
+        let filter = doc! {
+            "_id": app_id,
+            "organization_id": org_id,
+            "deletion": { "$exists": true },
+        };
+        collection.find_one(filter, None).await
+    }
+
+    pub async fn list_deleted_applications(
+        &self,
+        org_id: ObjectId,
+    ) -> Result<Vec<Application>, mongodb::error::Error> {
+        let collection = self.db.collection::<Application>("applications");
+        let filter = doc! { "organization_id": org_id, "deletion": { "$exists": true } };
+        let options = FindOptions::builder()
+            .sort(doc! { "deletion.requested_at": -1 })
+            .build();
+        let cursor = collection.find(filter, options).await?;
+        cursor.try_collect().await
+    }
+
+    /// Takes the lease on a deleted application that is due for purging and not held by
+    /// another server, marking it as purging.
+    pub async fn claim_app_deletion(
+        &self,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Option<Application>, mongodb::error::Error> {
+        let collection = self.db.collection::<Application>("applications");
+        let now = Utc::now().to_rfc3339();
+        let filter = doc! {
+            "deletion.status": { "$in": ["pending", "purging"] },
+            "deletion.purge_at": { "$lte": &now },
+            "$or": [
+                { "deletion.lease_until": { "$exists": false } },
+                { "deletion.lease_until": { "$lt": &now } },
+            ],
+        };
+        let update = doc! {
+            "$set": {
+                "deletion.status": "purging",
+                "deletion.lease_until": lease_until.to_rfc3339(),
+            },
+            // Keeps the time of the first attempt when resuming
+            "$min": { "deletion.started_at": &now },
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        collection
+            .find_one_and_update(filter, update, options)
+            .await
+    }
+
+    /// Counts a purged batch and extends the lease.
+    pub async fn record_purged_batch(
+        &self,
+        app_id: ObjectId,
+        collection_name: &str,
+        count: u64,
+        lease_until: DateTime<Utc>,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<Application>("applications");
+        let mut purged = Document::new();
+        purged.insert(format!("deletion.purged.{}", collection_name), count as i64);
+        collection
+            .update_one(
+                doc! { "_id": app_id },
+                doc! {
+                    "$set": { "deletion.lease_until": lease_until.to_rfc3339() },
+                    "$inc": purged,
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn complete_app_deletion(
+        &self,
+        app_id: ObjectId,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<Application>("applications");
+        collection
+            .update_one(
+                doc! { "_id": app_id },
+                doc! {
+                    "$set": {
+                        "deletion.status": "purged",
+                        "deletion.completed_at": Utc::now().to_rfc3339(),
+                    },
+                    "$unset": { "deletion.lease_until": "" },
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes up to `limit` documents of `collection_name` matching `filter`. Returns how many.
+    pub async fn delete_batch(
+        &self,
+        collection_name: &str,
+        filter: Document,
+        limit: i64,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>(collection_name);
+        let options = FindOptions::builder()
+            .projection(doc! { "_id": 1 })
+            .limit(limit)
+            .build();
+        let cursor = collection.find(filter, options).await?;
+        let ids: Vec<Bson> = cursor
+            .try_collect::<Vec<Document>>()
+            .await?
+            .into_iter()
+            .filter_map(|doc| doc.get("_id").cloned())
+            .collect();
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let result = collection
+            .delete_many(doc! { "_id": { "$in": ids } }, None)
+            .await?;
+        Ok(result.deleted_count)
+    }
+
+    pub async fn count_documents_in(
+        &self,
+        collection_name: &str,
+        filter: Document,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>(collection_name);
+        collection.count_documents(filter, None).await
+    }
+
+    // Retention operations
+    pub async fn list_applications(
+        &self,
+        org_id: Option<ObjectId>,
+    ) -> Result<Vec<Application>, mongodb::error::Error> {
+        let collection = self.db.collection::<Application>("applications");
+        let mut filter = doc! { "deletion": { "$exists": false } };
+        if let Some(org_id) = org_id {
+            filter.insert("organization_id", org_id);
+        }
+        let cursor = collection.find(filter, None).await?;
+        cursor.try_collect().await
+    }
+
+    /// Sets the retention of an application, or clears it when `days` is `None`.
+    pub async fn set_application_retention(
+        &self,
+        app_id: ObjectId,
+        days: Option<i64>,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Application>("applications");
+        let update = match days {
+            Some(days) => doc! { "$set": { "retention_days": days } },
+            None => doc! { "$unset": { "retention_days": "" } },
+        };
+        let result = collection
+            .update_one(doc! { "_id": app_id }, update, None)
+            .await?;
+        Ok(result.matched_count > 0)
+    }
+
+    /// Sets the default retention of an organization, or clears it when `days` is `None`.
+    pub async fn set_org_default_retention(
+        &self,
+        org_id: ObjectId,
+        days: Option<i64>,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Organization>("organizations");
+        let update = match days {
+            Some(days) => doc! { "$set": { "default_retention_days": days } },
+            None => doc! { "$unset": { "default_retention_days": "" } },
+        };
+        let result = collection
+            .update_one(doc! { "_id": org_id }, update, None)
+            .await?;
+        Ok(result.matched_count > 0)
+    }
+
+    /// Deletes the logs of an application received before the time of `before`. Logs restored
+    /// from an archive are left to `purge_restored_logs`.
+    pub async fn purge_logs_before(
+        &self,
+        app_id: ObjectId,
+        before: ObjectId,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("logs");
+        let filter = doc! {
+            "application_id": app_id,
+            "_id": { "$lt": before },
+            "restored_at": { "$exists": false },
+        };
+        let result = collection.delete_many(filter, None).await?;
+        Ok(result.deleted_count)
+    }
+
+    /// Oldest `limit` logs of an application received before the time of `before`, as stored.
+    pub async fn find_logs_before(
+        &self,
+        app_id: ObjectId,
+        before: ObjectId,
+        limit: i64,
+    ) -> Result<Vec<Document>, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("logs");
+        let filter = doc! {
+            "application_id": app_id,
+            "_id": { "$lt": before },
+            "restored_at": { "$exists": false },
+        };
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": 1 })
+            .limit(limit)
+            .build();
+        let cursor = collection.find(filter, options).await?;
+        cursor.try_collect().await
+    }
+
+    /// Deletes the logs of an application with ids from `first` to `last`.
+    pub async fn delete_log_range(
+        &self,
+        app_id: ObjectId,
+        first: ObjectId,
+        last: ObjectId,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("logs");
+        let filter = doc! {
+            "application_id": app_id,
+            "_id": { "$gte": first, "$lte": last },
+            "restored_at": { "$exists": false },
+        };
+        let result = collection.delete_many(filter, None).await?;
+        Ok(result.deleted_count)
+    }
+
+    /// Inserts logs as they were archived. Logs that are still stored are skipped; returns
+    /// how many were inserted.
+    pub async fn insert_log_documents(
+        &self,
+        logs: Vec<Document>,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("logs");
+        insert_many_skipping_duplicates(&collection, logs).await
+    }
+
+    /// Deletes the logs of an application restored from an archive before `before`.
+    pub async fn purge_restored_logs(
+        &self,
+        app_id: ObjectId,
+        before: DateTime<Utc>,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("logs");
+        let filter = doc! {
+            "application_id": app_id,
+            "restored_at": { "$lt": bson::DateTime::from_millis(before.timestamp_millis()) },
+        };
+        let result = collection.delete_many(filter, None).await?;
+        Ok(result.deleted_count)
+    }
+
+    /// Number of logs of an application and the id of the oldest one.
+    pub async fn application_log_stats(
+        &self,
+        app_id: ObjectId,
+    ) -> Result<(u64, Option<ObjectId>), mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("logs");
+        let filter = doc! { "application_id": app_id };
+        let count = collection.count_documents(filter.clone(), None).await?;
+        let options = FindOneOptions::builder()
+            .sort(doc! { "_id": 1 })
+            .projection(doc! { "_id": 1 })
+            .build();
+        let oldest = collection
+            .find_one(filter, options)
+            .await?
+            .and_then(|log| log.get_object_id("_id").ok());
+        Ok((count, oldest))
+    }
+
+    /// Average size in bytes of a stored log, from the collection's storage stats.
+    pub async fn average_log_size(&self) -> Result<Option<f64>, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("logs");
+        let pipeline = vec![doc! { "$collStats": { "storageStats": {} } }];
+        let mut cursor = collection.aggregate(pipeline, None).await?;
+        let stats = cursor.try_next().await?;
+        Ok(stats
+            .as_ref()
+            .and_then(|stats| stats.get_document("storageStats").ok())
+            .and_then(|storage| match storage.get("avgObjSize") {
+                Some(Bson::Int32(n)) => Some(f64::from(*n)),
+                Some(Bson::Int64(n)) => Some(*n as f64),
+                Some(Bson::Double(n)) => Some(*n),
+                _ => None,
+            }))
+    }
+
+    // Rollup operations
+    /// Adds `count` to a rollup counter, creating it if needed.
+    pub async fn increment_rollup(
+        &self,
+        granularity: RollupGranularity,
+        key: &RollupKey,
+        count: &RollupCount,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<Document>(granularity.collection());
+        let filter = doc! {
+            "application_id": key.application_id,
+            "bucket": bson::DateTime::from_millis(key.bucket),
+            "message_hash": &key.message_hash,
+            "level": &key.level,
+            "release": &key.release,
+        };
+        let update = doc! {
+            "$inc": { "count": count.count },
+            "$setOnInsert": {
+                "organization_id": count.organization_id,
+                "message": &count.message,
+            },
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+        collection.update_one(filter, update, options).await?;
+        Ok(())
+    }
+
+    /// Deletes the rollup counters of buckets starting before `before`, for one application
+    /// or all of them.
+    pub async fn delete_rollups(
+        &self,
+        app_id: Option<ObjectId>,
+        before: DateTime<Utc>,
+    ) -> Result<(), mongodb::error::Error> {
+        let mut filter =
+            doc! { "bucket": { "$lt": bson::DateTime::from_millis(before.timestamp_millis()) } };
+        if let Some(app_id) = app_id {
+            filter.insert("application_id", app_id);
+        }
+        for granularity in [RollupGranularity::Minute, RollupGranularity::Hour] {
+            self.db
+                .collection::<Document>(granularity.collection())
+                .delete_many(filter.clone(), None)
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn aggregate_rollups(
+        &self,
+        granularity: RollupGranularity,
+        pipeline: Vec<Document>,
+    ) -> Result<Vec<Document>, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>(granularity.collection());
+        let options = AggregateOptions::builder().allow_disk_use(true).build();
+        let cursor = collection.aggregate(pipeline, options).await?;
+        cursor.try_collect().await
+    }
+
+    /// Time from which the rollups of an application (or of all of them) count every log:
+    /// the earliest of the application's own rebuild and the coverage of all applications.
+    pub async fn rollup_coverage(
+        &self,
+        app_id: Option<ObjectId>,
+    ) -> Result<Option<DateTime<Utc>>, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("rollup_coverage");
+        let mut ids = vec![Bson::from("all")];
+        if let Some(app_id) = app_id {
+            ids.push(app_id.to_hex().into());
+        }
+        let cursor = collection
+            .find(doc! { "_id": { "$in": ids } }, None)
+            .await?;
+        let coverage: Vec<Document> = cursor.try_collect().await?;
+        Ok(coverage
+            .iter()
+            .filter_map(|doc| doc.get_datetime("complete_since").ok())
+            .filter_map(|since| Utc.timestamp_millis_opt(since.timestamp_millis()).single())
+            .min())
+    }
+
+    /// Logs of `filter` with only the fields rollups are keyed on.
+    pub async fn find_rollup_sources(
+        &self,
+        filter: Document,
+    ) -> Result<mongodb::Cursor<Document>, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("logs");
+        let options = FindOptions::builder()
+            .projection(doc! {
+                "organization_id": 1,
+                "application_id": 1,
+                "error": 1,
+                "level": 1,
+                "release": 1,
+            })
+            .build();
+        collection.find(filter, options).await
+    }
+
+    /// Records that the rollups of one application (or all of them) count every log received
+    /// since `since`. Unless `replace` is set, an existing coverage is kept.
+    pub async fn set_rollup_coverage(
+        &self,
+        app_id: Option<ObjectId>,
+        since: DateTime<Utc>,
+        replace: bool,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("rollup_coverage");
+        let id = app_id.map_or("all".to_string(), |id| id.to_hex());
+        let since = bson::DateTime::from_millis(since.timestamp_millis());
+        let update = if replace {
+            doc! { "$set": { "complete_since": since } }
+        } else {
+            doc! { "$setOnInsert": { "complete_since": since } }
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+        collection
+            .update_one(doc! { "_id": id }, update, options)
+            .await?;
+        Ok(())
+    }
+
+    // Saved search operations
+    pub async fn create_saved_search(
+        &self,
+        search: SavedSearch,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<SavedSearch>("saved_searches");
+        collection.insert_one(search, None).await?;
+        Ok(())
+    }
+
+    /// The searches of an application that `user_id` can see: their own and shared ones.
+    pub async fn list_saved_searches(
+        &self,
+        app_id: ObjectId,
+        user_id: ObjectId,
+    ) -> Result<Vec<SavedSearch>, mongodb::error::Error> {
+        let collection = self.db.collection::<SavedSearch>("saved_searches");
+        let find_options = FindOptions::builder().sort(doc! { "name": 1 }).build();
+        let cursor = collection
+            .find(visible_saved_searches(app_id, user_id), find_options)
+            .await?;
+        cursor.try_collect().await
+    }
+
+    pub async fn get_saved_search(
+        &self,
+        app_id: ObjectId,
+        user_id: ObjectId,
+        search_id: ObjectId,
+    ) -> Result<Option<SavedSearch>, mongodb::error::Error> {
+        let collection = self.db.collection::<SavedSearch>("saved_searches");
+        let mut filter = visible_saved_searches(app_id, user_id);
+        filter.insert("_id", search_id);
+        collection.find_one(filter, None).await
+    }
+
+    /// Updates a search owned by `owner_id` and returns it.
+    pub async fn update_saved_search(
+        &self,
+        app_id: ObjectId,
+        owner_id: ObjectId,
+        search_id: ObjectId,
+        mut fields: Document,
+    ) -> Result<Option<SavedSearch>, mongodb::error::Error> {
+        let collection = self.db.collection::<SavedSearch>("saved_searches");
+        fields.insert("updated_at", Utc::now().to_rfc3339());
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        collection
+            .find_one_and_update(
+                doc! { "_id": search_id, "application_id": app_id, "owner_id": owner_id },
+                doc! { "$set": fields },
+                options,
+            )
+            .await
+    }
+
+    pub async fn delete_saved_search(
+        &self,
+        app_id: ObjectId,
+        search_id: ObjectId,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<SavedSearch>("saved_searches");
+        let result = collection
+            .delete_one(doc! { "_id": search_id, "application_id": app_id }, None)
+            .await?;
+        Ok(result.deleted_count > 0)
+    }
+
+    /// Makes `search_id` the default search of `user_id` in the application, replacing the
+    /// previous one, or clears it when `pinned` is false.
+    pub async fn pin_saved_search(
+        &self,
+        app_id: ObjectId,
+        user_id: ObjectId,
+        search_id: ObjectId,
+        pinned: bool,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<SavedSearch>("saved_searches");
+        let unpin_filter = if pinned {
+            doc! { "application_id": app_id, "pinned_by": user_id }
+        } else {
+            doc! { "_id": search_id, "application_id": app_id }
+        };
+        collection
+            .update_many(
+                unpin_filter,
+                doc! { "$pull": { "pinned_by": user_id } },
+                None,
+            )
+            .await?;
+        if pinned {
+            collection
+                .update_one(
+                    doc! { "_id": search_id, "application_id": app_id },
+                    doc! { "$addToSet": { "pinned_by": user_id } },
+                    None,
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    // API key operations
+    pub async fn create_api_key(&self, key: ApiKey) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<ApiKey>("api_keys");
+        collection.insert_one(key, None).await?;
+        Ok(())
+    }
+
+    /// Finds a non-revoked API key by its public prefix. The caller verifies the secret part.
+    pub async fn get_active_api_key_by_prefix(
+        &self,
+        key_prefix: &str,
+    ) -> Result<Option<ApiKey>, mongodb::error::Error> {
+        let collection = self.db.collection::<ApiKey>("api_keys");
+        collection
+            .find_one(
+                doc! { "key_prefix": key_prefix, "revoked_at": { "$exists": false } },
+                None,
+            )
+            .await
+    }
+
+    pub async fn list_api_keys(
+        &self,
+        app_id: ObjectId,
+    ) -> Result<Vec<ApiKey>, mongodb::error::Error> {
+        let collection = self.db.collection::<ApiKey>("api_keys");
+        let find_options = FindOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .build();
+        let cursor = collection
+            .find(doc! { "application_id": app_id }, find_options)
+            .await?;
+        cursor.try_collect().await
+    }
+
+    /// Revokes an API key of the given application. Returns `false` if no active key matched.
+    pub async fn revoke_api_key(
+        &self,
+        app_id: ObjectId,
+        key_id: ObjectId,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<ApiKey>("api_keys");
+        let result = collection
+            .update_one(
+                doc! {
+                    "_id": key_id,
+                    "application_id": app_id,
+                    "revoked_at": { "$exists": false },
+                },
+                doc! { "$set": { "revoked_at": Utc::now().to_rfc3339() } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    // RAG inference version operations
+    pub async fn get_log(
+        &self,
+        app_id: ObjectId,
+        log_id: ObjectId,
+    ) -> Result<Option<LogPayload>, mongodb::error::Error> {
+        let collection = self.db.collection::<LogPayload>("logs");
+        collection
+            .find_one(doc! { "_id": log_id, "application_id": app_id }, None)
+            .await
+    }
+
+    /// Reserves version 1 for the inference a log got before versions existed. Returns `false`
+    /// if the log already has versions.
+    pub async fn claim_legacy_rag_inference(
+        &self,
+        app_id: ObjectId,
+        log_id: ObjectId,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<LogPayload>("logs");
+        let result = collection
+            .update_one(
+                doc! {
+                    "_id": log_id,
+                    "application_id": app_id,
+                    "rag_inference_versions": { "$exists": false },
+                },
+                doc! { "$set": { "rag_inference_versions": 1, "rag_inference_version": 1 } },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count > 0)
+    }
+
+    /// Numbers the next inference version of a log, `None` if there is no such log.
+    pub async fn next_rag_inference_version(
+        &self,
+        app_id: ObjectId,
+        log_id: ObjectId,
+    ) -> Result<Option<i32>, mongodb::error::Error> {
+        let collection = self.db.collection::<LogPayload>("logs");
+        let options = FindOneAndUpdateOptions::builder()
+            .projection(doc! { "rag_inference_versions": 1 })
+            .return_document(ReturnDocument::After)
+            .build();
+        let log = collection
+            .find_one_and_update(
+                doc! { "_id": log_id, "application_id": app_id },
+                doc! { "$inc": { "rag_inference_versions": 1 } },
+                options,
+            )
+            .await?;
+        Ok(log.and_then(|log| log.rag_inference_versions))
+    }
+
+    pub async fn insert_rag_inference(
+        &self,
+        version: &RagInferenceVersion,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<RagInferenceVersion>("rag_inferences");
+        collection.insert_one(version, None).await?;
+        Ok(())
+    }
+
+    /// Shows a new version on the log unless a version was accepted or a later one is shown.
+    pub async fn show_latest_rag_inference(
+        &self,
+        app_id: ObjectId,
+        log_id: ObjectId,
+        version: i32,
+        inference: &serde_json::Value,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<LogPayload>("logs");
+        collection
+            .update_one(
+                doc! {
+                    "_id": log_id,
+                    "application_id": app_id,
+                    "rag_inference_accepted": { "$ne": true },
+                    "$or": [
+                        { "rag_inference_version": { "$exists": false } },
+                        { "rag_inference_version": { "$lt": version } },
+                    ],
+                },
+                doc! {
+                    "$set": {
+                        "rag_inference": bson::to_bson(inference)?,
+                        "rag_inference_version": version,
+                    }
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Shows `version` on the log, as the accepted one or not.
+    pub async fn set_log_rag_inference(
+        &self,
+        app_id: ObjectId,
+        log_id: ObjectId,
+        version: &RagInferenceVersion,
+        accepted: bool,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<LogPayload>("logs");
+        let mut set = doc! {
+            "rag_inference": bson::to_bson(&version.inference)?,
+            "rag_inference_version": version.version,
+        };
+        let update = if accepted {
+            set.insert("rag_inference_accepted", true);
+            doc! { "$set": set }
+        } else {
+            doc! { "$set": set, "$unset": { "rag_inference_accepted": "" } }
+        };
+        let result = collection
+            .update_one(
+                doc! { "_id": log_id, "application_id": app_id },
+                update,
+                None,
+            )
+            .await?;
+        Ok(result.matched_count > 0)
+    }
+
+    /// Inference versions of a log, newest first.
+    pub async fn list_rag_inferences(
+        &self,
+        app_id: ObjectId,
+        log_id: ObjectId,
+    ) -> Result<Vec<RagInferenceVersion>, mongodb::error::Error> {
+        let collection = self.db.collection::<RagInferenceVersion>("rag_inferences");
+        let options = FindOptions::builder().sort(doc! { "version": -1 }).build();
+        let cursor = collection
+            .find(doc! { "application_id": app_id, "log_id": log_id }, options)
+            .await?;
+        cursor.try_collect().await
+    }
+
+    /// One version of a log's inference, or the latest when `version` is `None`.
+    pub async fn get_rag_inference(
+        &self,
+        app_id: ObjectId,
+        log_id: ObjectId,
+        version: Option<i32>,
+    ) -> Result<Option<RagInferenceVersion>, mongodb::error::Error> {
+        let collection = self.db.collection::<RagInferenceVersion>("rag_inferences");
+        let mut filter = doc! { "application_id": app_id, "log_id": log_id };
+        if let Some(version) = version {
+            filter.insert("version", version);
+        }
+        let options = FindOneOptions::builder()
+            .sort(doc! { "version": -1 })
+            .build();
+        collection.find_one(filter, options).await
+    }
+
+    /// Deletes the inference versions of the logs of an application received before `before`.
+    pub async fn purge_rag_inferences_before(
+        &self,
+        app_id: ObjectId,
+        before: ObjectId,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("rag_inferences");
+        let result = collection
+            .delete_many(
+                doc! { "application_id": app_id, "log_id": { "$lt": before } },
+                None,
+            )
+            .await?;
+        Ok(result.deleted_count)
+    }
+
+    // Inference feedback operations
+    /// Saves a member's feedback on an inference version, replacing the previous one.
+    pub async fn save_inference_feedback(
+        &self,
+        feedback: &InferenceFeedback,
+    ) -> Result<Option<InferenceFeedback>, mongodb::error::Error> {
+        let collection = self
+            .db
+            .collection::<InferenceFeedback>("inference_feedback");
+        let mut set = doc! {
+            "fixed": feedback.fixed,
+            "revision": feedback.revision,
+            "updated_at": feedback.updated_at.to_rfc3339(),
+        };
+        let mut unset = Document::new();
+        for (field, value) in [
+            ("helpful", feedback.helpful.map(Bson::Boolean)),
+            ("applied", feedback.applied.map(Bson::Boolean)),
+            ("correction", feedback.correction.clone().map(Bson::String)),
+        ] {
+            match value {
+                Some(value) => set.insert(field, value),
+                None => unset.insert(field, ""),
+            };
+        }
+        let mut update = doc! {
+            "$set": set,
+            "$setOnInsert": {
+                "organization_id": feedback.organization_id,
+                "created_at": feedback.created_at.to_rfc3339(),
+            },
+        };
+        if !unset.is_empty() {
+            update.insert("$unset", unset);
+        }
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+        collection
+            .find_one_and_update(
+                doc! {
+                    "application_id": feedback.application_id,
+                    "log_id": feedback.log_id,
+                    "version": feedback.version,
+                    "user_id": feedback.user_id,
+                },
+                update,
+                options,
+            )
+            .await
+    }
+
+    /// Feedback on a log's inference versions, or on one of them, newest version first.
+    pub async fn list_inference_feedback(
+        &self,
+        app_id: ObjectId,
+        log_id: ObjectId,
+        version: Option<i32>,
+    ) -> Result<Vec<InferenceFeedback>, mongodb::error::Error> {
+        let collection = self
+            .db
+            .collection::<InferenceFeedback>("inference_feedback");
+        let mut filter = doc! { "application_id": app_id, "log_id": log_id };
+        if let Some(version) = version {
+            filter.insert("version", version);
+        }
+        let options = FindOptions::builder()
+            .sort(doc! { "version": -1, "updated_at": -1 })
+            .build();
+        let cursor = collection.find(filter, options).await?;
+        cursor.try_collect().await
+    }
+
+    /// Whether any member still says this version fixed the log.
+    pub async fn inference_marked_fixed(
+        &self,
+        app_id: ObjectId,
+        log_id: ObjectId,
+        version: i32,
+    ) -> Result<bool, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("inference_feedback");
+        let count = collection
+            .count_documents(
+                doc! {
+                    "application_id": app_id,
+                    "log_id": log_id,
+                    "version": version,
+                    "fixed": true,
+                },
+                None,
+            )
+            .await?;
+        Ok(count > 0)
+    }
+
+    /// Marks the log as fixed by `version`, or clears the mark if it points at `version`.
+    pub async fn set_log_fixed_by(
+        &self,
+        app_id: ObjectId,
+        log_id: ObjectId,
+        version: i32,
+        fixed: bool,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<LogPayload>("logs");
+        let (filter, update) = if fixed {
+            (
+                doc! { "_id": log_id, "application_id": app_id },
+                doc! { "$set": { "rag_inference_fixed_by": version } },
+            )
+        } else {
+            (
+                doc! {
+                    "_id": log_id,
+                    "application_id": app_id,
+                    "rag_inference_fixed_by": version,
+                },
+                doc! { "$unset": { "rag_inference_fixed_by": "" } },
+            )
+        };
+        collection.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    /// Up to `limit` feedback entries of an application saved after the `after` revision,
+    /// oldest first.
+    pub async fn export_inference_feedback(
+        &self,
+        app_id: ObjectId,
+        after: Option<ObjectId>,
+        limit: i64,
+    ) -> Result<Vec<InferenceFeedback>, mongodb::error::Error> {
+        let collection = self
+            .db
+            .collection::<InferenceFeedback>("inference_feedback");
+        let mut filter = doc! { "application_id": app_id };
+        if let Some(after) = after {
+            filter.insert("revision", doc! { "$gt": after });
+        }
+        let options = FindOptions::builder()
+            .sort(doc! { "revision": 1 })
+            .limit(limit)
+            .build();
+        let cursor = collection.find(filter, options).await?;
+        cursor.try_collect().await
+    }
+
+    /// Logs of an application by id, in no particular order.
+    pub async fn get_logs_by_ids(
+        &self,
+        app_id: ObjectId,
+        log_ids: &[ObjectId],
+    ) -> Result<Vec<LogPayload>, mongodb::error::Error> {
+        let collection = self.db.collection::<LogPayload>("logs");
+        let cursor = collection
+            .find(
+                doc! { "application_id": app_id, "_id": { "$in": log_ids } },
+                None,
+            )
+            .await?;
+        cursor.try_collect().await
+    }
+
+    /// Inference versions of an application by log and version number, in no particular order.
+    pub async fn get_rag_inferences(
+        &self,
+        app_id: ObjectId,
+        versions: &[(ObjectId, i32)],
+    ) -> Result<Vec<RagInferenceVersion>, mongodb::error::Error> {
+        if versions.is_empty() {
+            return Ok(Vec::new());
+        }
+        let collection = self.db.collection::<RagInferenceVersion>("rag_inferences");
+        let any_of: Vec<Document> = versions
+            .iter()
+            .map(|(log_id, version)| doc! { "log_id": log_id, "version": version })
+            .collect();
+        let cursor = collection
+            .find(doc! { "application_id": app_id, "$or": any_of }, None)
+            .await?;
+        cursor.try_collect().await
+    }
+
+    /// Deletes the feedback on the logs of an application received before `before`.
+    pub async fn purge_inference_feedback_before(
+        &self,
+        app_id: ObjectId,
+        before: ObjectId,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("inference_feedback");
+        let result = collection
+            .delete_many(
+                doc! { "application_id": app_id, "log_id": { "$lt": before } },
+                None,
+            )
+            .await?;
+        Ok(result.deleted_count)
+    }
+
+    // Log signature operations
+    /// Stores signatures, skipping logs that already have one.
+    pub async fn insert_log_signatures(
+        &self,
+        signatures: Vec<LogSignature>,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<LogSignature>("log_signatures");
+        insert_many_skipping_duplicates(&collection, signatures).await?;
+        Ok(())
+    }
+
+    pub async fn get_log_signature(
+        &self,
+        app_id: ObjectId,
+        log_id: ObjectId,
+    ) -> Result<Option<LogSignature>, mongodb::error::Error> {
+        let collection = self.db.collection::<LogSignature>("log_signatures");
+        collection
+            .find_one(doc! { "_id": log_id, "application_id": app_id }, None)
+            .await
+    }
+
+    /// Signatures of logs received before `before` that share a band with `bands`, newest
+    /// first.
+    pub async fn find_signature_candidates(
+        &self,
+        app_id: ObjectId,
+        before: ObjectId,
+        bands: &[i64],
+        limit: i64,
+    ) -> Result<Vec<LogSignature>, mongodb::error::Error> {
+        let collection = self.db.collection::<LogSignature>("log_signatures");
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": -1 })
+            .limit(limit)
+            .build();
+        let cursor = collection
+            .find(
+                doc! {
+                    "application_id": app_id,
+                    "_id": { "$lt": before },
+                    "bands": { "$in": bands },
+                },
+                options,
+            )
+            .await?;
+        cursor.try_collect().await
+    }
+
+    /// The logs among `log_ids` that have an accepted inference or one marked as fixing them.
+    pub async fn resolved_log_ids(
+        &self,
+        app_id: ObjectId,
+        log_ids: &[ObjectId],
+    ) -> Result<Vec<ObjectId>, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("logs");
+        let options = FindOptions::builder().projection(doc! { "_id": 1 }).build();
+        let cursor = collection
+            .find(
+                doc! {
+                    "application_id": app_id,
+                    "_id": { "$in": log_ids },
+                    "$or": [
+                        { "rag_inference_accepted": true },
+                        { "rag_inference_fixed_by": { "$exists": true } },
+                    ],
+                },
+                options,
+            )
+            .await?;
+        let logs: Vec<Document> = cursor.try_collect().await?;
+        Ok(logs
+            .iter()
+            .filter_map(|log| log.get_object_id("_id").ok())
+            .collect())
+    }
+
+    /// Deletes the signatures of the logs of an application received before `before`.
+    pub async fn purge_log_signatures_before(
+        &self,
+        app_id: ObjectId,
+        before: ObjectId,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<Document>("log_signatures");
+        let result = collection
+            .delete_many(
+                doc! { "application_id": app_id, "_id": { "$lt": before } },
+                None,
+            )
+            .await?;
+        Ok(result.deleted_count)
+    }
+
+    // Inference job operations
+    /// Queues jobs, skipping logs that already have one.
+    pub async fn enqueue_inference_jobs(
+        &self,
+        jobs: Vec<InferenceJob>,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<InferenceJob>("inference_jobs");
+        insert_many_skipping_duplicates(&collection, jobs).await?;
+        Ok(())
+    }
+
+    /// Fails the jobs of an application whose lease expired on their last attempt.
+    pub async fn fail_exhausted_inference_jobs(
+        &self,
+        app_id: ObjectId,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<InferenceJob>("inference_jobs");
+        let now = Utc::now().to_rfc3339();
+        let result = collection
+            .update_many(
+                doc! {
+                    "application_id": app_id,
+                    "status": JobStatus::Leased.as_str(),
+                    "lease_until": { "$lt": &now },
+                    "$expr": { "$gte": ["$attempts", "$max_attempts"] },
+                },
+                doc! {
+                    "$set": {
+                        "status": JobStatus::Failed.as_str(),
+                        "last_error": "Lease expired on the last attempt",
+                        "updated_at": &now,
+                    },
+                    "$unset": { "lease_until": "" },
+                },
+                None,
+            )
+            .await?;
+        Ok(result.modified_count)
+    }
+
+    /// Leases the oldest job of an application that is queued and available, or whose lease
+    /// expired, to `worker_id`.
+    pub async fn claim_inference_job(
+        &self,
+        app_id: ObjectId,
+        worker_id: &str,
+        lease_until: DateTime<Utc>,
+    ) -> Result<Option<InferenceJob>, mongodb::error::Error> {
+        let collection = self.db.collection::<InferenceJob>("inference_jobs");
+        let now = Utc::now().to_rfc3339();
+        let filter = doc! {
+            "application_id": app_id,
+            "$or": [
+                { "status": JobStatus::Queued.as_str(), "available_at": { "$lte": &now } },
+                { "status": JobStatus::Leased.as_str(), "lease_until": { "$lt": &now } },
+            ],
+        };
+        let update = doc! {
+            "$set": {
+                "status": JobStatus::Leased.as_str(),
+                "worker_id": worker_id,
+                "lease_until": lease_until.to_rfc3339(),
+                "updated_at": &now,
+            },
+            "$inc": { "attempts": 1 },
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .sort(doc! { "_id": 1 })
+            .return_document(ReturnDocument::After)
+            .build();
+        collection
+            .find_one_and_update(filter, update, options)
+            .await
+    }
+
+    /// Applies `update` to a job if `worker_id` still holds its lease.
+    pub async fn update_leased_inference_job(
+        &self,
+        app_id: ObjectId,
+        job_id: ObjectId,
+        worker_id: &str,
+        update: Document,
+    ) -> Result<Option<InferenceJob>, mongodb::error::Error> {
+        let collection = self.db.collection::<InferenceJob>("inference_jobs");
+        let filter = doc! {
+            "_id": job_id,
+            "application_id": app_id,
+            "status": JobStatus::Leased.as_str(),
+            "worker_id": worker_id,
+        };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        collection
+            .find_one_and_update(filter, update, options)
+            .await
+    }
+
+    /// Marks the open jobs of a log completed, for inferences written without a lease.
+    pub async fn complete_inference_jobs_for_log(
+        &self,
+        app_id: ObjectId,
+        log_id: ObjectId,
+    ) -> Result<(), mongodb::error::Error> {
+        let collection = self.db.collection::<InferenceJob>("inference_jobs");
+        let now = Utc::now().to_rfc3339();
+        collection
+            .update_many(
+                doc! {
+                    "application_id": app_id,
+                    "log_id": log_id,
+                    "status": { "$ne": JobStatus::Completed.as_str() },
+                },
+                doc! {
+                    "$set": {
+                        "status": JobStatus::Completed.as_str(),
+                        "completed_at": &now,
+                        "updated_at": &now,
+                    },
+                    "$unset": { "lease_until": "" },
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes the jobs of the logs of an application received before `before`, whatever
+    /// their status.
+    pub async fn purge_inference_jobs_before(
+        &self,
+        app_id: ObjectId,
+        before: ObjectId,
+    ) -> Result<u64, mongodb::error::Error> {
+        let collection = self.db.collection::<InferenceJob>("inference_jobs");
+        let result = collection
+            .delete_many(
+                doc! { "application_id": app_id, "log_id": { "$lt": before } },
+                None,
+            )
+            .await?;
+        Ok(result.deleted_count)
+    }
+
+    pub async fn get_inference_job(
+        &self,
+        app_id: ObjectId,
+        job_id: ObjectId,
+    ) -> Result<Option<InferenceJob>, mongodb::error::Error> {
+        let collection = self.db.collection::<InferenceJob>("inference_jobs");
+        collection
+            .find_one(doc! { "_id": job_id, "application_id": app_id }, None)
+            .await
+    }
+
+    /// Lists the jobs of an application, newest first.
+    pub async fn list_inference_jobs(
+        &self,
+        app_id: ObjectId,
+        status: Option<JobStatus>,
+        limit: i64,
+    ) -> Result<Vec<InferenceJob>, mongodb::error::Error> {
+        let collection = self.db.collection::<InferenceJob>("inference_jobs");
+        let mut filter = doc! { "application_id": app_id };
+        if let Some(status) = status {
+            filter.insert("status", status.as_str());
+        }
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": -1 })
+            .limit(limit)
+            .build();
+        let cursor = collection.find(filter, options).await?;
+        cursor.try_collect().await
+    }
+
+    /// Number of jobs of an application per status.
+    pub async fn count_inference_jobs(
+        &self,
+        app_id: ObjectId,
+    ) -> Result<Vec<(String, i64)>, mongodb::error::Error> {
+        let collection = self.db.collection::<InferenceJob>("inference_jobs");
+        let pipeline = vec![
+            doc! { "$match": { "application_id": app_id } },
+            doc! { "$group": { "_id": "$status", "count": { "$sum": 1 } } },
+        ];
+        let mut cursor = collection.aggregate(pipeline, None).await?;
+        let mut counts = Vec::new();
+        while let Some(group) = cursor.try_next().await? {
+            if let Ok(status) = group.get_str("_id") {
+                let count = match group.get("count") {
+                    Some(Bson::Int32(n)) => *n as i64,
+                    Some(Bson::Int64(n)) => *n,
+                    _ => 0,
+                };
+                counts.push((status.to_string(), count));
+            }
+        }
+        Ok(counts)
+    }
+
+    pub async fn setup_otp_ttl_index(db: &Database) {
+        let collection = db.collection::<mongodb::bson::Document>("otps");
+
+        let index_options = IndexOptions::builder()
+            .expire_after(std::time::Duration::from_secs(600)) // 10-minute expiry
+            .build();
+
+        let index_model = IndexModel::builder()
+            .keys(doc! { "created_at": 1 })
+            .options(index_options)
+            .build();
+
+        collection.create_index(index_model, None).await.unwrap();
+    }
+
+    /// Hashes any organization credentials still stored in plaintext (`cd_secret`, `admin_password`)
+    /// and removes the plaintext fields.
+    pub async fn migrate_plaintext_org_credentials(db: &Database) {
+        let collection = db.collection::<Document>("organizations");
+        let filter = doc! {
+            "$or": [
+                { "cd_secret": { "$exists": true } },
+                { "admin_password": { "$exists": true } },
+            ]
+        };
+
+        let mut cursor = match collection.find(filter, None).await {
+            Ok(cursor) => cursor,
+            Err(e) => {
+                log::error!(
+                    "Failed to scan organizations for plaintext credentials: {}",
+                    e
+                );
+                return;
+            }
+        };
+
+        let mut migrated = 0;
+        while let Ok(Some(org)) = cursor.try_next().await {
+            let Ok(id) = org.get_object_id("_id") else {
+                continue;
+            };
+
+            let mut set = Document::new();
+            if let Ok(secret) = org.get_str("cd_secret") {
+                set.insert("cd_secret_hash", credential_service::hash_secret(secret));
+            }
+            if let Ok(password) = org.get_str("admin_password") {
+                match bcrypt::hash(password, bcrypt::DEFAULT_COST) {
+                    Ok(hash) => {
+                        set.insert("admin_password_hash", hash);
+                    }
+                    Err(e) => {
+                        log::error!("Failed to hash admin password for org {}: {}", id, e);
+                        continue;
+                    }
+                }
+            }
+
+            let update = doc! {
+                "$set": set,
+                "$unset": { "cd_secret": "", "admin_password": "" },
+            };
+            match collection
+                .update_one(doc! { "_id": id }, update, None)
+                .await
+            {
+                Ok(_) => migrated += 1,
+                Err(e) => log::error!("Failed to migrate credentials for org {}: {}", id, e),
+            }
+        }
+
+        if migrated > 0 {
+            log::info!(
+                "Hashed plaintext credentials for {} organization(s)",
+                migrated
+            );
+        }
+    }
+}
+
+/// Checks a CD-Secret against the current hash and any retired hash still inside its grace window.
+fn org_secret_matches(org: &Organization, cd_secret: &str) -> bool {
+    let now = Utc::now();
+    // Evaluate every candidate so timing does not reveal which one matched
+    let mut matched = credential_service::verify_secret(cd_secret, &org.cd_secret_hash);
+    for retired in &org.previous_secrets {
+        let valid = credential_service::verify_secret(cd_secret, &retired.secret_hash);
+        matched |= valid && retired.expires_at > now;
+    }
+    matched
+}
+
+fn visible_saved_searches(app_id: ObjectId, user_id: ObjectId) -> Document {
+    doc! {
+        "application_id": app_id,
+        "$or": [ { "owner_id": user_id }, { "shared": true } ],
+    }
+}
+
+/// Inserts `docs` without stopping at the first error, skipping the ones that violate a
+/// unique index. Returns how many were inserted.
+async fn insert_many_skipping_duplicates<T: Serialize>(
+    collection: &Collection<T>,
+    docs: Vec<T>,
+) -> Result<u64, mongodb::error::Error> {
+    let count = docs.len() as u64;
+    if count == 0 {
+        return Ok(0);
+    }
+    let options = InsertManyOptions::builder().ordered(false).build();
+    match collection.insert_many(docs, options).await {
+        Ok(result) => Ok(result.inserted_ids.len() as u64),
+        Err(e) => match e.kind.as_ref() {
+            ErrorKind::BulkWrite(BulkWriteFailure {
+                write_errors: Some(write_errors),
+                write_concern_error: None,
+                ..
+            }) if write_errors.iter().all(|w| w.code == 11000) => {
+                Ok(count - write_errors.len() as u64)
+            }
+            _ => Err(e),
+        },
+    }
+}
+
+/// Whether `error` is a unique index violation.
+pub(crate) fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    match error.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(e)) => e.code == 11000,
+        ErrorKind::Command(e) => e.code == 11000,
+        _ => false,
     }
 }
@@ -0,0 +1,565 @@
+use crate::db::MongoRepo;
+use crate::middlewares::auth_middleware::Claims;
+use crate::models::api_key::ApiKeyScope;
+use crate::models::application::Application;
+use crate::models::membership::OrgRole;
+use crate::models::organization::Organization;
+use crate::services::api_key_service::{self, ApiKeyError};
+use crate::services::tenant_cache::{CachedTenant, TenantCache};
+use crate::services::{credential_service, jwt_service};
+use actix_web::{
+    dev::Payload, http::StatusCode, web, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+    ResponseError,
+};
+use futures_util::future::LocalBoxFuture;
+use mongodb::bson::oid::ObjectId;
+use std::fmt;
+
+/// What the presented credentials are allowed to do.
+#[derive(Debug, Clone)]
+pub enum Access {
+    /// Org-wide CD-ID/CD-Secret pair. `current` is false for a rotated-out secret in its grace window.
+    OrgSecret { current: bool },
+    /// Application-scoped API key.
+    ApiKey {
+        key_id: ObjectId,
+        scopes: Vec<ApiKeyScope>,
+    },
+    /// Dashboard user signed in through the `auth_token` cookie.
+    Member { user_id: ObjectId, role: OrgRole },
+}
+
+impl Access {
+    pub fn allows(&self, scope: ApiKeyScope) -> bool {
+        match self {
+            Access::OrgSecret { .. } => true,
+            Access::ApiKey { scopes, .. } => scopes.contains(&scope),
+            Access::Member { role, .. } => role.grants(scope),
+        }
+    }
+
+    /// Org credentials act with full rights; members need at least `role`; API keys never qualify.
+    pub fn require_role(&self, role: OrgRole) -> Result<(), TenantAuthError> {
+        match self {
+            Access::OrgSecret { .. } => Ok(()),
+            Access::Member { role: actual, .. } if *actual >= role => Ok(()),
+            _ => Err(TenantAuthError::InsufficientRole(role)),
+        }
+    }
+
+    pub fn user_id(&self) -> Option<ObjectId> {
+        match self {
+            Access::Member { user_id, .. } => Some(*user_id),
+            _ => None,
+        }
+    }
+}
+
+/// Uniform tenant authentication failures, rendered as `{"error": "..."}`.
+#[derive(Debug)]
+pub enum TenantAuthError {
+    MissingCredentials,
+    InvalidHeader(&'static str),
+    InvalidCredentials,
+    InvalidApiKey,
+    MissingApplicationId,
+    InvalidApplicationId,
+    ApplicationNotFound,
+    ApplicationMismatch,
+    MissingScope(ApiKeyScope),
+    CurrentSecretRequired,
+    InvalidSession,
+    MissingOrganizationId,
+    InvalidOrganizationId,
+    OrganizationMismatch,
+    NotAMember,
+    InsufficientRole(OrgRole),
+    Internal,
+}
+
+impl fmt::Display for TenantAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TenantAuthError::MissingCredentials => {
+                write!(f, "Missing CD-ID/CD-Secret or CD-API-Key headers")
+            }
+            TenantAuthError::InvalidHeader(name) => write!(f, "Invalid {} header encoding", name),
+            TenantAuthError::InvalidCredentials => write!(f, "Invalid CD-ID or CD-Secret"),
+            TenantAuthError::InvalidApiKey => write!(f, "Invalid or revoked API key"),
+            TenantAuthError::MissingApplicationId => write!(f, "Missing Application-ID header"),
+            TenantAuthError::InvalidApplicationId => write!(f, "Invalid Application-ID format"),
+            TenantAuthError::ApplicationNotFound => write!(f, "Application not found"),
+            TenantAuthError::ApplicationMismatch => {
+                write!(f, "Application does not belong to the organization")
+            }
+            TenantAuthError::MissingScope(scope) => {
+                write!(f, "Credentials are missing the `{}` scope", scope.as_str())
+            }
+            TenantAuthError::CurrentSecretRequired => {
+                write!(f, "This action requires the current CD-Secret")
+            }
+            TenantAuthError::InvalidSession => write!(f, "Invalid or expired session"),
+            TenantAuthError::MissingOrganizationId => write!(f, "Missing Organization-ID header"),
+            TenantAuthError::InvalidOrganizationId => write!(f, "Invalid Organization-ID format"),
+            TenantAuthError::OrganizationMismatch => {
+                write!(f, "Credentials do not belong to this organization")
+            }
+            TenantAuthError::NotAMember => write!(f, "Not a member of this organization"),
+            TenantAuthError::InsufficientRole(role) => {
+                write!(f, "This action requires the `{}` role", role.as_str())
+            }
+            TenantAuthError::Internal => write!(f, "Failed to authenticate request"),
+        }
+    }
+}
+
+impl ResponseError for TenantAuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TenantAuthError::MissingCredentials
+            | TenantAuthError::InvalidCredentials
+            | TenantAuthError::InvalidApiKey
+            | TenantAuthError::ApplicationMismatch
+            | TenantAuthError::InvalidSession
+            | TenantAuthError::OrganizationMismatch => StatusCode::UNAUTHORIZED,
+            TenantAuthError::InvalidHeader(_)
+            | TenantAuthError::MissingApplicationId
+            | TenantAuthError::InvalidApplicationId
+            | TenantAuthError::MissingOrganizationId
+            | TenantAuthError::InvalidOrganizationId => StatusCode::BAD_REQUEST,
+            TenantAuthError::ApplicationNotFound => StatusCode::NOT_FOUND,
+            TenantAuthError::MissingScope(_)
+            | TenantAuthError::CurrentSecretRequired
+            | TenantAuthError::NotAMember
+            | TenantAuthError::InsufficientRole(_) => StatusCode::FORBIDDEN,
+            TenantAuthError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+impl From<ApiKeyError> for TenantAuthError {
+    fn from(err: ApiKeyError) -> Self {
+        match err {
+            ApiKeyError::Invalid => TenantAuthError::InvalidApiKey,
+            ApiKeyError::ApplicationMismatch => TenantAuthError::ApplicationMismatch,
+            ApiKeyError::ApplicationNotFound => TenantAuthError::ApplicationNotFound,
+            ApiKeyError::Database => TenantAuthError::Internal,
+        }
+    }
+}
+
+/// Tenant credentials as sent by agents and SDKs, or the dashboard session cookie.
+#[derive(Debug, Clone, Default)]
+pub struct TenantCredentials {
+    pub cd_id: Option<String>,
+    pub cd_secret: Option<String>,
+    pub api_key: Option<String>,
+    pub application_id: Option<String>,
+    pub organization_id: Option<String>,
+    /// Email from a valid `auth_token` cookie; `Err` if a cookie was sent but did not validate.
+    pub session: Option<Result<String, ()>>,
+}
+
+impl TenantCredentials {
+    /// Reads the credential headers and session cookie. Ids in the route path take precedence
+    /// over the `Application-ID` and `Organization-ID` headers.
+    pub fn from_request(req: &HttpRequest) -> Result<Self, TenantAuthError> {
+        let header = |name: &'static str| -> Result<Option<String>, TenantAuthError> {
+            match req.headers().get(name) {
+                Some(value) => value
+                    .to_str()
+                    .map(|v| Some(v.trim().trim_matches('"').to_string()))
+                    .map_err(|_| TenantAuthError::InvalidHeader(name)),
+                None => Ok(None),
+            }
+        };
+
+        let application_id = match req.match_info().get("application_id") {
+            Some(id) => Some(id.to_string()),
+            None => header("Application-ID")?,
+        };
+
+        let organization_id = match req.match_info().get("organization_id") {
+            Some(id) => Some(id.to_string()),
+            None => header("Organization-ID")?,
+        };
+
+        // Reuse the claims inserted by `AuthMiddleware` when the route is behind it
+        let session = match req.extensions().get::<Claims>() {
+            Some(claims) => Some(Ok(claims.sub.clone())),
+            None => req.cookie("auth_token").map(|cookie| {
+                jwt_service::decode_claims(cookie.value())
+                    .map(|claims| claims.sub)
+                    .map_err(|_| ())
+            }),
+        };
+
+        Ok(Self {
+            cd_id: header("CD-ID")?,
+            cd_secret: header("CD-Secret")?,
+            api_key: header(api_key_service::API_KEY_HEADER)?,
+            application_id,
+            organization_id,
+            session,
+        })
+    }
+
+    /// Resolves the organization from the CD-ID/CD-Secret pair, or from the session cookie
+    /// plus an organization id the user is a member of.
+    pub async fn resolve_org(
+        &self,
+        data: &MongoRepo,
+        cache: Option<&TenantCache>,
+    ) -> Result<AuthenticatedOrg, TenantAuthError> {
+        let (Some(cd_id), Some(cd_secret)) = (&self.cd_id, &self.cd_secret) else {
+            let email = self.session_email()?;
+            let org_id = self
+                .organization_id
+                .as_deref()
+                .ok_or(TenantAuthError::MissingOrganizationId)?;
+            let org_id =
+                ObjectId::parse_str(org_id).map_err(|_| TenantAuthError::InvalidOrganizationId)?;
+
+            let key = TenantCache::key(&["member", email, &org_id.to_hex()]);
+            if let Some(tenant) = lookup(cache, &key).await {
+                return Ok(AuthenticatedOrg {
+                    org: tenant.org,
+                    access: tenant.access,
+                });
+            }
+
+            let (org, access) = resolve_member(data, email, org_id).await?;
+            store(
+                cache,
+                key,
+                CachedTenant {
+                    org: org.clone(),
+                    app: None,
+                    access: access.clone(),
+                },
+            )
+            .await;
+            return Ok(AuthenticatedOrg { org, access });
+        };
+
+        let auth = self
+            .resolve_org_secret(data, cache, cd_id, cd_secret)
+            .await?;
+        if let Some(org_id) = &self.organization_id {
+            if ObjectId::parse_str(org_id).ok() != auth.org.id {
+                return Err(TenantAuthError::OrganizationMismatch);
+            }
+        }
+        Ok(auth)
+    }
+
+    async fn resolve_org_secret(
+        &self,
+        data: &MongoRepo,
+        cache: Option<&TenantCache>,
+        cd_id: &str,
+        cd_secret: &str,
+    ) -> Result<AuthenticatedOrg, TenantAuthError> {
+        let key = TenantCache::key(&["org", cd_id, cd_secret]);
+        if let Some(tenant) = lookup(cache, &key).await {
+            return Ok(AuthenticatedOrg {
+                org: tenant.org,
+                access: tenant.access,
+            });
+        }
+
+        let org = match data
+            .get_organization_by_cd_id_and_secret(cd_id, cd_secret)
+            .await
+        {
+            Ok(Some(org)) => org,
+            Ok(None) => return Err(TenantAuthError::InvalidCredentials),
+            Err(e) => {
+                log::error!("Database error during organization lookup: {}", e);
+                return Err(TenantAuthError::Internal);
+            }
+        };
+        let access = Access::OrgSecret {
+            current: credential_service::verify_secret(cd_secret, &org.cd_secret_hash),
+        };
+
+        store(
+            cache,
+            key,
+            CachedTenant {
+                org: org.clone(),
+                app: None,
+                access: access.clone(),
+            },
+        )
+        .await;
+
+        Ok(AuthenticatedOrg { org, access })
+    }
+
+    fn session_email(&self) -> Result<&str, TenantAuthError> {
+        match &self.session {
+            Some(Ok(email)) => Ok(email),
+            Some(Err(_)) => Err(TenantAuthError::InvalidSession),
+            None => Err(TenantAuthError::MissingCredentials),
+        }
+    }
+
+    /// Resolves the application from an API key, from the CD-ID/CD-Secret pair, or from the
+    /// session cookie of a member of the organization owning the application.
+    pub async fn resolve_app(
+        &self,
+        data: &MongoRepo,
+        cache: Option<&TenantCache>,
+    ) -> Result<AuthenticatedApp, TenantAuthError> {
+        let app_id = self.application_id.as_deref().unwrap_or_default();
+
+        if let Some(api_key) = &self.api_key {
+            let key = TenantCache::key(&["api_key", api_key, app_id]);
+            if let Some(tenant) = lookup(cache, &key).await {
+                return tenant.try_into();
+            }
+
+            let expected_app_id = self.application_id.as_deref();
+            let (api_key, app) =
+                api_key_service::authenticate_api_key(data, api_key, expected_app_id).await?;
+            let org = match data.get_organization_by_id(api_key.organization_id).await {
+                Ok(Some(org)) => org,
+                Ok(None) => return Err(TenantAuthError::InvalidApiKey),
+                Err(_) => return Err(TenantAuthError::Internal),
+            };
+            let tenant = CachedTenant {
+                org,
+                app: Some(app),
+                access: Access::ApiKey {
+                    key_id: api_key.id.unwrap(),
+                    scopes: api_key.scopes,
+                },
+            };
+            store(cache, key, tenant.clone()).await;
+            return tenant.try_into();
+        }
+
+        if app_id.is_empty() {
+            return match (&self.cd_id, &self.cd_secret, &self.session) {
+                (Some(_), Some(_), _) | (_, _, Some(_)) => {
+                    Err(TenantAuthError::MissingApplicationId)
+                }
+                _ => Err(TenantAuthError::MissingCredentials),
+            };
+        }
+        let parsed_app_id =
+            ObjectId::parse_str(app_id).map_err(|_| TenantAuthError::InvalidApplicationId)?;
+
+        let (Some(cd_id), Some(cd_secret)) = (&self.cd_id, &self.cd_secret) else {
+            // Dashboard session: the organization follows from the application
+            let email = self.session_email()?;
+            let key = TenantCache::key(&["member_app", email, app_id]);
+            if let Some(tenant) = lookup(cache, &key).await {
+                return tenant.try_into();
+            }
+
+            let app = find_app(data, parsed_app_id).await?;
+            let org_id = app
+                .organization_id
+                .ok_or(TenantAuthError::ApplicationNotFound)?;
+            let (org, access) = resolve_member(data, email, org_id).await?;
+
+            let tenant = CachedTenant {
+                org,
+                app: Some(app),
+                access,
+            };
+            store(cache, key, tenant.clone()).await;
+            return tenant.try_into();
+        };
+
+        let key = TenantCache::key(&["app", cd_id, cd_secret, app_id]);
+        if let Some(tenant) = lookup(cache, &key).await {
+            return tenant.try_into();
+        }
+
+        let AuthenticatedOrg { org, access } = self
+            .resolve_org_secret(data, cache, cd_id, cd_secret)
+            .await?;
+        let app = find_app(data, parsed_app_id).await?;
+        if app.organization_id != org.id {
+            return Err(TenantAuthError::ApplicationMismatch);
+        }
+
+        let tenant = CachedTenant {
+            org,
+            app: Some(app),
+            access,
+        };
+        store(cache, key, tenant.clone()).await;
+        tenant.try_into()
+    }
+}
+
+async fn find_app(data: &MongoRepo, app_id: ObjectId) -> Result<Application, TenantAuthError> {
+    match data.get_application_by_id(app_id).await {
+        Ok(Some(app)) => Ok(app),
+        Ok(None) => Err(TenantAuthError::ApplicationNotFound),
+        Err(_) => Err(TenantAuthError::Internal),
+    }
+}
+
+/// Resolves the membership of the signed-in user in an organization.
+async fn resolve_member(
+    data: &MongoRepo,
+    email: &str,
+    org_id: ObjectId,
+) -> Result<(Organization, Access), TenantAuthError> {
+    let user = match data.get_user_by_email(email).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(TenantAuthError::InvalidSession),
+        Err(_) => return Err(TenantAuthError::Internal),
+    };
+    let user_id = user.id.ok_or(TenantAuthError::InvalidSession)?;
+
+    let membership = match data.get_membership(org_id, user_id).await {
+        Ok(Some(membership)) => membership,
+        Ok(None) => return Err(TenantAuthError::NotAMember),
+        Err(_) => return Err(TenantAuthError::Internal),
+    };
+    let org = match data.get_organization_by_id(org_id).await {
+        Ok(Some(org)) => org,
+        Ok(None) => return Err(TenantAuthError::NotAMember),
+        Err(_) => return Err(TenantAuthError::Internal),
+    };
+
+    Ok((
+        org,
+        Access::Member {
+            user_id,
+            role: membership.role,
+        },
+    ))
+}
+
+async fn lookup(cache: Option<&TenantCache>, key: &[u8; 32]) -> Option<CachedTenant> {
+    match cache {
+        Some(cache) => cache.get(key).await,
+        None => None,
+    }
+}
+
+async fn store(cache: Option<&TenantCache>, key: [u8; 32], tenant: CachedTenant) {
+    if let Some(cache) = cache {
+        cache.insert(key, tenant).await;
+    }
+}
+
+/// An organization authenticated with its CD-ID/CD-Secret pair.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedOrg {
+    pub org: Organization,
+    pub access: Access,
+}
+
+impl AuthenticatedOrg {
+    pub fn org_id(&self) -> ObjectId {
+        self.org.id.unwrap()
+    }
+
+    pub fn require_role(&self, role: OrgRole) -> Result<(), TenantAuthError> {
+        self.access.require_role(role)
+    }
+
+    /// Allows managing the org's CD-Secrets: the current secret itself or an admin member.
+    /// Rejects secrets that are only valid because they are inside a rotation grace window.
+    pub fn require_secret_management(&self) -> Result<(), TenantAuthError> {
+        match self.access {
+            Access::OrgSecret { current: true } => Ok(()),
+            Access::OrgSecret { current: false } => Err(TenantAuthError::CurrentSecretRequired),
+            _ => self.access.require_role(OrgRole::Admin),
+        }
+    }
+}
+
+/// An application resolved from an API key or from org credentials plus an application id.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedApp {
+    pub org: Organization,
+    pub app: Application,
+    pub access: Access,
+}
+
+impl AuthenticatedApp {
+    pub fn org_id(&self) -> ObjectId {
+        self.org.id.unwrap()
+    }
+
+    pub fn app_id(&self) -> ObjectId {
+        self.app.id.unwrap()
+    }
+
+    pub fn require_role(&self, role: OrgRole) -> Result<(), TenantAuthError> {
+        self.access.require_role(role)
+    }
+
+    /// Checks that the credentials carry `scope`. Org credentials carry every scope.
+    pub fn require(&self, scope: ApiKeyScope) -> Result<(), TenantAuthError> {
+        if self.access.allows(scope) {
+            Ok(())
+        } else {
+            Err(TenantAuthError::MissingScope(scope))
+        }
+    }
+}
+
+impl TryFrom<CachedTenant> for AuthenticatedApp {
+    type Error = TenantAuthError;
+
+    fn try_from(tenant: CachedTenant) -> Result<Self, Self::Error> {
+        let app = tenant.app.ok_or(TenantAuthError::MissingApplicationId)?;
+        Ok(Self {
+            org: tenant.org,
+            app,
+            access: tenant.access,
+        })
+    }
+}
+
+impl FromRequest for AuthenticatedOrg {
+    type Error = TenantAuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let data = req
+                .app_data::<web::Data<MongoRepo>>()
+                .ok_or(TenantAuthError::Internal)?;
+            let cache = req.app_data::<web::Data<TenantCache>>();
+            TenantCredentials::from_request(&req)?
+                .resolve_org(data, cache.map(|c| c.get_ref()))
+                .await
+        })
+    }
+}
+
+impl FromRequest for AuthenticatedApp {
+    type Error = TenantAuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let data = req
+                .app_data::<web::Data<MongoRepo>>()
+                .ok_or(TenantAuthError::Internal)?;
+            let cache = req.app_data::<web::Data<TenantCache>>();
+            TenantCredentials::from_request(&req)?
+                .resolve_app(data, cache.map(|c| c.get_ref()))
+                .await
+        })
+    }
+}
@@ -0,0 +1,94 @@
+use async_graphql::{Context, Error, Object, Result};
+use mongodb::bson::oid::ObjectId;
+use crate::db::MongoRepo;
+use crate::graphql::query::{authorize_app, saved_search_error};
+use crate::models::api_key::ApiKeyScope;
+use crate::models::log::LogListQuery;
+use crate::models::saved_search::{
+    CreateSavedSearchPayload, LogFiltersGql, SavedSearchGql, UpdateSavedSearchPayload,
+};
+use crate::services::saved_search_service;
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    /// Saves a named set of log filters for the signed-in user.
+    async fn create_saved_search(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        filters: Option<LogFiltersGql>,
+        shared: Option<bool>,
+    ) -> Result<SavedSearchGql> {
+        let auth = authorize_app(ctx, ApiKeyScope::ReadLogs).await?;
+        let mongo_repo = ctx.data::<MongoRepo>()?;
+
+        let payload = CreateSavedSearchPayload {
+            name,
+            filters: log_filters(filters.unwrap_or_default())?,
+            shared: shared.unwrap_or(false),
+        };
+        let search = saved_search_service::create(mongo_repo, &auth, payload)
+            .await
+            .map_err(|e| saved_search_error(&e))?;
+        Ok(SavedSearchGql::new(search, auth.access.user_id().unwrap()))
+    }
+
+    /// Changes a saved search owned by the signed-in user. Arguments left out are kept.
+    async fn update_saved_search(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        name: Option<String>,
+        filters: Option<LogFiltersGql>,
+        shared: Option<bool>,
+    ) -> Result<SavedSearchGql> {
+        let auth = authorize_app(ctx, ApiKeyScope::ReadLogs).await?;
+        let mongo_repo = ctx.data::<MongoRepo>()?;
+
+        let payload = UpdateSavedSearchPayload {
+            name,
+            filters: filters.map(log_filters).transpose()?,
+            shared,
+        };
+        let search = saved_search_service::update(mongo_repo, &auth, parse_id(&id)?, payload)
+            .await
+            .map_err(|e| saved_search_error(&e))?;
+        Ok(SavedSearchGql::new(search, auth.access.user_id().unwrap()))
+    }
+
+    async fn delete_saved_search(&self, ctx: &Context<'_>, id: String) -> Result<bool> {
+        let auth = authorize_app(ctx, ApiKeyScope::ReadLogs).await?;
+        let mongo_repo = ctx.data::<MongoRepo>()?;
+
+        saved_search_service::delete(mongo_repo, &auth, parse_id(&id)?)
+            .await
+            .map_err(|e| saved_search_error(&e))?;
+        Ok(true)
+    }
+
+    /// Pins a saved search as the signed-in user's default view of the application, or unpins it.
+    async fn set_default_saved_search(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        pinned: bool,
+    ) -> Result<SavedSearchGql> {
+        let auth = authorize_app(ctx, ApiKeyScope::ReadLogs).await?;
+        let mongo_repo = ctx.data::<MongoRepo>()?;
+
+        let search = saved_search_service::set_default(mongo_repo, &auth, parse_id(&id)?, pinned)
+            .await
+            .map_err(|e| saved_search_error(&e))?;
+        Ok(SavedSearchGql::new(search, auth.access.user_id().unwrap()))
+    }
+}
+
+fn parse_id(id: &str) -> Result<ObjectId> {
+    ObjectId::parse_str(id).map_err(|_| Error::new("Invalid saved search ID format"))
+}
+
+fn log_filters(filters: LogFiltersGql) -> Result<LogListQuery> {
+    LogListQuery::try_from(filters).map_err(Error::new)
+}
@@ -1,9 +1,19 @@
-use async_graphql::{Context, Object, Result, Error};
+use async_graphql::{Context, Error, ErrorExtensions, Object, Result};
 use mongodb::bson::{doc, oid::ObjectId};
 use mongodb::options::FindOptions;
 use futures_util::stream::TryStreamExt;
 use crate::db::MongoRepo;
-use crate::models::log::{LogPayload, LogPayloadGql};
+use crate::extractors::tenant::{AuthenticatedApp, TenantCredentials};
+use crate::models::api_key::ApiKeyScope;
+use crate::models::log::{LogListQuery, LogPageGql, LogPayload, LogPayloadGql};
+use crate::models::log_signature::{SimilarLogGql, SimilarLogsQuery};
+use crate::models::saved_search::{LogFiltersGql, SavedSearchGql};
+use crate::models::stats::{LogStats, LogStatsParams, StatsDimension};
+use crate::services::log_stats::{self, LogStatsError};
+use crate::services::log_search::{self, LogSearchError};
+use crate::services::saved_search_service::{self, SavedSearchError};
+use crate::services::similarity_service;
+use crate::services::tenant_cache::TenantCache;
 
 pub struct QueryRoot;
 
@@ -16,35 +26,9 @@ impl QueryRoot {
         page: Option<i32>,
         limit: Option<i32>,
     ) -> Result<Vec<LogPayloadGql>> {
-        let headers = ctx.data::<std::collections::HashMap<String, String>>()?;
-        let cd_id = headers.get("CD-ID").ok_or_else(|| Error::new("Missing CD-ID"))?;
-        let cd_secret = headers
-            .get("CD-Secret")
-            .ok_or_else(|| Error::new("Missing CD-Secret"))?;
-        let app_id = headers
-            .get("Application-ID")
-            .ok_or_else(|| Error::new("Missing Application-ID"))?;
-
+        let auth = authorize_app(ctx, ApiKeyScope::ReadLogs).await?;
         let mongo_repo = ctx.data::<MongoRepo>()?;
 
-        // Authenticate organization
-        let org = mongo_repo
-            .get_organization_by_cd_id_and_secret(cd_id, cd_secret)
-            .await?
-            .ok_or_else(|| Error::new("Invalid CD-ID or CD-Secret"))?;
-
-        // Validate application
-        let app_id = ObjectId::parse_str(app_id)
-            .map_err(|_| Error::new("Invalid Application-ID format"))?;
-        let app = mongo_repo
-            .get_application_by_id(app_id)
-            .await?
-            .ok_or_else(|| Error::new("Application not found"))?;
-
-        if app.organization_id != Some(org.id.unwrap()) {
-            return Err(Error::new("Unauthorized"));
-        }
-
         // Pagination logic
         let page = page.unwrap_or(1);
         let limit = limit.unwrap_or(10);
@@ -52,7 +36,7 @@ impl QueryRoot {
 
         // Fetch logs
         let collection = mongo_repo.db.collection::<LogPayload>("logs");
-        let filter = doc! { "application_id": app.id.unwrap() };
+        let filter = doc! { "application_id": auth.app_id() };
         let find_options = FindOptions::builder()
             .skip(Some(skip as u64))
             .limit(Some(limit as i64))
@@ -68,46 +52,104 @@ impl QueryRoot {
         Ok(logs)
     }
 
-    /// Fetches a single log by its ID.
-    async fn log_by_id(
+    /// Searches logs with the search language, e.g. `level:error env:prod since:24h`,
+    /// newest first. Parse errors carry the `position` they refer to in their extensions.
+    async fn search(
         &self,
         ctx: &Context<'_>,
-        log_id: String,
-    ) -> Result<LogPayloadGql> {
-        let headers = ctx.data::<std::collections::HashMap<String, String>>()?;
-        let cd_id = headers.get("CD-ID").ok_or_else(|| Error::new("Missing CD-ID"))?;
-        let cd_secret = headers
-            .get("CD-Secret")
-            .ok_or_else(|| Error::new("Missing CD-Secret"))?;
-        let app_id = headers
-            .get("Application-ID")
-            .ok_or_else(|| Error::new("Missing Application-ID"))?;
+        query: String,
+        limit: Option<i64>,
+        cursor: Option<String>,
+    ) -> Result<LogPageGql> {
+        let auth = authorize_app(ctx, ApiKeyScope::ReadLogs).await?;
+        let mongo_repo = ctx.data::<MongoRepo>()?;
+
+        let params = LogListQuery {
+            query: Some(query),
+            limit,
+            cursor,
+            ..Default::default()
+        };
+        let filter =
+            log_search::build_filter(auth.app_id(), &params).map_err(|e| log_search_error(&e))?;
+
+        let (logs, next_cursor) =
+            log_search::fetch_page(mongo_repo, filter, params.limit()).await?;
+        Ok(LogPageGql {
+            logs: logs.into_iter().map(LogPayloadGql::from).collect(),
+            next_cursor,
+        })
+    }
 
+    /// Log counts per group and time bucket, e.g. errors per hour for the last 7 days with
+    /// `filters: { query: "level:error since:7d" }, interval: "1h"`.
+    async fn stats(
+        &self,
+        ctx: &Context<'_>,
+        filters: Option<LogFiltersGql>,
+        interval: Option<String>,
+        group_by: Option<StatsDimension>,
+        top: Option<i64>,
+    ) -> Result<LogStats> {
+        let auth = authorize_app(ctx, ApiKeyScope::ReadLogs).await?;
         let mongo_repo = ctx.data::<MongoRepo>()?;
 
-        // Authenticate organization
-        let org = mongo_repo
-            .get_organization_by_cd_id_and_secret(cd_id, cd_secret)
-            .await?
-            .ok_or_else(|| Error::new("Invalid CD-ID or CD-Secret"))?;
+        let filters =
+            LogListQuery::try_from(filters.unwrap_or_default()).map_err(Error::new)?;
+        let params = LogStatsParams {
+            interval,
+            group_by,
+            top,
+        };
+        log_stats::log_stats(mongo_repo, auth.app_id(), &filters, &params)
+            .await
+            .map_err(|e| match &e {
+                LogStatsError::Search(e) => log_search_error(e),
+                _ => Error::new(e.to_string()),
+            })
+    }
 
-        // Validate application
-        let app_id = ObjectId::parse_str(app_id)
-            .map_err(|_| Error::new("Invalid Application-ID format"))?;
-        let app = mongo_repo
-            .get_application_by_id(app_id)
-            .await?
-            .ok_or_else(|| Error::new("Application not found"))?;
+    /// The requesting user's saved searches and those shared with the organization.
+    async fn saved_searches(&self, ctx: &Context<'_>) -> Result<Vec<SavedSearchGql>> {
+        let auth = authorize_app(ctx, ApiKeyScope::ReadLogs).await?;
+        let mongo_repo = ctx.data::<MongoRepo>()?;
 
-        if app.organization_id != Some(org.id.unwrap()) {
-            return Err(Error::new("Unauthorized"));
-        }
+        let searches = saved_search_service::list(mongo_repo, &auth)
+            .await
+            .map_err(|e| saved_search_error(&e))?;
+        let user_id = auth.access.user_id().unwrap();
+        Ok(searches
+            .into_iter()
+            .map(|search| SavedSearchGql::new(search, user_id))
+            .collect())
+    }
+
+    async fn saved_search(&self, ctx: &Context<'_>, id: String) -> Result<SavedSearchGql> {
+        let auth = authorize_app(ctx, ApiKeyScope::ReadLogs).await?;
+        let mongo_repo = ctx.data::<MongoRepo>()?;
+
+        let search_id =
+            ObjectId::parse_str(&id).map_err(|_| Error::new("Invalid saved search ID format"))?;
+        let search = saved_search_service::get(mongo_repo, &auth, search_id)
+            .await
+            .map_err(|e| saved_search_error(&e))?;
+        Ok(SavedSearchGql::new(search, auth.access.user_id().unwrap()))
+    }
+
+    /// Fetches a single log by its ID.
+    async fn log_by_id(
+        &self,
+        ctx: &Context<'_>,
+        log_id: String,
+    ) -> Result<LogPayloadGql> {
+        let auth = authorize_app(ctx, ApiKeyScope::ReadLogs).await?;
+        let mongo_repo = ctx.data::<MongoRepo>()?;
 
         // Fetch the log
         let log_id = ObjectId::parse_str(&log_id)
             .map_err(|_| Error::new("Invalid Log ID format"))?;
         let collection = mongo_repo.db.collection::<LogPayload>("logs");
-        let filter = doc! { "_id": log_id, "application_id": app.id.unwrap() };
+        let filter = doc! { "_id": log_id, "application_id": auth.app_id() };
 
         let log = collection
             .find_one(filter, None)
@@ -116,4 +158,62 @@ impl QueryRoot {
 
         Ok(LogPayloadGql::from(log))
     }
+
+    /// Earlier logs of the application most like `log_id`, most similar first. By default only
+    /// logs with an accepted or fixing `ragInference` are returned.
+    async fn similar_logs(
+        &self,
+        ctx: &Context<'_>,
+        log_id: String,
+        limit: Option<i64>,
+        min_similarity: Option<f64>,
+        resolved: Option<bool>,
+    ) -> Result<Vec<SimilarLogGql>> {
+        let auth = authorize_app(ctx, ApiKeyScope::ReadLogs).await?;
+        let mongo_repo = ctx.data::<MongoRepo>()?;
+
+        let log_id = ObjectId::parse_str(&log_id)
+            .map_err(|_| Error::new("Invalid Log ID format"))?;
+        let query = SimilarLogsQuery { limit, min_similarity, resolved };
+        let similar = similarity_service::find_similar(mongo_repo, auth.app_id(), log_id, &query)
+            .await
+            .map_err(|e| Error::new(e.to_string()))?;
+        Ok(similar
+            .into_iter()
+            .map(|(similarity, log)| SimilarLogGql {
+                similarity,
+                log: LogPayloadGql::from(log),
+            })
+            .collect())
+    }
+}
+
+/// Resolves the application from the request credentials and checks that they carry `scope`.
+pub(crate) async fn authorize_app(ctx: &Context<'_>, scope: ApiKeyScope) -> Result<AuthenticatedApp> {
+    let credentials = ctx.data::<TenantCredentials>()?;
+    let mongo_repo = ctx.data::<MongoRepo>()?;
+    let tenant_cache = ctx.data::<TenantCache>()?;
+
+    let auth = credentials
+        .resolve_app(mongo_repo, Some(tenant_cache))
+        .await
+        .map_err(|e| Error::new(e.to_string()))?;
+    auth.require(scope).map_err(|e| Error::new(e.to_string()))?;
+    Ok(auth)
+}
+
+/// Search errors keep the `position` of query errors in their extensions.
+pub(crate) fn log_search_error(e: &LogSearchError) -> Error {
+    match e {
+        LogSearchError::Query(query_error) => Error::new(e.to_string())
+            .extend_with(|_, ext| ext.set("position", query_error.position as u64)),
+        _ => Error::new(e.to_string()),
+    }
+}
+
+pub(crate) fn saved_search_error(e: &SavedSearchError) -> Error {
+    match e {
+        SavedSearchError::InvalidFilters(e) => log_search_error(e),
+        _ => Error::new(e.to_string()),
+    }
 }
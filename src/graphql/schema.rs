@@ -1,11 +1,14 @@
-use async_graphql::{Schema, EmptyMutation, EmptySubscription};
+use async_graphql::{Schema, EmptySubscription};
+use crate::graphql::mutation::MutationRoot;
 use crate::graphql::query::QueryRoot;
 use crate::db::MongoRepo;
+use crate::services::tenant_cache::TenantCache;
 
-pub type AppSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+pub type AppSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
 
-pub fn create_schema(mongo_repo: MongoRepo) -> AppSchema {
-    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+pub fn create_schema(mongo_repo: MongoRepo, tenant_cache: TenantCache) -> AppSchema {
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription)
         .data(mongo_repo) // Share the MongoRepo instance
+        .data(tenant_cache)
         .finish()
 }
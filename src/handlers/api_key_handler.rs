@@ -0,0 +1,110 @@
+use crate::db::MongoRepo;
+use crate::extractors::tenant::AuthenticatedApp;
+use crate::models::api_key::{ApiKey, ApiKeyResponse, ApiKeyScope, CreateApiKeyPayload};
+use crate::services::tenant_cache::TenantCache;
+use crate::services::{api_key_service, credential_service};
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+
+/// Creates an API key for one application. The plaintext key is only returned here.
+pub async fn create_api_key(
+    auth: AuthenticatedApp,
+    payload: web::Json<CreateApiKeyPayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ManageApps) {
+        return e.error_response();
+    }
+
+    let payload = payload.into_inner();
+    if payload.scopes.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "At least one scope is required"
+        }));
+    }
+
+    let (token, key_prefix) = api_key_service::generate_api_key();
+    let mut scopes = payload.scopes;
+    scopes.sort();
+    scopes.dedup();
+    let key = ApiKey {
+        id: Some(ObjectId::new()),
+        organization_id: auth.org_id(),
+        application_id: auth.app_id(),
+        name: payload.name,
+        key_prefix,
+        key_hash: credential_service::hash_secret(&token),
+        scopes,
+        created_at: Utc::now(),
+        revoked_at: None,
+    };
+    let key_id = key.id;
+    let response = ApiKeyResponse::from(key.clone());
+
+    match data.create_api_key(key).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "API key created",
+            "id": key_id.map(|id| id.to_string()),
+            "api_key": token,
+            "key": response,
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to create API key"
+        })),
+    }
+}
+
+pub async fn list_api_keys(auth: AuthenticatedApp, data: web::Data<MongoRepo>) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ManageApps) {
+        return e.error_response();
+    }
+
+    match data.list_api_keys(auth.app_id()).await {
+        Ok(keys) => HttpResponse::Ok().json(
+            keys.into_iter()
+                .map(ApiKeyResponse::from)
+                .collect::<Vec<_>>(),
+        ),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to fetch API keys"
+        })),
+    }
+}
+
+pub async fn revoke_api_key(
+    auth: AuthenticatedApp,
+    path: web::Path<(String, String)>,
+    data: web::Data<MongoRepo>,
+    tenant_cache: web::Data<TenantCache>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ManageApps) {
+        return e.error_response();
+    }
+
+    let (_, key_id) = path.into_inner();
+    let key_id = match ObjectId::parse_str(key_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid API key ID format"
+            }));
+        }
+    };
+
+    match data.revoke_api_key(auth.app_id(), key_id).await {
+        Ok(true) => {
+            // Stop serving the revoked key from the lookup cache
+            tenant_cache.invalidate_app(auth.app_id()).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "API key revoked"
+            }))
+        }
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "API key not found"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to revoke API key"
+        })),
+    }
+}
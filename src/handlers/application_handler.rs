@@ -1,48 +1,34 @@
 use crate::db::MongoRepo;
-use crate::models::application::{Application, DeleteApplicationPayload};
-use actix_web::{web, HttpResponse, Responder};
+use crate::extractors::tenant::{AuthenticatedApp, AuthenticatedOrg};
+use crate::models::api_key::ApiKeyScope;
+use crate::models::application::{Application, UpdateRetentionPayload};
+use crate::models::membership::OrgRole;
+use crate::services::app_deletion_service::{self, AppDeletionError};
+use crate::services::retention_service;
+use crate::services::tenant_cache::TenantCache;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
 use mongodb::bson::oid::ObjectId;
-use actix_web::{ HttpRequest};
 use mongodb::bson::doc;
 use futures_util::stream::TryStreamExt;
 
 pub async fn create_application(
-    req: HttpRequest,
+    auth: AuthenticatedOrg,
     payload: web::Json<Application>,
     data: web::Data<MongoRepo>,
 ) -> impl Responder {
-    // Extract CD-ID and CD-SECRET headers
-    let cd_id = req
-        .headers()
-        .get("CD-ID")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-    let cd_secret = req
-        .headers()
-        .get("CD-SECRET")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-
-    // Authenticate organization using CD-ID and CD-SECRET
-    let org = match data.get_organization_by_cd_id_and_secret(cd_id, cd_secret).await {
-        Ok(Some(org)) => org,
-        Ok(None) => {
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Invalid CD-ID or CD-SECRET"
-            }));
-        }
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to authenticate organization"
-            }));
-        }
-    };
+    if let Err(e) = auth.require_role(OrgRole::Admin) {
+        return e.error_response();
+    }
 
     // Prepare the application
     let mut app = payload.into_inner();
     let app_id = ObjectId::new(); // Generate a new ObjectId
-    app.id = Some(app_id.clone());
-    app.organization_id = Some(org.id.unwrap()); // Set the authenticated organization's ID
+    app.id = Some(app_id);
+    app.organization_id = Some(auth.org_id()); // Set the authenticated organization's ID
+    app.deletion = None;
+    if let Err(e) = retention_service::validate_days(app.retention_days) {
+        return e.error_response();
+    }
 
     // Save the application to the database
     match data.create_application(app).await {
@@ -50,44 +36,19 @@ pub async fn create_application(
             "message": "Application created",
             "application_id": app_id.to_string() // Include the created application ID
         })),
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e.to_string()
+        })),
     }
 }
 
 pub async fn get_applications(
-    req: HttpRequest,
+    auth: AuthenticatedOrg,
     data: web::Data<MongoRepo>,
 ) -> impl Responder {
-    // Extract CD-ID and CD-Secret headers
-    let cd_id = req
-        .headers()
-        .get("CD-ID")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-    let cd_secret = req
-        .headers()
-        .get("CD-Secret")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-
-    // Authenticate organization using CD-ID and CD-Secret
-    let org = match data.get_organization_by_cd_id_and_secret(cd_id, cd_secret).await {
-        Ok(Some(org)) => org,
-        Ok(None) => {
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Invalid CD-ID or CD-Secret"
-            }));
-        }
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to authenticate organization"
-            }));
-        }
-    };
-
     // Fetch all applications for the authenticated organization
     let collection = data.db.collection::<Application>("applications");
-    let filter = doc! { "organization_id": org.id.unwrap() };
+    let filter = doc! { "organization_id": auth.org_id(), "deletion": { "$exists": false } };
 
     match collection.find(filter, None).await {
         Ok(mut cursor) => {
@@ -103,76 +64,158 @@ pub async fn get_applications(
     }
 }
 
+/// Deletes the application: it disappears right away and its logs, issues, keys and other
+/// data are purged once the grace period is over. Until then the deletion can be undone.
 pub async fn delete_application(
-    req: HttpRequest,
-    path: web::Path<String>, // Extract application ID from the URL
+    auth: AuthenticatedApp, // Resolves the application ID from the URL
     data: web::Data<MongoRepo>,
+    tenant_cache: web::Data<TenantCache>,
 ) -> impl Responder {
-    // Extract CD-ID and CD-SECRET headers
-    let cd_id = req
-        .headers()
-        .get("CD-ID")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-    let cd_secret = req
-        .headers()
-        .get("CD-SECRET")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-
-    // Authenticate organization using CD-ID and CD-SECRET
-    let org = match data.get_organization_by_cd_id_and_secret(cd_id, cd_secret).await {
-        Ok(Some(org)) => org,
-        Ok(None) => {
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Invalid CD-ID or CD-SECRET"
-            }));
+    if let Err(e) = auth.require(ApiKeyScope::ManageApps) {
+        return e.error_response();
+    }
+
+    match app_deletion_service::delete(&data, auth.app_id()).await {
+        Ok(Some(deletion)) => {
+            tenant_cache.invalidate_app(auth.app_id()).await;
+            HttpResponse::Accepted().json(serde_json::json!({
+                "message": "Application deleted",
+                "application_id": auth.app_id().to_hex(),
+                "purge_at": deletion.purge_at.to_rfc3339(),
+            }))
         }
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Application not found"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to delete application"
+        })),
+    }
+}
+
+/// Lists the organization's deleted applications and where their purge stands.
+pub async fn list_deleted_applications(
+    auth: AuthenticatedOrg,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require_role(OrgRole::Admin) {
+        return e.error_response();
+    }
+
+    let apps = match data.list_deleted_applications(auth.org_id()).await {
+        Ok(apps) => apps,
         Err(_) => {
             return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to authenticate organization"
+                "error": "Failed to fetch deleted applications"
             }));
         }
     };
+    let mut deletions = Vec::with_capacity(apps.len());
+    for app in &apps {
+        match app_deletion_service::progress(&data, app, false).await {
+            Ok(deletion) => deletions.push(deletion),
+            Err(_) => {
+                return HttpResponse::InternalServerError().json(serde_json::json!({
+                    "error": "Failed to fetch deleted applications"
+                }));
+            }
+        }
+    }
+    HttpResponse::Ok().json(deletions)
+}
 
-    // Validate and parse application_id from the URL
-    let application_id = match ObjectId::parse_str(&path.into_inner()) {
+/// Deletion state of one application, with the documents left to purge while it is purging.
+pub async fn get_application_deletion(
+    auth: AuthenticatedOrg,
+    path: web::Path<String>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require_role(OrgRole::Admin) {
+        return e.error_response();
+    }
+
+    let app_id = match parse_application_id(path.into_inner()) {
         Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Invalid application ID format"
-            }));
-        }
+        Err(response) => return response,
     };
+    let app = match data.get_deleted_application(auth.org_id(), app_id).await {
+        Ok(Some(app)) => app,
+        Ok(None) => return AppDeletionError::NotFound.error_response(),
+        Err(_) => return AppDeletionError::Database.error_response(),
+    };
+    match app_deletion_service::progress(&data, &app, true).await {
+        Ok(deletion) => HttpResponse::Ok().json(deletion),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to fetch purge progress"
+        })),
+    }
+}
 
-    // Ensure the application belongs to the authenticated organization
-    let app = match data.get_application_by_id(application_id).await {
-        Ok(Some(app)) if app.organization_id == Some(org.id.unwrap()) => app,
-        Ok(_) => {
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Application does not belong to the authenticated organization"
-            }));
-        }
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to validate application"
-            }));
-        }
+/// Undoes the deletion of an application while its grace period lasts.
+pub async fn restore_application(
+    auth: AuthenticatedOrg,
+    path: web::Path<String>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require_role(OrgRole::Admin) {
+        return e.error_response();
+    }
+
+    let app_id = match parse_application_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
     };
+    match app_deletion_service::undo(&data, auth.org_id(), app_id).await {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Application restored",
+            "application_id": app_id.to_hex(),
+        })),
+        Err(e) => e.error_response(),
+    }
+}
 
-    // Delete the application
-    let collection = data.db.collection::<Application>("applications");
-    match collection.delete_one(doc! { "_id": app.id.unwrap() }, None).await {
-        Ok(delete_result) if delete_result.deleted_count > 0 => {
+fn parse_application_id(app_id: String) -> Result<ObjectId, HttpResponse> {
+    ObjectId::parse_str(app_id).map_err(|_| {
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid application ID format"
+        }))
+    })
+}
+
+/// Sets how many days the application's logs are kept; `null` inherits the organization's
+/// default. Expired logs are deleted by the next purge.
+pub async fn update_application_retention(
+    auth: AuthenticatedApp,
+    payload: web::Json<UpdateRetentionPayload>,
+    data: web::Data<MongoRepo>,
+    tenant_cache: web::Data<TenantCache>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ManageApps) {
+        return e.error_response();
+    }
+
+    let days = payload.into_inner().retention_days;
+    match retention_service::set_application_retention(&data, auth.app_id(), days).await {
+        Ok(()) => {
+            tenant_cache.invalidate_app(auth.app_id()).await;
             HttpResponse::Ok().json(serde_json::json!({
-                "message": "Application deleted successfully"
+                "message": "Retention updated",
+                "retention_days": days,
             }))
         }
-        Ok(_) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Application not found"
-        })),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Lists how many logs each application of the organization stores and when they expire.
+pub async fn get_application_storage(
+    auth: AuthenticatedOrg,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    match retention_service::storage_report(&data, &auth.org).await {
+        Ok(report) => HttpResponse::Ok().json(report),
         Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to delete application"
+            "error": "Failed to compute storage"
         })),
     }
 }
@@ -0,0 +1,169 @@
+use crate::db::MongoRepo;
+use crate::extractors::tenant::AuthenticatedApp;
+use crate::models::api_key::ApiKeyScope;
+use crate::models::inference_job::{
+    ClaimJobsPayload, CompleteJobPayload, FailJobPayload, HeartbeatPayload, ListJobsQuery,
+};
+use crate::services::inference_queue::{self, job_json, MAX_CLAIM};
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use mongodb::bson::oid::ObjectId;
+
+/// Counts the application's jobs per status and lists the latest ones, optionally of one status.
+pub async fn list_jobs(
+    auth: AuthenticatedApp,
+    query: web::Query<ListJobsQuery>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    let query = query.into_inner();
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let (counts, jobs) = match (
+        data.count_inference_jobs(auth.app_id()).await,
+        data.list_inference_jobs(auth.app_id(), query.status, limit)
+            .await,
+    ) {
+        (Ok(counts), Ok(jobs)) => (counts, jobs),
+        _ => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch inference jobs"
+            }));
+        }
+    };
+
+    let mut by_status =
+        serde_json::json!({ "queued": 0, "leased": 0, "completed": 0, "failed": 0 });
+    for (status, count) in counts {
+        by_status[status] = count.into();
+    }
+    let jobs: Vec<_> = jobs.iter().map(job_json).collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "counts": by_status,
+        "jobs": jobs,
+    }))
+}
+
+pub async fn get_job(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    let job_id = match parse_job_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match data.get_inference_job(auth.app_id(), job_id).await {
+        Ok(Some(job)) => HttpResponse::Ok().json(job_json(&job)),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Inference job not found"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to fetch inference job"
+        })),
+    }
+}
+
+/// Leases up to `limit` (at most 100) available jobs to the calling worker, oldest first.
+pub async fn claim_jobs(
+    auth: AuthenticatedApp,
+    payload: web::Json<ClaimJobsPayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::WriteRagInference) {
+        return e.error_response();
+    }
+
+    match inference_queue::claim(&data, auth.app_id(), payload.into_inner()).await {
+        Ok(jobs) => HttpResponse::Ok().json(serde_json::json!({
+            "jobs": jobs,
+            "max_claim": MAX_CLAIM,
+        })),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Extends the lease of a job the worker is still working on.
+pub async fn heartbeat_job(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    payload: web::Json<HeartbeatPayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::WriteRagInference) {
+        return e.error_response();
+    }
+
+    let job_id = match parse_job_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let payload = payload.into_inner();
+    match inference_queue::heartbeat(
+        &data,
+        auth.app_id(),
+        job_id,
+        &payload.worker_id,
+        payload.lease_secs,
+    )
+    .await
+    {
+        Ok(job) => HttpResponse::Ok().json(job_json(&job)),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Stores the inference on the job's log and completes the job.
+pub async fn complete_job(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    payload: web::Json<CompleteJobPayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::WriteRagInference) {
+        return e.error_response();
+    }
+
+    let job_id = match parse_job_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match inference_queue::complete(&data, &auth, job_id, payload.into_inner()).await {
+        Ok(job) => HttpResponse::Ok().json(job_json(&job)),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Records a failed attempt; the job is retried until it runs out of attempts.
+pub async fn fail_job(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    payload: web::Json<FailJobPayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::WriteRagInference) {
+        return e.error_response();
+    }
+
+    let job_id = match parse_job_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match inference_queue::fail(&data, auth.app_id(), job_id, payload.into_inner()).await {
+        Ok(job) => HttpResponse::Ok().json(job_json(&job)),
+        Err(e) => e.error_response(),
+    }
+}
+
+fn parse_job_id(job_id: String) -> Result<ObjectId, HttpResponse> {
+    ObjectId::parse_str(job_id).map_err(|_| {
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid job ID format"
+        }))
+    })
+}
@@ -0,0 +1,248 @@
+use crate::db::{self, MongoRepo};
+use crate::extractors::tenant::AuthenticatedOrg;
+use crate::handlers::membership_handler::grantable_by;
+use crate::handlers::organization_handler::current_user;
+use crate::models::invitation::{
+    AcceptInvitationPayload, CreateInvitationPayload, Invitation, InvitationStatus,
+};
+use crate::models::membership::OrgRole;
+use crate::services::invitation_service;
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+
+fn invitation_json(invitation: &Invitation) -> serde_json::Value {
+    serde_json::json!({
+        "id": invitation.id.map(|id| id.to_hex()),
+        "email": invitation.email,
+        "role": invitation.role,
+        "status": invitation.status,
+        "invited_by": invitation.invited_by.map(|id| id.to_hex()),
+        "created_at": invitation.created_at.to_rfc3339(),
+        "expires_at": invitation.expires_at.to_rfc3339(),
+        "expired": invitation.is_expired(),
+    })
+}
+
+/// Invites someone by email. Requires the admin role; only owners can invite owners.
+pub async fn create_invitation(
+    auth: AuthenticatedOrg,
+    payload: web::Json<CreateInvitationPayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    let payload = payload.into_inner();
+    if let Err(e) = auth.require_role(grantable_by(payload.role)) {
+        return e.error_response();
+    }
+
+    let email = invitation_service::normalize_email(&payload.email);
+    if email.is_empty() || !email.contains('@') {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid email address"
+        }));
+    }
+
+    // Existing members are managed through the members endpoints instead
+    if let Ok(Some(user)) = data.get_user_by_email(&email).await {
+        if let Ok(Some(_)) = data.get_membership(auth.org_id(), user.id.unwrap()).await {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "User is already a member of this organization"
+            }));
+        }
+    }
+
+    let invitation = invitation_service::new_invitation(
+        auth.org_id(),
+        &email,
+        payload.role,
+        auth.access.user_id(),
+    );
+    match data.create_invitation(invitation.clone()).await {
+        Ok(_) => {}
+        Err(e) if db::is_duplicate_key(&e) => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "A pending invitation already exists for this email, resend it instead"
+            }));
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to create invitation"
+            }));
+        }
+    }
+
+    let token = invitation_service::issue_token(&invitation);
+    if let Err(e) =
+        invitation_service::send_invitation_email(&invitation, &auth.org.org_name, &token).await
+    {
+        log::error!("Failed to send invitation email to {}: {}", email, e);
+        // Don't leave behind an invitation nobody was told about
+        let _ = data
+            .revoke_invitation(auth.org_id(), invitation.id.unwrap())
+            .await;
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to send invitation email"
+        }));
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": "Invitation sent",
+        "invitation": invitation_json(&invitation),
+    }))
+}
+
+/// Lists pending invitations, including ones that have expired but were not revoked.
+pub async fn list_invitations(
+    auth: AuthenticatedOrg,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require_role(OrgRole::Admin) {
+        return e.error_response();
+    }
+
+    match data.list_pending_invitations(auth.org_id()).await {
+        Ok(invitations) => {
+            let invitations: Vec<_> = invitations.iter().map(invitation_json).collect();
+            HttpResponse::Ok().json(invitations)
+        }
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to fetch invitations"
+        })),
+    }
+}
+
+/// Sends a fresh token with a new expiry. Links from earlier emails stop working.
+pub async fn resend_invitation(
+    auth: AuthenticatedOrg,
+    path: web::Path<(String, String)>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    let mut invitation = match pending_invitation(&auth, path.into_inner().1, &data).await {
+        Ok(invitation) => invitation,
+        Err(response) => return response,
+    };
+
+    invitation.nonce = invitation_service::generate_nonce();
+    invitation.expires_at = Utc::now() + invitation_service::invitation_ttl();
+    match data
+        .refresh_invitation(
+            invitation.id.unwrap(),
+            &invitation.nonce,
+            invitation.expires_at,
+        )
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Invitation is no longer pending"
+            }));
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to update invitation"
+            }));
+        }
+    }
+
+    let token = invitation_service::issue_token(&invitation);
+    match invitation_service::send_invitation_email(&invitation, &auth.org.org_name, &token).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Invitation resent",
+            "invitation": invitation_json(&invitation),
+        })),
+        Err(e) => {
+            log::error!(
+                "Failed to resend invitation email to {}: {}",
+                invitation.email,
+                e
+            );
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to send invitation email"
+            }))
+        }
+    }
+}
+
+pub async fn revoke_invitation(
+    auth: AuthenticatedOrg,
+    path: web::Path<(String, String)>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    let invitation = match pending_invitation(&auth, path.into_inner().1, &data).await {
+        Ok(invitation) => invitation,
+        Err(response) => return response,
+    };
+
+    match data
+        .revoke_invitation(auth.org_id(), invitation.id.unwrap())
+        .await
+    {
+        Ok(true) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Invitation revoked"
+        })),
+        Ok(false) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Invitation is no longer pending"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to revoke invitation"
+        })),
+    }
+}
+
+/// Accepts an invitation for the signed-in user.
+pub async fn accept_invitation(
+    req: HttpRequest,
+    payload: web::Json<AcceptInvitationPayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    let user = match current_user(&req, &data).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match invitation_service::accept_invitation(&data, &payload.token, &user).await {
+        Ok(membership) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Invitation accepted",
+            "organization_id": membership.organization_id.to_hex(),
+            "role": membership.role,
+        })),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Loads a pending invitation of the organization and checks the caller may manage it.
+async fn pending_invitation(
+    auth: &AuthenticatedOrg,
+    invitation_id: String,
+    data: &MongoRepo,
+) -> Result<Invitation, HttpResponse> {
+    let invitation_id = ObjectId::parse_str(invitation_id).map_err(|_| {
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid invitation ID format"
+        }))
+    })?;
+
+    let invitation = match data.get_invitation(auth.org_id(), invitation_id).await {
+        Ok(Some(invitation)) => invitation,
+        Ok(None) => {
+            return Err(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Invitation not found"
+            })));
+        }
+        Err(_) => {
+            return Err(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch invitation"
+            })));
+        }
+    };
+
+    auth.require_role(grantable_by(invitation.role))
+        .map_err(|e| e.error_response())?;
+    if invitation.status != InvitationStatus::Pending {
+        return Err(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Invitation is no longer pending"
+        })));
+    }
+    Ok(invitation)
+}
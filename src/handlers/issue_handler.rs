@@ -0,0 +1,229 @@
+use crate::db::MongoRepo;
+use crate::extractors::tenant::AuthenticatedApp;
+use crate::models::api_key::ApiKeyScope;
+use crate::models::issue::{
+    AssignIssuePayload, IssueListQuery, IssueStatus, UpdateIssueStatusPayload,
+};
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use chrono::Utc;
+use mongodb::bson::{doc, oid::ObjectId};
+
+/// Most further events an issue can be ignored for.
+const MAX_IGNORE_COUNT: i64 = 1_000_000_000;
+
+/// Lists the issues of an application, most recently seen first.
+pub async fn list_issues(
+    auth: AuthenticatedApp,
+    query: web::Query<IssueListQuery>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    match data
+        .list_issues(auth.app_id(), query.status, query.limit(), query.skip())
+        .await
+    {
+        Ok(issues) => HttpResponse::Ok().json(issues),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to retrieve issues"
+        })),
+    }
+}
+
+pub async fn get_issue(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    let issue_id = match parse_issue_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match data.get_issue(auth.app_id(), issue_id).await {
+        Ok(Some(issue)) => HttpResponse::Ok().json(issue),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Issue not found"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to retrieve issue"
+        })),
+    }
+}
+
+/// Lists the logs grouped into an issue, newest first.
+pub async fn list_issue_logs(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    query: web::Query<IssueListQuery>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    let issue_id = match parse_issue_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    match data
+        .list_issue_logs(auth.app_id(), issue_id, query.limit(), query.skip())
+        .await
+    {
+        Ok(logs) => HttpResponse::Ok().json(logs),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to retrieve logs"
+        })),
+    }
+}
+
+/// Moves an issue through the workflow: resolve (optionally in the next release), ignore until
+/// a number of further events or a date, mute, or reopen.
+pub async fn update_issue_status(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    payload: web::Json<UpdateIssueStatusPayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::TriageIssues) {
+        return e.error_response();
+    }
+
+    let issue_id = match parse_issue_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let payload = payload.into_inner();
+
+    let issue = match data.get_issue(auth.app_id(), issue_id).await {
+        Ok(Some(issue)) => issue,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Issue not found"
+            }));
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to retrieve issue"
+            }));
+        }
+    };
+
+    let mut fields = doc! {};
+    match payload.status {
+        IssueStatus::Resolved => {
+            fields.insert("resolved_at", Utc::now().to_rfc3339());
+            fields.insert("is_regression", false);
+            if payload.in_next_release {
+                let Some(release) = issue.last_release else {
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": "Issue has no events with a release to resolve in the next release"
+                    }));
+                };
+                fields.insert("resolved_in_release", release);
+            }
+        }
+        IssueStatus::Ignored => {
+            if let Some(count) = payload.ignore_count {
+                let until_count = Some(count)
+                    .filter(|count| (1..=MAX_IGNORE_COUNT).contains(count))
+                    .and_then(|count| issue.event_count.checked_add(count));
+                let Some(until_count) = until_count else {
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": format!("ignore_count must be between 1 and {}", MAX_IGNORE_COUNT)
+                    }));
+                };
+                fields.insert("ignore_until_count", until_count);
+            }
+            if let Some(until) = payload.ignore_until {
+                fields.insert("ignore_until", until.to_rfc3339());
+            }
+        }
+        IssueStatus::Unresolved | IssueStatus::Muted => {}
+    }
+
+    match data
+        .update_issue_status(auth.app_id(), issue_id, payload.status, fields)
+        .await
+    {
+        Ok(Some(issue)) => HttpResponse::Ok().json(issue),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Issue not found"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update issue"
+        })),
+    }
+}
+
+/// Assigns an issue to a member of the organization, or unassigns it with `null`.
+pub async fn assign_issue(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    payload: web::Json<AssignIssuePayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::TriageIssues) {
+        return e.error_response();
+    }
+
+    let issue_id = match parse_issue_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+
+    let assignee_id = match payload.into_inner().user_id {
+        Some(user_id) => {
+            let Ok(user_id) = ObjectId::parse_str(user_id) else {
+                return HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "Invalid user ID format"
+                }));
+            };
+            match data.get_membership(auth.org_id(), user_id).await {
+                Ok(Some(_)) => Some(user_id),
+                Ok(None) => {
+                    return HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": "Assignee is not a member of this organization"
+                    }));
+                }
+                Err(_) => {
+                    return HttpResponse::InternalServerError().json(serde_json::json!({
+                        "error": "Failed to look up assignee"
+                    }));
+                }
+            }
+        }
+        None => None,
+    };
+
+    match data
+        .assign_issue(auth.app_id(), issue_id, assignee_id)
+        .await
+    {
+        Ok(true) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Issue assignee updated",
+            "assignee_id": assignee_id.map(|id| id.to_hex()),
+        })),
+        Ok(false) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Issue not found"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update issue"
+        })),
+    }
+}
+
+fn parse_issue_id(issue_id: String) -> Result<ObjectId, HttpResponse> {
+    ObjectId::parse_str(issue_id).map_err(|_| {
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid issue ID format"
+        }))
+    })
+}
@@ -1,225 +1,192 @@
 use crate::db::MongoRepo;
-use crate::models::log::LogPayload;
-use crate::services::log_service;
+use crate::extractors::tenant::AuthenticatedApp;
+use crate::models::api_key::ApiKeyScope;
+use crate::models::log::{LogListQuery, LogPayload};
+use crate::models::rag_inference::InferenceMetadata;
+use crate::models::stats::LogStatsParams;
+use crate::services::{ingest_body, log_search, log_service, log_stats, rag_inference_service};
+use crate::services::rollup_service::RollupBuffer;
 use crate::services::websocket_queue::WebSocketQueue;
 use crate::websocket::server::WebSocketServer;
-use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder, ResponseError};
 use serde_json::Value;
-use log::{error, info};
-use mongodb::bson;
+use log::info;
 
 use mongodb::bson::{doc, oid::ObjectId};
-use mongodb::options::FindOptions;
-use futures_util::stream::TryStreamExt; 
 
 
 pub async fn save_log(
+    auth: AuthenticatedApp,
     req: HttpRequest,
-    payload: web::Json<LogPayload>,
+    body: web::Payload,
     data: web::Data<MongoRepo>,
     websocket_server: web::Data<WebSocketServer>,
     websocket_queue: web::Data<WebSocketQueue>,
+    rollups: web::Data<RollupBuffer>,
 ) -> impl Responder {
-    let cd_id = match req.headers().get("CD-ID") {
-        Some(value) => match value.to_str() {
-            Ok(v) => {
-                log::debug!("Received CD-ID: {}", v);
-                v
-            }
-            Err(_) => {
-                log::error!("Invalid CD-ID header encoding");
-                return HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Invalid CD-ID header encoding"
-                }));
-            }
-        },
-        None => {
-            log::error!("Missing CD-ID header");
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Missing CD-ID header"
-            }));
-        }
-    };
-
-    let cd_secret = match req.headers().get("CD-Secret") {
-        Some(value) => match value.to_str() {
-            Ok(v) => {
-                log::debug!("Received CD-Secret: {}", v);
-                v
-            }
-            Err(_) => {
-                log::error!("Invalid CD-Secret header encoding");
-                return HttpResponse::BadRequest().json(serde_json::json!({
-                    "error": "Invalid CD-Secret header encoding"
-                }));
-            }
-        },
-        None => {
-            log::error!("Missing CD-Secret header");
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Missing CD-Secret header"
-            }));
-        }
-    };
+    if let Err(e) = auth.require(ApiKeyScope::Ingest) {
+        return e.error_response();
+    }
 
-    let application_id = match req.headers().get("Application-ID") {
-        Some(value) => match value.to_str() {
-            Ok(v) => v,
-            Err(_) => {
-                error!("Invalid Application-ID header");
-                return HttpResponse::BadRequest().body("Invalid Application-ID header");
-            }
-        },
-        None => {
-            error!("Missing Application-ID header");
-            return HttpResponse::BadRequest().body("Missing Application-ID header");
-        }
+    // The body may be compressed, so it is read and decoded before parsing
+    let body = match ingest_body::read_ingest_body(&req, body, log_service::log_max_bytes()).await {
+        Ok(body) => body,
+        Err(e) => return e.error_response(),
     };
-
-    let org = match data
-        .get_organization_by_cd_id_and_secret(cd_id, cd_secret)
-        .await
-    {
-        Ok(Some(org)) => {
-            log::info!("Successfully authenticated organization: {}", org.org_name);
-            org
-        }
-        Ok(None) => {
-            log::warn!("Authentication failed for CD-ID: {}", cd_id);
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Invalid CD-ID or CD-Secret"
-            }));
-        }
+    let mut log: LogPayload = match serde_json::from_slice(&body) {
+        Ok(log) => log,
         Err(e) => {
-            log::error!("Database error during organization lookup: {}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Internal server error during authentication"
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid log payload: {}", e)
             }));
         }
     };
 
-    let app_id = match ObjectId::parse_str(application_id) {
-        Ok(id) => id,
-        Err(_) => {
-            error!("Invalid Application-ID format");
-            return HttpResponse::BadRequest().body("Invalid Application-ID format");
-        }
-    };
-
-    let app = match data.get_application_by_id(app_id).await {
-        Ok(Some(app)) => app,
-        Ok(None) => {
-            error!("Application not found");
-            return HttpResponse::NotFound().body("Application not found");
-        }
-        Err(e) => {
-            error!("Database error: {}", e);
-            return HttpResponse::InternalServerError().body("Database error");
-        }
-    };
-
-    // Ensure the application belongs to the organization
-    if app.organization_id != Some(org.id.unwrap()) {
-        error!("Application does not belong to the organization");
-        return HttpResponse::Unauthorized()
-            .body("Application does not belong to the organization");
-    }
     // Process the log
-    let mut log = payload.into_inner();
-    log.organization_id = Some(org.id.unwrap());
-    log.application_id = Some(app.id.unwrap());
+    log.organization_id = Some(auth.org_id());
+    log.application_id = Some(auth.app_id());
 
     match log_service::process_log(
         log,
         data.clone(),
         websocket_server.clone(),
         websocket_queue.clone(),
+        rollups.clone(),
     )
     .await
     {
         Ok(_) => HttpResponse::Ok().json(serde_json::json!({"message": "Log saved"})),
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e
+        })),
     }
 }
 
 
-
-
-pub async fn get_log_by_id(
+/// Save a batch of logs sent as a JSON array or as NDJSON (one log per line).
+/// Items are validated independently and the response lists the outcome of each.
+pub async fn save_logs_batch(
+    auth: AuthenticatedApp,
     req: HttpRequest,
-    path: web::Path<String>,
+    body: web::Payload,
     data: web::Data<MongoRepo>,
+    websocket_server: web::Data<WebSocketServer>,
+    rollups: web::Data<RollupBuffer>,
 ) -> impl Responder {
-    // Extract headers for authentication
-    let cd_id = req
-        .headers()
-        .get("CD-ID")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-    let cd_secret = req
-        .headers()
-        .get("CD-Secret")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-    let app_id = req
+    if let Err(e) = auth.require(ApiKeyScope::Ingest) {
+        return e.error_response();
+    }
+
+    let body = match ingest_body::read_ingest_body(&req, body, log_service::batch_max_bytes()).await {
+        Ok(body) => body,
+        Err(e) => return e.error_response(),
+    };
+    let ndjson = req
         .headers()
-        .get("Application-ID")
+        .get(actix_web::http::header::CONTENT_TYPE)
         .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-
-    // Validate ObjectId from URL
-    let log_id = match ObjectId::parse_str(path.into_inner()) {
-        Ok(id) => id,
-        Err(_) => {
+        .is_some_and(|v| v.contains("ndjson") || v.contains("jsonl"));
+    let items = match parse_batch(&body, ndjson) {
+        Ok(items) => items,
+        Err(e) => {
             return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Invalid log ID format"
+                "error": e
             }));
         }
     };
 
-    // Authenticate organization using CD-ID and CD-Secret
-    let org = match data.get_organization_by_cd_id_and_secret(cd_id, cd_secret).await {
-        Ok(Some(org)) => org,
-        Ok(None) => {
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Invalid CD-ID or CD-Secret"
-            }));
-        }
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to authenticate organization"
-            }));
+    let max_items = log_service::batch_max_items();
+    if items.len() > max_items {
+        return HttpResponse::PayloadTooLarge().json(serde_json::json!({
+            "error": format!("Batch exceeds the limit of {} logs", max_items)
+        }));
+    }
+
+    // Store the valid logs, remembering where each one sits in the request
+    let mut results: Vec<serde_json::Value> = Vec::with_capacity(items.len());
+    let mut valid = Vec::new();
+    let mut valid_indexes = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        match item {
+            Ok(log) => {
+                valid_indexes.push(index);
+                valid.push(log);
+                results.push(serde_json::Value::Null);
+            }
+            Err(e) => results.push(serde_json::json!({ "index": index, "error": e })),
         }
-    };
+    }
 
-    // Validate Application-ID
-    let app_id = match ObjectId::parse_str(app_id) {
+    let stored = log_service::process_log_batch(
+        valid,
+        auth.org_id(),
+        auth.app_id(),
+        &data,
+        &websocket_server,
+        &rollups,
+    )
+    .await;
+    for (index, result) in valid_indexes.into_iter().zip(stored) {
+        results[index] = match result {
+            Ok(id) => serde_json::json!({ "index": index, "id": id.to_hex() }),
+            Err(e) => serde_json::json!({ "index": index, "error": e }),
+        };
+    }
+
+    let accepted = results.iter().filter(|r| r.get("id").is_some()).count();
+    HttpResponse::Ok().json(serde_json::json!({
+        "accepted": accepted,
+        "rejected": results.len() - accepted,
+        "results": results,
+    }))
+}
+
+/// Splits a batch body into logs. A body starting with `[` is a JSON array unless the
+/// content type says NDJSON; anything else is read as one JSON document per line.
+fn parse_batch(body: &[u8], ndjson: bool) -> Result<Vec<Result<LogPayload, String>>, String> {
+    let is_array = !ndjson && body.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'[');
+    if is_array {
+        let values: Vec<Value> =
+            serde_json::from_slice(body).map_err(|e| format!("Invalid JSON array: {}", e))?;
+        return Ok(values
+            .into_iter()
+            .map(|value| serde_json::from_value(value).map_err(|e| e.to_string()))
+            .collect());
+    }
+
+    let text = std::str::from_utf8(body).map_err(|_| "Body is not valid UTF-8".to_string())?;
+    Ok(text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| e.to_string()))
+        .collect())
+}
+
+
+
+
+pub async fn get_log_by_id(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    // Validate ObjectId from URL
+    let log_id = match ObjectId::parse_str(path.into_inner()) {
         Ok(id) => id,
         Err(_) => {
             return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Invalid Application-ID format"
-            }));
-        }
-    };
-
-    // Ensure application belongs to the authenticated organization
-    let app = match data.get_application_by_id(app_id).await {
-        Ok(Some(app)) if app.organization_id == Some(org.id.unwrap()) => app,
-        Ok(_) => {
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Application does not belong to the organization"
-            }));
-        }
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to validate application"
+                "error": "Invalid log ID format"
             }));
         }
     };
 
     // Fetch log from database
     let collection = data.db.collection::<LogPayload>("logs");
-    match collection.find_one(doc! { "_id": log_id, "application_id": app.id.unwrap() }, None).await
+    match collection.find_one(doc! { "_id": log_id, "application_id": auth.app_id() }, None).await
     {
         Ok(Some(log)) => HttpResponse::Ok().json(log),
         Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
@@ -232,83 +199,27 @@ pub async fn get_log_by_id(
 }
 
 
-/// Fetch all logs for a specific organization and application.
+/// Lists the logs of an application, newest first, one page at a time.
+/// Pass the `next_cursor` of a response as `cursor` to get the next page.
 pub async fn get_all_logs(
-    req: HttpRequest,
+    auth: AuthenticatedApp,
+    query: web::Query<LogListQuery>,
     data: web::Data<MongoRepo>,
 ) -> impl Responder {
-    // Extract headers
-    let cd_id = req
-        .headers()
-        .get("CD-ID")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-    let cd_secret = req
-        .headers()
-        .get("CD-Secret")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-    let app_id = req
-        .headers()
-        .get("Application-ID")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-
-    // Authenticate organization using CD-ID and CD-Secret
-    let org = match data.get_organization_by_cd_id_and_secret(cd_id, cd_secret).await {
-        Ok(Some(org)) => org,
-        Ok(None) => {
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Invalid CD-ID or CD-Secret"
-            }));
-        }
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to authenticate organization"
-            }));
-        }
-    };
-
-    // Validate Application-ID
-    let app_id = match mongodb::bson::oid::ObjectId::parse_str(app_id) {
-        Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Invalid Application-ID format"
-            }));
-        }
-    };
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
 
-    let app = match data.get_application_by_id(app_id).await {
-        Ok(Some(app)) if app.organization_id == Some(org.id.unwrap()) => app,
-        Ok(_) => {
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Application does not belong to the organization"
-            }));
-        }
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to validate application"
-            }));
-        }
+    let filter = match log_search::build_filter(auth.app_id(), &query) {
+        Ok(filter) => filter,
+        Err(e) => return e.error_response(),
     };
 
-    // Fetch logs from the database
-    let collection = data.db.collection::<LogPayload>("logs");
-    let filter = doc! { "application_id": app.id.unwrap() };
-
-    let find_options = FindOptions::builder()
-        .sort(doc! { "_id": -1 }) // Optional: Sort by descending order of insertion
-        .build();
-
-    match collection.find(filter, find_options).await {
-        Ok(mut cursor) => {
-            let mut logs = Vec::new();
-            while let Some(log) = cursor.try_next().await.unwrap_or(None) {
-                logs.push(log);
-            }
-            HttpResponse::Ok().json(logs)
-        }
+    match log_search::fetch_page(&data, filter, query.limit()).await {
+        Ok((logs, next_cursor)) => HttpResponse::Ok().json(serde_json::json!({
+            "logs": logs,
+            "next_cursor": next_cursor
+        })),
         Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
             "error": "Failed to retrieve logs"
         })),
@@ -316,29 +227,36 @@ pub async fn get_all_logs(
 }
 
 
-/// Update the `rag_inference` field of a specific log.
+/// Counts logs per group and time bucket, e.g. errors per hour or the top URLs.
+/// Takes the filters of `GET /logs` plus `interval`, `group_by` and `top`.
+pub async fn get_log_stats(
+    auth: AuthenticatedApp,
+    filters: web::Query<LogListQuery>,
+    params: web::Query<LogStatsParams>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    match log_stats::log_stats(&data, auth.app_id(), &filters, &params).await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) => e.error_response(),
+    }
+}
+
+
+/// Records a new version of the `rag_inference` of a specific log. The body is the inference;
+/// `POST /logs/{log_id}/rag-inferences` also takes the model and prompt it came from.
 pub async fn update_rag_inference(
-    req: HttpRequest,
+    auth: AuthenticatedApp,
     path: web::Path<String>,
     data: web::Data<MongoRepo>,
     payload: web::Json<Value>, // The `rag_inference` data
 ) -> impl Responder {
-    // Extract headers for authentication
-    let cd_id = req
-        .headers()
-        .get("CD-ID")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-    let cd_secret = req
-        .headers()
-        .get("CD-Secret")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-    let app_id = req
-        .headers()
-        .get("Application-ID")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
+    if let Err(e) = auth.require(ApiKeyScope::WriteRagInference) {
+        return e.error_response();
+    }
 
     // Parse `log_id` from the URL
     let log_id = match ObjectId::parse_str(path.into_inner()) {
@@ -350,69 +268,29 @@ pub async fn update_rag_inference(
         }
     };
 
-    // Authenticate the organization using `CD-ID` and `CD-Secret`
-    let org = match data.get_organization_by_cd_id_and_secret(cd_id, cd_secret).await {
-        Ok(Some(org)) => org,
-        Ok(None) => {
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Invalid CD-ID or CD-Secret"
-            }));
-        }
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to authenticate organization"
-            }));
-        }
-    };
-
-    // Validate the `Application-ID`
-    let app_id = match ObjectId::parse_str(app_id) {
-        Ok(id) => id,
-        Err(_) => {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Invalid Application-ID format"
-            }));
-        }
-    };
-
-    let app = match data.get_application_by_id(app_id).await {
-        Ok(Some(app)) if app.organization_id == Some(org.id.unwrap())=> app,
-        Ok(_) => {
-            return HttpResponse::Unauthorized().json(serde_json::json!({
-                "error": "Application does not belong to the organization"
-            }));
-        }
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Failed to validate application"
-            }));
-        }
-    };
-
-    // Update the `rag_inference` field in the log
-    let collection = data.db.collection::<LogPayload>("logs");
-    let filter = doc! {
-        "_id": log_id,
-        "application_id": app.id.unwrap(),
-    };
-    let update = doc! {
-        "$set": {
-            "rag_inference": bson::to_bson(&payload.into_inner()).unwrap_or(bson::Bson::Null),
-        }
-    };
-
-    match collection.update_one(filter, update, None).await {
-        Ok(update_result) if update_result.matched_count > 0 => {
-            info!("Updated `rag_inference` for log ID: {}", log_id);
+    let author = rag_inference_service::author(&auth.access, None);
+    match rag_inference_service::record(
+        &data,
+        &auth,
+        log_id,
+        payload.into_inner(),
+        InferenceMetadata::default(),
+        author,
+        None,
+    )
+    .await
+    {
+        Ok(version) => {
+            info!("Recorded `rag_inference` version {} for log ID: {}", version.version, log_id);
+            // Written without a lease: the log's job must not be handed to a worker again
+            if let Err(e) = data.complete_inference_jobs_for_log(auth.app_id(), log_id).await {
+                log::error!("Failed to complete inference jobs for log {}: {}", log_id, e);
+            }
             HttpResponse::Ok().json(serde_json::json!({
-                "message": "RAG inference updated successfully"
+                "message": "RAG inference updated successfully",
+                "version": version.version,
             }))
         }
-        Ok(_) => HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Log not found"
-        })),
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to update RAG inference"
-        })),
+        Err(e) => e.error_response(),
     }
-}
\ No newline at end of file
+}
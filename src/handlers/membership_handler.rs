@@ -0,0 +1,183 @@
+use crate::db::MongoRepo;
+use crate::extractors::tenant::{Access, AuthenticatedOrg};
+use crate::models::membership::{OrgRole, UpdateMemberRolePayload};
+use crate::services::tenant_cache::TenantCache;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use mongodb::bson::oid::ObjectId;
+
+/// Lists the members of an organization with their role.
+pub async fn list_members(auth: AuthenticatedOrg, data: web::Data<MongoRepo>) -> impl Responder {
+    let memberships = match data.list_memberships_for_org(auth.org_id()).await {
+        Ok(memberships) => memberships,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch members"
+            }));
+        }
+    };
+
+    let user_ids = memberships.iter().map(|m| m.user_id).collect();
+    let users = match data.get_users_by_ids(user_ids).await {
+        Ok(users) => users,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch members"
+            }));
+        }
+    };
+
+    let members: Vec<_> = memberships
+        .into_iter()
+        .filter_map(|membership| {
+            let user = users.iter().find(|u| u.id == Some(membership.user_id))?;
+            Some(serde_json::json!({
+                "user_id": membership.user_id.to_hex(),
+                "email": user.email,
+                "first_name": user.first_name,
+                "last_name": user.last_name,
+                "role": membership.role,
+                "created_at": membership.created_at.to_rfc3339(),
+            }))
+        })
+        .collect();
+
+    HttpResponse::Ok().json(members)
+}
+
+/// Changes the role of a member. Owners can only be promoted or demoted by owners,
+/// and the last owner cannot be demoted.
+pub async fn update_member_role(
+    auth: AuthenticatedOrg,
+    path: web::Path<(String, String)>,
+    payload: web::Json<UpdateMemberRolePayload>,
+    data: web::Data<MongoRepo>,
+    tenant_cache: web::Data<TenantCache>,
+) -> impl Responder {
+    let (_, user_id) = path.into_inner();
+    let user_id = match ObjectId::parse_str(user_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid user ID format"
+            }));
+        }
+    };
+    let role = payload.into_inner().role;
+
+    let current = match data.get_membership(auth.org_id(), user_id).await {
+        Ok(Some(membership)) => membership,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Member not found"
+            }));
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch member"
+            }));
+        }
+    };
+
+    if let Err(e) = auth.require_role(grantable_by(role.max(current.role))) {
+        return e.error_response();
+    }
+    if current.role == OrgRole::Owner && role != OrgRole::Owner {
+        if let Err(response) = ensure_other_owner(&data, auth.org_id()).await {
+            return response;
+        }
+    }
+
+    match data
+        .update_membership_role(auth.org_id(), user_id, role)
+        .await
+    {
+        Ok(_) => {
+            tenant_cache.invalidate_org(auth.org_id()).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Member role updated",
+                "role": role,
+            }))
+        }
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to update member role"
+        })),
+    }
+}
+
+/// Removes a member. Admins can remove non-owners, members can always leave,
+/// and the last owner cannot be removed.
+pub async fn remove_member(
+    auth: AuthenticatedOrg,
+    path: web::Path<(String, String)>,
+    data: web::Data<MongoRepo>,
+    tenant_cache: web::Data<TenantCache>,
+) -> impl Responder {
+    let (_, user_id) = path.into_inner();
+    let user_id = match ObjectId::parse_str(user_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid user ID format"
+            }));
+        }
+    };
+
+    let current = match data.get_membership(auth.org_id(), user_id).await {
+        Ok(Some(membership)) => membership,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Member not found"
+            }));
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch member"
+            }));
+        }
+    };
+
+    let leaving = matches!(auth.access, Access::Member { user_id: me, .. } if me == user_id);
+    if !leaving {
+        if let Err(e) = auth.require_role(grantable_by(current.role)) {
+            return e.error_response();
+        }
+    }
+    if current.role == OrgRole::Owner {
+        if let Err(response) = ensure_other_owner(&data, auth.org_id()).await {
+            return response;
+        }
+    }
+
+    match data.delete_membership(auth.org_id(), user_id).await {
+        Ok(_) => {
+            tenant_cache.invalidate_org(auth.org_id()).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Member removed"
+            }))
+        }
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to remove member"
+        })),
+    }
+}
+
+/// Minimum role needed to grant or take away `role`.
+pub(crate) fn grantable_by(role: OrgRole) -> OrgRole {
+    if role == OrgRole::Owner {
+        OrgRole::Owner
+    } else {
+        OrgRole::Admin
+    }
+}
+
+async fn ensure_other_owner(data: &MongoRepo, org_id: ObjectId) -> Result<(), HttpResponse> {
+    match data.count_org_owners(org_id).await {
+        Ok(count) if count > 1 => Ok(()),
+        Ok(_) => Err(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "An organization must keep at least one owner"
+        }))),
+        Err(_) => Err(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to check organization owners"
+        }))),
+    }
+}
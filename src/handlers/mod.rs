@@ -1,7 +1,14 @@
+pub mod api_key_handler;
 pub mod application_handler;
 pub mod forget_password_handler;
+pub mod inference_job_handler;
+pub mod invitation_handler;
+pub mod issue_handler;
 pub mod log_handler;
+pub mod membership_handler;
 pub mod organization_handler;
+pub mod rag_inference_handler;
+pub mod saved_search_handler;
 pub mod signin_handler;
 pub mod test_handler;
 pub mod user_handler;
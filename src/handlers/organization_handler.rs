@@ -1,62 +1,470 @@
 use crate::db::MongoRepo;
-use crate::models::organization::Organization;
-use actix_web::{web, HttpResponse, Responder,HttpRequest};
+use crate::extractors::tenant::{Access, AuthenticatedOrg};
+use crate::middlewares::auth_middleware::Claims;
+use crate::models::membership::{ClaimOrganizationPayload, Membership, OrgRole};
+use crate::models::organization::{
+    CreateOrganizationPayload, Organization, RotateSecretPayload, TransferOwnershipPayload,
+    UpdateDefaultRetentionPayload, UpdateOrganizationPayload,
+};
+use crate::models::user::User;
+use crate::services::tenant_cache::TenantCache;
+use crate::services::{
+    credential_service, invitation_service, jwt_service, organization_service, retention_service,
+};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError};
+use bcrypt::{hash, DEFAULT_COST};
+use chrono::Utc;
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::doc;
 
+/// Creates an organization with server-generated credentials. The CD-Secret is only
+/// returned here.
 pub async fn create_organization(
-    payload: web::Json<Organization>,
+    req: HttpRequest,
+    payload: web::Json<CreateOrganizationPayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    let payload = payload.into_inner();
+    let (org_name, admin_email) = match (
+        organization_service::validate_name(&payload.org_name),
+        organization_service::validate_email(&payload.admin_email),
+    ) {
+        (Ok(name), Ok(email)) => (name, email),
+        (Err(e), _) | (_, Err(e)) => return e.error_response(),
+    };
+
+    let admin_password_hash = match hash(&payload.admin_password, DEFAULT_COST) {
+        Ok(hash) => hash,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to hash admin password"
+            }));
+        }
+    };
+
+    // Only salted hashes of the credentials are persisted
+    let cd_id = credential_service::generate_cd_id();
+    let cd_secret = credential_service::generate_secret();
+    let org = Organization {
+        id: Some(ObjectId::new()),
+        org_name,
+        admin_email,
+        admin_password_hash,
+        cd_id: cd_id.clone(),
+        cd_secret_hash: credential_service::hash_secret(&cd_secret),
+        previous_secrets: Vec::new(),
+        secret_rotated_at: None,
+        default_retention_days: None,
+        deleted_at: None,
+    };
+    let org_id = org.id.unwrap();
+    let admin_email = org.admin_email.clone();
+    let org_name = org.org_name.clone();
+    if let Err(e) = data.create_organization(org).await {
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": e.to_string()
+        }));
+    }
+
+    // The signed-in creator becomes the owner. Without a session nobody is linked silently:
+    // the admin email is invited as owner and has to accept.
+    let session_email = req
+        .cookie("auth_token")
+        .and_then(|cookie| jwt_service::validate_jwt(cookie.value()).ok());
+    let session_user = match session_email {
+        Some(email) => data.get_user_by_email(&email).await.ok().flatten(),
+        None => None,
+    };
+    let mut response = serde_json::json!({
+        "message": "Organization created",
+        "organization_id": org_id.to_hex(),
+        "cd_id": cd_id,
+        "cd_secret": cd_secret,
+    });
+    match session_user.and_then(|user| user.id) {
+        Some(user_id) => {
+            let membership = Membership {
+                id: Some(ObjectId::new()),
+                organization_id: org_id,
+                user_id,
+                role: OrgRole::Owner,
+                created_at: Utc::now(),
+            };
+            if let Err(e) = data.create_membership(membership).await {
+                log::error!(
+                    "Failed to create owner membership for org {}: {}",
+                    org_id,
+                    e
+                );
+            }
+        }
+        None => {
+            response["owner_invitation"] =
+                invite_owner(&data, org_id, &org_name, &admin_email).await;
+        }
+    }
+
+    HttpResponse::Ok().json(response)
+}
+
+/// Invites the admin email of an organization created without a session to become its owner.
+/// Returns what the response says about the invitation.
+async fn invite_owner(
+    data: &MongoRepo,
+    org_id: ObjectId,
+    org_name: &str,
+    email: &str,
+) -> serde_json::Value {
+    let invitation = invitation_service::new_invitation(org_id, email, OrgRole::Owner, None);
+    if let Err(e) = data.create_invitation(invitation.clone()).await {
+        log::error!(
+            "Failed to create owner invitation for org {}: {}",
+            org_id,
+            e
+        );
+        return serde_json::json!({ "sent": false });
+    }
+    let token = invitation_service::issue_token(&invitation);
+    if let Err(e) = invitation_service::send_invitation_email(&invitation, org_name, &token).await {
+        log::error!("Failed to send owner invitation email to {}: {}", email, e);
+        // The organization can still be claimed with its credentials
+        let _ = data.revoke_invitation(org_id, invitation.id.unwrap()).await;
+        return serde_json::json!({ "sent": false });
+    }
+    serde_json::json!({
+        "sent": true,
+        "email": invitation.email,
+        "expires_at": invitation.expires_at.to_rfc3339(),
+    })
+}
+
+pub async fn get_organization_details(auth: AuthenticatedOrg) -> impl Responder {
+    let role = match auth.access {
+        Access::Member { role, .. } => Some(role),
+        _ => None,
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "id": auth.org_id().to_hex(),
+        "org_name": auth.org.org_name,
+        "role": role,
+    }))
+}
+
+/// Renames the organization and/or changes its admin email. Requires the admin role.
+pub async fn update_organization(
+    auth: AuthenticatedOrg,
+    payload: web::Json<UpdateOrganizationPayload>,
     data: web::Data<MongoRepo>,
+    tenant_cache: web::Data<TenantCache>,
 ) -> impl Responder {
-    let mut org = payload.into_inner();
-    org.id = Some(ObjectId::new());
-    match data.create_organization(org).await {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({"message": "Organization created"})),
-        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    if let Err(e) = auth.require_role(OrgRole::Admin) {
+        return e.error_response();
+    }
+
+    match organization_service::update(&data, auth.org_id(), payload.into_inner()).await {
+        Ok(()) => {
+            tenant_cache.invalidate_org(auth.org_id()).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Organization updated"
+            }))
+        }
+        Err(e) => e.error_response(),
     }
 }
 
+/// Deletes the organization along with all its applications, whose data is purged after the
+/// application grace period. Requires an owner or the current CD-Secret.
+pub async fn delete_organization(
+    auth: AuthenticatedOrg,
+    data: web::Data<MongoRepo>,
+    tenant_cache: web::Data<TenantCache>,
+) -> impl Responder {
+    if let Err(e) = auth
+        .require_secret_management()
+        .and_then(|_| auth.require_role(OrgRole::Owner))
+    {
+        return e.error_response();
+    }
+
+    match organization_service::delete(&data, auth.org_id()).await {
+        Ok(Some((applications, purge_at))) => {
+            tenant_cache.invalidate_org(auth.org_id()).await;
+            HttpResponse::Accepted().json(serde_json::json!({
+                "message": "Organization deleted",
+                "organization_id": auth.org_id().to_hex(),
+                "applications_deleted": applications,
+                "purge_at": purge_at.to_rfc3339(),
+            }))
+        }
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Organization not found"
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to delete organization"
+        })),
+    }
+}
+
+/// Makes another member the owner. A signed-in owner handing over becomes an admin; with the
+/// org credentials every other owner does.
+pub async fn transfer_ownership(
+    auth: AuthenticatedOrg,
+    payload: web::Json<TransferOwnershipPayload>,
+    data: web::Data<MongoRepo>,
+    tenant_cache: web::Data<TenantCache>,
+) -> impl Responder {
+    if let Err(e) = auth
+        .require_secret_management()
+        .and_then(|_| auth.require_role(OrgRole::Owner))
+    {
+        return e.error_response();
+    }
+
+    let user_id = match ObjectId::parse_str(payload.into_inner().user_id) {
+        Ok(id) => id,
+        Err(_) => {
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid user ID format"
+            }));
+        }
+    };
+    let previous_owner = auth.access.user_id();
+    if previous_owner == Some(user_id) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Ownership must go to another member"
+        }));
+    }
+
+    match organization_service::transfer_ownership(&data, auth.org_id(), user_id, previous_owner)
+        .await
+    {
+        Ok(()) => {
+            tenant_cache.invalidate_org(auth.org_id()).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Ownership transferred",
+                "owner_id": user_id.to_hex(),
+            }))
+        }
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Lists the organizations the signed-in user is a member of, with their role.
+pub async fn list_my_organizations(req: HttpRequest, data: web::Data<MongoRepo>) -> impl Responder {
+    let user = match current_user(&req, &data).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let memberships = match data.list_memberships_for_user(user.id.unwrap()).await {
+        Ok(memberships) => memberships,
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch organizations"
+            }));
+        }
+    };
+
+    let mut organizations = Vec::new();
+    for membership in memberships {
+        if let Ok(Some(org)) = data
+            .get_organization_by_id(membership.organization_id)
+            .await
+        {
+            organizations.push(serde_json::json!({
+                "id": membership.organization_id.to_hex(),
+                "org_name": org.org_name,
+                "cd_id": org.cd_id,
+                "role": membership.role,
+            }));
+        }
+    }
+
+    HttpResponse::Ok().json(organizations)
+}
 
-pub async fn get_organization_details(
+/// Links an organization created before memberships existed to the signed-in user as owner.
+/// Proves control with the org credentials and only works while the org has no owner.
+pub async fn claim_organization(
     req: HttpRequest,
+    payload: web::Json<ClaimOrganizationPayload>,
     data: web::Data<MongoRepo>,
 ) -> impl Responder {
-    // Extract CD-ID and CD-SECRET headers
-    let cd_id = req
-        .headers()
-        .get("CD-ID")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-    let cd_secret = req
-        .headers()
-        .get("CD-SECRET")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or_default();
-
-    // Find organization by CD-ID and CD-SECRET
-    let collection = data.db.collection::<mongodb::bson::Document>("organizations");
-    let filter = doc! { "cd_id": cd_id, "cd_secret": cd_secret };
-
-    match collection.find_one(filter, None).await {
-        Ok(Some(org)) => {
-            let id = org.get_object_id("_id").ok();
-            let org_name = org.get_str("org_name").ok();
-            if let (Some(id), Some(org_name)) = (id, org_name) {
-                HttpResponse::Ok().json(serde_json::json!({
-                    "id": id.to_hex(),
-                    "org_name": org_name,
-                }))
-            } else {
-                HttpResponse::InternalServerError().json(serde_json::json!({
-                    "error": "Incomplete organization data",
-                }))
+    let user = match current_user(&req, &data).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let payload = payload.into_inner();
+    let org = match data
+        .get_organization_by_cd_id_and_secret(&payload.cd_id, &payload.cd_secret)
+        .await
+    {
+        Ok(Some(org)) => org,
+        Ok(None) => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Invalid CD-ID or CD-Secret"
+            }));
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to authenticate organization"
+            }));
+        }
+    };
+    let org_id = org.id.unwrap();
+
+    match data.count_org_owners(org_id).await {
+        Ok(0) => {}
+        Ok(_) => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Organization already has an owner"
+            }));
+        }
+        Err(_) => {
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to check organization owners"
+            }));
+        }
+    }
+
+    let membership = Membership {
+        id: Some(ObjectId::new()),
+        organization_id: org_id,
+        user_id: user.id.unwrap(),
+        role: OrgRole::Owner,
+        created_at: Utc::now(),
+    };
+    match data.create_membership(membership).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Organization linked",
+            "organization_id": org_id.to_hex(),
+            "role": OrgRole::Owner,
+        })),
+        Err(_) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Already a member of this organization"
+        })),
+    }
+}
+
+/// Loads the user behind the claims inserted by `AuthMiddleware`.
+pub(crate) async fn current_user(
+    req: &HttpRequest,
+    data: &MongoRepo,
+) -> Result<User, HttpResponse> {
+    let email = req
+        .extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone());
+    let Some(email) = email else {
+        return Err(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Missing authentication token"
+        })));
+    };
+
+    match data.get_user_by_email(&email).await {
+        Ok(Some(user)) if user.id.is_some() => Ok(user),
+        Ok(_) => Err(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "User not found"
+        }))),
+        Err(_) => Err(HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to load user"
+        }))),
+    }
+}
+
+/// Issues a new CD-Secret. The current secret keeps working for the grace period so
+/// deployed agents can be updated before it is revoked. The new secret is only returned here.
+pub async fn rotate_secret(
+    auth: AuthenticatedOrg,
+    payload: Option<web::Json<RotateSecretPayload>>,
+    data: web::Data<MongoRepo>,
+    tenant_cache: web::Data<TenantCache>,
+) -> impl Responder {
+    // A secret that is already being rotated out must not be able to mint a new one
+    if let Err(e) = auth.require_secret_management() {
+        return e.error_response();
+    }
+
+    let grace_period = payload
+        .and_then(|p| p.into_inner().grace_period_secs)
+        .map(credential_service::grace_period)
+        .unwrap_or_else(credential_service::default_grace_period);
+
+    let new_secret = credential_service::generate_secret();
+    let new_secret_hash = credential_service::hash_secret(&new_secret);
+
+    match data
+        .rotate_organization_secret(&auth.org, new_secret_hash, grace_period)
+        .await
+    {
+        Ok(true) => {
+            tenant_cache.invalidate_org(auth.org_id()).await;
+            let mut response = serde_json::json!({
+                "message": "CD-Secret rotated",
+                "cd_id": auth.org.cd_id,
+                "cd_secret": new_secret,
+            });
+            // Without a grace window the previous secret stopped working right away
+            if grace_period > chrono::Duration::zero() {
+                response["previous_secret_expires_at"] =
+                    (chrono::Utc::now() + grace_period).to_rfc3339().into();
             }
+            HttpResponse::Ok().json(response)
         }
-        Ok(None) => HttpResponse::Unauthorized().json(serde_json::json!({
-            "error": "Invalid CD-ID or CD-SECRET",
+        Ok(false) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": "CD-Secret was rotated concurrently, retry with the latest secret"
         })),
         Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to fetch organization details",
+            "error": "Failed to rotate CD-Secret"
         })),
     }
 }
+
+/// Revokes every rotated-out CD-Secret immediately instead of waiting for the grace period.
+pub async fn revoke_previous_secrets(
+    auth: AuthenticatedOrg,
+    data: web::Data<MongoRepo>,
+    tenant_cache: web::Data<TenantCache>,
+) -> impl Responder {
+    if let Err(e) = auth.require_secret_management() {
+        return e.error_response();
+    }
+
+    match data.revoke_previous_secrets(auth.org_id()).await {
+        Ok(revoked) => {
+            tenant_cache.invalidate_org(auth.org_id()).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Previous CD-Secrets revoked",
+                "revoked": revoked,
+            }))
+        }
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to revoke previous CD-Secrets"
+        })),
+    }
+}
+
+/// Sets the retention of the organization's applications that have none of their own;
+/// `null` falls back to the server default.
+pub async fn update_default_retention(
+    auth: AuthenticatedOrg,
+    payload: web::Json<UpdateDefaultRetentionPayload>,
+    data: web::Data<MongoRepo>,
+    tenant_cache: web::Data<TenantCache>,
+) -> impl Responder {
+    if let Err(e) = auth.require_role(OrgRole::Admin) {
+        return e.error_response();
+    }
+
+    let days = payload.into_inner().default_retention_days;
+    match retention_service::set_org_default_retention(&data, auth.org_id(), days).await {
+        Ok(()) => {
+            tenant_cache.invalidate_org(auth.org_id()).await;
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Default retention updated",
+                "default_retention_days": days,
+            }))
+        }
+        Err(e) => e.error_response(),
+    }
+}
@@ -0,0 +1,230 @@
+use crate::db::MongoRepo;
+use crate::extractors::tenant::AuthenticatedApp;
+use crate::models::api_key::ApiKeyScope;
+use crate::models::log_signature::SimilarLogsQuery;
+use crate::models::rag_inference::{
+    CreateRagInferencePayload, FeedbackExportQuery, InferenceFeedbackPayload,
+};
+use crate::services::inference_feedback_service::{self, feedback_json};
+use crate::services::rag_inference_service::{self, version_json};
+use crate::services::similarity_service;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use mongodb::bson::oid::ObjectId;
+
+/// Lists every inference version of a log, newest first, marking the one the log shows.
+pub async fn list_rag_inferences(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    let log_id = match parse_log_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match rag_inference_service::history(&data, auth.app_id(), log_id).await {
+        Ok(history) => HttpResponse::Ok().json(history),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Records a new inference version of a log with the model and prompt it came from.
+pub async fn create_rag_inference(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    payload: web::Json<CreateRagInferencePayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::WriteRagInference) {
+        return e.error_response();
+    }
+
+    let log_id = match parse_log_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let payload = payload.into_inner();
+    let author = rag_inference_service::author(&auth.access, None);
+    match rag_inference_service::record(
+        &data,
+        &auth,
+        log_id,
+        payload.inference,
+        payload.metadata,
+        author,
+        None,
+    )
+    .await
+    {
+        Ok(version) => {
+            if let Err(e) = data
+                .complete_inference_jobs_for_log(auth.app_id(), log_id)
+                .await
+            {
+                log::error!(
+                    "Failed to complete inference jobs for log {}: {}",
+                    log_id,
+                    e
+                );
+            }
+            HttpResponse::Created().json(version_json(&version, None))
+        }
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Makes the log show this version, even when later versions are recorded.
+pub async fn accept_rag_inference(
+    auth: AuthenticatedApp,
+    path: web::Path<(String, i32)>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::WriteRagInference) {
+        return e.error_response();
+    }
+
+    let (log_id, version) = path.into_inner();
+    let log_id = match parse_log_id(log_id) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match rag_inference_service::accept(&data, auth.app_id(), log_id, version).await {
+        Ok(version) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "RAG inference accepted",
+            "version": version_json(&version, Some(version.version)),
+        })),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Withdraws the accepted version; the log shows the latest one again.
+pub async fn unaccept_rag_inference(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::WriteRagInference) {
+        return e.error_response();
+    }
+
+    let log_id = match parse_log_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match rag_inference_service::unaccept(&data, auth.app_id(), log_id).await {
+        Ok(version) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Accepted RAG inference withdrawn",
+            "version": version_json(&version, Some(version.version)),
+        })),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Saves the signed-in member's rating, correction and "fixed" mark for an inference version.
+pub async fn save_feedback(
+    auth: AuthenticatedApp,
+    path: web::Path<(String, i32)>,
+    payload: web::Json<InferenceFeedbackPayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    let (log_id, version) = path.into_inner();
+    let log_id = match parse_log_id(log_id) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match inference_feedback_service::save(&data, &auth, log_id, version, payload.into_inner())
+        .await
+    {
+        Ok(feedback) => HttpResponse::Ok().json(feedback_json(&feedback)),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Lists every member's feedback on an inference version.
+pub async fn list_feedback(
+    auth: AuthenticatedApp,
+    path: web::Path<(String, i32)>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    let (log_id, version) = path.into_inner();
+    let log_id = match parse_log_id(log_id) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match inference_feedback_service::list(&data, auth.app_id(), log_id, Some(version)).await {
+        Ok(feedback) => HttpResponse::Ok().json(serde_json::json!({ "feedback": feedback })),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Pages through the application's feedback with the rated inferences and their logs, for
+/// evaluating and fine-tuning the inference model.
+pub async fn export_feedback(
+    auth: AuthenticatedApp,
+    query: web::Query<FeedbackExportQuery>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    match inference_feedback_service::export(&data, auth.app_id(), &query).await {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Earlier logs of the application most like this one, with the inferences they were resolved
+/// with, as retrieval context for inference workers.
+pub async fn similar_logs(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    query: web::Query<SimilarLogsQuery>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    let log_id = match parse_log_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match similarity_service::find_similar(&data, auth.app_id(), log_id, &query).await {
+        Ok(similar) => {
+            let similar: Vec<_> = similar
+                .into_iter()
+                .map(|(similarity, log)| {
+                    serde_json::json!({
+                        "similarity": similarity,
+                        "log": log,
+                    })
+                })
+                .collect();
+            HttpResponse::Ok().json(serde_json::json!({
+                "log_id": log_id.to_hex(),
+                "similar": similar,
+            }))
+        }
+        Err(e) => e.error_response(),
+    }
+}
+
+fn parse_log_id(log_id: String) -> Result<ObjectId, HttpResponse> {
+    ObjectId::parse_str(log_id).map_err(|_| {
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid log ID format"
+        }))
+    })
+}
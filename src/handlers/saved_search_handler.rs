@@ -0,0 +1,220 @@
+use crate::db::MongoRepo;
+use crate::extractors::tenant::AuthenticatedApp;
+use crate::models::api_key::ApiKeyScope;
+use crate::models::log::LogListQuery;
+use crate::models::saved_search::{
+    CreateSavedSearchPayload, SavedSearch, UpdateSavedSearchPayload,
+};
+use crate::services::log_search;
+use crate::services::saved_search_service;
+use actix_web::{web, HttpResponse, Responder, ResponseError};
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+
+fn saved_search_json(search: &SavedSearch, user_id: ObjectId) -> serde_json::Value {
+    serde_json::json!({
+        "id": search.id.map(|id| id.to_hex()),
+        "application_id": search.application_id.to_hex(),
+        "owner_id": search.owner_id.to_hex(),
+        "name": search.name,
+        "filters": search.filters,
+        "shared": search.shared,
+        "is_default": search.is_default_for(user_id),
+        "created_at": search.created_at.to_rfc3339(),
+        "updated_at": search.updated_at.to_rfc3339(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RunSavedSearchQuery {
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Lists the user's own searches and those shared with the organization.
+pub async fn list_saved_searches(
+    auth: AuthenticatedApp,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    match saved_search_service::list(&data, &auth).await {
+        Ok(searches) => {
+            let user_id = auth.access.user_id().unwrap();
+            let searches: Vec<_> = searches
+                .iter()
+                .map(|search| saved_search_json(search, user_id))
+                .collect();
+            HttpResponse::Ok().json(searches)
+        }
+        Err(e) => e.error_response(),
+    }
+}
+
+pub async fn create_saved_search(
+    auth: AuthenticatedApp,
+    payload: web::Json<CreateSavedSearchPayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    match saved_search_service::create(&data, &auth, payload.into_inner()).await {
+        Ok(search) => HttpResponse::Created().json(saved_search_json(&search, search.owner_id)),
+        Err(e) => e.error_response(),
+    }
+}
+
+pub async fn get_saved_search(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    let search_id = match parse_search_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match saved_search_service::get(&data, &auth, search_id).await {
+        Ok(search) => {
+            HttpResponse::Ok().json(saved_search_json(&search, auth.access.user_id().unwrap()))
+        }
+        Err(e) => e.error_response(),
+    }
+}
+
+pub async fn update_saved_search(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    payload: web::Json<UpdateSavedSearchPayload>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    let search_id = match parse_search_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match saved_search_service::update(&data, &auth, search_id, payload.into_inner()).await {
+        Ok(search) => HttpResponse::Ok().json(saved_search_json(&search, search.owner_id)),
+        Err(e) => e.error_response(),
+    }
+}
+
+pub async fn delete_saved_search(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    let search_id = match parse_search_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match saved_search_service::delete(&data, &auth, search_id).await {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Saved search deleted"
+        })),
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Pins the search as the user's default view of the application (`PUT`) or unpins it
+/// (`DELETE`). Pinning replaces the previous default.
+pub async fn pin_saved_search(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    set_default(auth, path, data, true).await
+}
+
+pub async fn unpin_saved_search(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    set_default(auth, path, data, false).await
+}
+
+async fn set_default(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    data: web::Data<MongoRepo>,
+    pinned: bool,
+) -> HttpResponse {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    let search_id = match parse_search_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    match saved_search_service::set_default(&data, &auth, search_id, pinned).await {
+        Ok(search) => {
+            HttpResponse::Ok().json(saved_search_json(&search, auth.access.user_id().unwrap()))
+        }
+        Err(e) => e.error_response(),
+    }
+}
+
+/// Runs a saved search like `GET /logs` with its filters.
+pub async fn list_saved_search_logs(
+    auth: AuthenticatedApp,
+    path: web::Path<String>,
+    query: web::Query<RunSavedSearchQuery>,
+    data: web::Data<MongoRepo>,
+) -> impl Responder {
+    if let Err(e) = auth.require(ApiKeyScope::ReadLogs) {
+        return e.error_response();
+    }
+
+    let search_id = match parse_search_id(path.into_inner()) {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let search = match saved_search_service::get(&data, &auth, search_id).await {
+        Ok(search) => search,
+        Err(e) => return e.error_response(),
+    };
+
+    let query = query.into_inner();
+    let filters = LogListQuery {
+        cursor: query.cursor,
+        limit: query.limit.or(search.filters.limit),
+        ..search.filters
+    };
+    let filter = match log_search::build_filter(auth.app_id(), &filters) {
+        Ok(filter) => filter,
+        Err(e) => return e.error_response(),
+    };
+    match log_search::fetch_page(&data, filter, filters.limit()).await {
+        Ok((logs, next_cursor)) => HttpResponse::Ok().json(serde_json::json!({
+            "logs": logs,
+            "next_cursor": next_cursor
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to retrieve logs"
+        })),
+    }
+}
+
+fn parse_search_id(search_id: String) -> Result<ObjectId, HttpResponse> {
+    ObjectId::parse_str(search_id).map_err(|_| {
+        HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid saved search ID format"
+        }))
+    })
+}
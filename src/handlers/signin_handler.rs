@@ -1,5 +1,8 @@
 use crate::{db::MongoRepo, models::user::User, services::jwt_service};
-use actix_web::{cookie::Cookie, web, HttpResponse, Responder};
+use actix_web::{
+    cookie::{Cookie, SameSite},
+    web, HttpResponse, Responder,
+};
 use bcrypt::verify;
 use mongodb::bson::doc;
 use serde::Serialize;
@@ -58,6 +61,8 @@ pub async fn signin(payload: web::Json<SigninRequest>, db: web::Data<MongoRepo>)
                     Cookie::build("auth_token", jwt) // Store JWT in HttpOnly Cookie
                         .http_only(true)
                         .secure(true)
+                        // Not sent with cross-site requests, so other sites can't act as the user
+                        .same_site(SameSite::Lax)
                         .finish(),
                 )
                 .json(SigninResponse {
@@ -2,9 +2,9 @@ use crate::services::email_service::EmailService;
 use crate::{
     db::MongoRepo,
     models::user::User,
-    services::{jwt_service, otp_service},
+    services::{invitation_service, jwt_service, otp_service},
 };
-use actix_web::cookie::{time, Cookie};
+use actix_web::cookie::{time, Cookie, SameSite};
 use actix_web::{web, HttpRequest, HttpResponse, Responder};
 use bcrypt::{hash, DEFAULT_COST};
 use mongodb::bson::doc;
@@ -19,6 +19,7 @@ pub struct SignupPayload {
     pub middle_name: Option<String>,
     pub last_name: String,
     pub password: String,
+    pub invitation_token: Option<String>, // Accept an organization invitation on signup
 }
 
 #[derive(Debug, Deserialize)]
@@ -100,7 +101,27 @@ pub async fn verify_and_delete_otp_and_signup(
     };
 
     let collection = db.db.collection::<User>("users");
-    let _ = collection.insert_one(user, None).await;
+    let inserted = collection.insert_one(&user, None).await;
+
+    let mut invitation = None;
+    if let Some(user_id) = inserted.ok().and_then(|r| r.inserted_id.as_object_id()) {
+        // The account exists either way; a bad invitation is reported alongside the signup
+        if let Some(token) = &payload.invitation_token {
+            let user = User {
+                id: Some(user_id),
+                ..user
+            };
+            invitation = Some(
+                match invitation_service::accept_invitation(&db, token, &user).await {
+                    Ok(membership) => serde_json::json!({
+                        "organization_id": membership.organization_id.to_hex(),
+                        "role": membership.role,
+                    }),
+                    Err(e) => serde_json::json!({ "error": e.to_string() }),
+                },
+            );
+        }
+    }
 
     let jwt = jwt_service::generate_jwt(&payload.email);
 
@@ -109,9 +130,10 @@ pub async fn verify_and_delete_otp_and_signup(
             Cookie::build("auth_token", jwt)
                 .http_only(true)
                 .secure(true)
+                .same_site(SameSite::Lax)
                 .finish(),
         )
-        .json(serde_json::json!({ "message": "Signup successful" }))
+        .json(serde_json::json!({ "message": "Signup successful", "invitation": invitation }))
 }
 
 pub async fn logout() -> impl Responder {
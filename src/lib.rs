@@ -1,4 +1,5 @@
 pub mod db;
+pub mod extractors;
 pub mod graphql;
 pub mod handlers;
 pub mod logger;
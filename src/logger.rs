@@ -1,5 +1,3 @@
-use env_logger;
-
 pub fn init() {
     env_logger::init();
 }
@@ -1,20 +1,16 @@
 use actix_cors::Cors;
 use actix_web::{middleware, web, App, HttpRequest, HttpServer};
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use cadmium_cloud::{db, logger, routes};
 use dotenv::dotenv; // Import Cors middleware
-mod db;
-mod graphql;
-mod handlers;
-mod logger;
-mod middlewares;
-mod models;
-mod routes;
-mod services;
-mod websocket; // Make sure this module is declared
-
-use crate::graphql::schema::{create_schema, AppSchema};
-use crate::services::websocket_queue::WebSocketQueue;
-use crate::websocket::server::WebSocketServer;
+
+use cadmium_cloud::extractors::tenant::TenantCredentials;
+use cadmium_cloud::graphql::schema::{create_schema, AppSchema};
+use cadmium_cloud::services::rollup_service::{self, RollupBuffer};
+use cadmium_cloud::services::tenant_cache::TenantCache;
+use cadmium_cloud::services::websocket_queue::WebSocketQueue;
+use cadmium_cloud::services::{app_deletion_service, archive_service, retention_service};
+use cadmium_cloud::websocket::server::WebSocketServer;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -25,6 +21,29 @@ async fn main() -> std::io::Result<()> {
     // Ensure OTP TTL index is created
     // Call `setup_otp_ttl_index` as an associated function
     db::MongoRepo::setup_otp_ttl_index(&mongo_repo.db).await;
+    // Hash any organization credentials still stored in plaintext
+    db::MongoRepo::migrate_plaintext_org_credentials(&mongo_repo.db).await;
+
+    // `cadmium-cloud rebuild-rollups [application_id]` recomputes the log rollups and exits
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("rebuild-rollups") {
+        return rebuild_rollups(&mongo_repo, args.get(2)).await;
+    }
+    // `cadmium-cloud restore-archive <application_id> <from> [to]` re-imports archived logs
+    if args.get(1).map(String::as_str) == Some("restore-archive") {
+        return restore_archive(&mongo_repo, &args[2..]).await;
+    }
+
+    // Log counters are buffered in memory and flushed to the rollup collections
+    let rollup_buffer = RollupBuffer::new();
+    rollup_buffer.spawn_flusher(mongo_repo.clone()).await;
+    let rollup_buffer_data = web::Data::new(rollup_buffer.clone());
+    let flush_repo = mongo_repo.clone();
+
+    // Delete logs older than the retention of their application
+    retention_service::spawn_purge_job(mongo_repo.clone());
+    // Purge the data of applications deleted more than the grace period ago
+    app_deletion_service::spawn_purge_job(mongo_repo.clone());
 
     // Initialize the WebSocket server and queue
     let websocket_server = WebSocketServer::new();
@@ -33,24 +52,27 @@ async fn main() -> std::io::Result<()> {
     let websocket_server_data = web::Data::new(websocket_server.clone());
     let websocket_queue_data = web::Data::new(websocket_queue.clone());
 
+    // Shared cache of authenticated tenant lookups
+    let tenant_cache = TenantCache::new();
+    let tenant_cache_data = web::Data::new(tenant_cache.clone());
+
     // Create GraphQL schema
-    let schema = create_schema(mongo_repo.clone());
+    let schema = create_schema(mongo_repo.clone(), tenant_cache);
     let schema_data = web::Data::new(schema);
 
+    // Browsers send the session cookie along, so only trusted origins may read responses
+    let cors_origins = cors_allowed_origins();
+
     let server = HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(mongo_repo.clone()))
             .app_data(schema_data.clone())
             .app_data(websocket_server_data.clone())
             .app_data(websocket_queue_data.clone())
+            .app_data(tenant_cache_data.clone())
+            .app_data(rollup_buffer_data.clone())
             .wrap(middleware::Logger::default())
-            .wrap(
-                Cors::default() // Configure CORS to allow all origins
-                    .allow_any_origin() // Allow any origin (bypass CORS)
-                    .allow_any_method() // Allow any HTTP method
-                    .allow_any_header() // Allow any header
-                    .supports_credentials(), // Explicitly allow credentials
-            )
+            .wrap(cors(&cors_origins))
             .configure(routes::init)
             .route("/graphql", web::post().to(graphql_handler))
     })
@@ -58,44 +80,111 @@ async fn main() -> std::io::Result<()> {
 
     let server_result = server.run().await;
 
+    // Write the counters of the last logs before exiting; `SIGINT` and `SIGTERM` stop the
+    // server gracefully and end up here too
+    if let Err(e) = rollup_buffer.flush(&flush_repo).await {
+        log::error!("Failed to flush log rollups on shutdown: {}", e);
+    }
+
     // Optionally handle the queue processor task if needed
     // queue_processor.await.expect("Queue processor task failed");
 
     server_result
 }
 
+/// Origins allowed to make credentialed requests: `CORS_ALLOWED_ORIGINS` (comma-separated),
+/// or the dashboard's.
+fn cors_allowed_origins() -> Vec<String> {
+    let origins = std::env::var("CORS_ALLOWED_ORIGINS")
+        .or_else(|_| std::env::var("DASHBOARD_URL"))
+        .unwrap_or_else(|_| "http://localhost:3000".to_string());
+    origins
+        .split(',')
+        .map(|origin| origin.trim().trim_end_matches('/').to_string())
+        // A wildcard can't be combined with credentials
+        .filter(|origin| !origin.is_empty() && origin != "*")
+        .collect()
+}
+
+fn cors(origins: &[String]) -> Cors {
+    origins
+        .iter()
+        .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+        .allow_any_method()
+        .allow_any_header()
+        .supports_credentials()
+}
+
+async fn rebuild_rollups(
+    mongo_repo: &db::MongoRepo,
+    app_id: Option<&String>,
+) -> std::io::Result<()> {
+    let app_id = app_id
+        .map(mongodb::bson::oid::ObjectId::parse_str)
+        .transpose()
+        .map_err(|_| std::io::Error::other("Invalid application ID format"))?;
+
+    let summary = rollup_service::rebuild(mongo_repo, app_id)
+        .await
+        .map_err(std::io::Error::other)?;
+    println!(
+        "Rebuilt log rollups from {} logs received before {}",
+        summary.logs,
+        summary.until.to_rfc3339()
+    );
+    if !summary.complete {
+        println!(
+            "Live counting started after {}; run the rebuild again in an hour to complete the rollups",
+            summary.until.to_rfc3339()
+        );
+    }
+    Ok(())
+}
+
+async fn restore_archive(mongo_repo: &db::MongoRepo, args: &[String]) -> std::io::Result<()> {
+    let usage = || std::io::Error::other("Usage: restore-archive <application_id> <from> [to]");
+    let app_id = args
+        .first()
+        .and_then(|id| mongodb::bson::oid::ObjectId::parse_str(id).ok())
+        .ok_or_else(usage)?;
+    let day = |arg: Option<&String>| {
+        arg.and_then(|day| chrono::NaiveDate::parse_from_str(day, "%Y-%m-%d").ok())
+    };
+    let from = day(args.get(1)).ok_or_else(usage)?;
+    let to = match args.get(2) {
+        Some(_) => day(args.get(2)).ok_or_else(usage)?,
+        None => from,
+    };
+
+    let config = archive_service::config()
+        .map_err(std::io::Error::other)?
+        .ok_or_else(|| std::io::Error::other("LOG_ARCHIVE is not set"))?;
+    let summary = archive_service::restore(mongo_repo, &config, app_id, from, to)
+        .await
+        .map_err(std::io::Error::other)?;
+    println!(
+        "Restored {} logs from {} archive files ({} to {}); they are kept for {} days",
+        summary.logs, summary.files, from, to, config.restore_days
+    );
+    Ok(())
+}
+
 async fn graphql_handler(
     schema: web::Data<AppSchema>,
     req: GraphQLRequest,
     http_req: HttpRequest,
 ) -> GraphQLResponse {
-    let mut headers_map = std::collections::HashMap::new();
-
-    // Extract headers
-    if let Some(cd_id) = http_req
-        .headers()
-        .get("CD-ID")
-        .and_then(|v| v.to_str().ok())
-    {
-        headers_map.insert("CD-ID".to_string(), cd_id.to_string());
-    }
-    if let Some(cd_secret) = http_req
-        .headers()
-        .get("CD-Secret")
-        .and_then(|v| v.to_str().ok())
-    {
-        headers_map.insert("CD-Secret".to_string(), cd_secret.to_string());
-    }
-    if let Some(app_id) = http_req
-        .headers()
-        .get("Application-ID")
-        .and_then(|v| v.to_str().ok())
-    {
-        headers_map.insert("Application-ID".to_string(), app_id.to_string());
-    }
+    // Tenant credentials are resolved lazily by the resolvers that need them
+    let credentials = match TenantCredentials::from_request(&http_req) {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            let error = async_graphql::ServerError::new(e.to_string(), None);
+            return async_graphql::Response::from_errors(vec![error]).into();
+        }
+    };
 
     let mut request = req.into_inner();
-    request = request.data(headers_map);
+    request = request.data(credentials);
 
     schema.execute(request).await.into()
 }
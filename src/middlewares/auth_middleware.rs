@@ -3,20 +3,18 @@ use actix_web::{
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
     Error, HttpMessage, HttpResponse,
 };
+use crate::services::jwt_service;
 use futures_util::future::{ok, LocalBoxFuture, Ready};
-use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use std::{
-    env,
-    task::{Context, Poll},
-};
+use std::task::{Context, Poll};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     // 🔹 Add `pub`
     pub sub: String, // User ID or email
     pub exp: usize,  // Expiration timestamp
+    pub aud: String, // `jwt_service::SESSION_AUDIENCE`
 }
 
 pub struct AuthMiddleware;
@@ -60,17 +58,9 @@ where
         Box::pin(async move {
             if let Some(cookie) = req.cookie("auth_token") {
                 let token = cookie.value();
-                let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-                let validation = Validation::default();
-
-                match decode::<Claims>(
-                    token,
-                    &DecodingKey::from_secret(secret.as_ref()),
-                    &validation,
-                ) {
-                    Ok(token_data) => {
-                        let claims = token_data.claims;
 
+                match jwt_service::decode_claims(token) {
+                    Ok(claims) => {
                         // ✅ Pass user details to next handler
                         req.extensions_mut().insert(claims);
 
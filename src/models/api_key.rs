@@ -0,0 +1,73 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Permissions an API key can carry. Each key is limited to a single application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyScope {
+    Ingest,            // POST /logs
+    ReadLogs,          // GET /logs, GET /logs/{id}, GraphQL queries
+    WriteRagInference, // PUT /logs/{id}/rag-inference
+    ManageApps,        // API key management for the application
+    TriageIssues,      // PUT /issues/{id}/status, PUT /issues/{id}/assignee
+}
+
+impl ApiKeyScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyScope::Ingest => "ingest",
+            ApiKeyScope::ReadLogs => "read_logs",
+            ApiKeyScope::WriteRagInference => "write_rag_inference",
+            ApiKeyScope::ManageApps => "manage_apps",
+            ApiKeyScope::TriageIssues => "triage_issues",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub organization_id: ObjectId,
+    pub application_id: ObjectId,
+    pub name: String,
+    pub key_prefix: String, // Public part of the key, used for lookup
+    pub key_hash: String,   // Salted hash of the full key
+    pub scopes: Vec<ApiKeyScope>,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyPayload {
+    pub name: String,
+    pub scopes: Vec<ApiKeyScope>,
+}
+
+/// API key as returned by the list endpoint (never includes the hash).
+#[derive(Debug, Serialize)]
+pub struct ApiKeyResponse {
+    pub id: Option<String>,
+    pub application_id: String,
+    pub name: String,
+    pub key_prefix: String,
+    pub scopes: Vec<ApiKeyScope>,
+    pub created_at: String,
+    pub revoked_at: Option<String>,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(key: ApiKey) -> Self {
+        Self {
+            id: key.id.map(|id| id.to_string()),
+            application_id: key.application_id.to_string(),
+            name: key.name,
+            key_prefix: key.key_prefix,
+            scopes: key.scopes,
+            created_at: key.created_at.to_rfc3339(),
+            revoked_at: key.revoked_at.map(|dt| dt.to_rfc3339()),
+        }
+    }
+}
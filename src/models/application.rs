@@ -1,13 +1,48 @@
+use chrono::{DateTime, Utc};
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Application {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     #[serde(skip_serializing_if = "Option::is_none")] // Allows omission during deserialization
     pub organization_id: Option<ObjectId>,
     pub application_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention_days: Option<i64>, // Falls back to the organization's default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deletion: Option<AppDeletion>, // Set once the application is deleted
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletionStatus {
+    Pending, // Inside the grace period, can be undone
+    Purging,
+    Purged,
+}
+
+/// A deleted application: hidden right away, its data purged once `purge_at` passes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppDeletion {
+    pub status: DeletionStatus,
+    pub requested_at: DateTime<Utc>,
+    pub purge_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub purged: BTreeMap<String, i64>, // Documents deleted so far, per collection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_until: Option<DateTime<Utc>>, // Held by the server purging the application
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateRetentionPayload {
+    pub retention_days: Option<i64>, // `null` inherits the organization's default
 }
 
 #[derive(Deserialize)]
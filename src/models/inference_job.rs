@@ -0,0 +1,88 @@
+use crate::models::rag_inference::InferenceMetadata;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,    // Waiting for a worker, from `available_at` on
+    Leased,    // Held by a worker until `lease_until`; re-leased to another one after that
+    Completed, // The inference was written to the log
+    Failed,    // Gave up after `max_attempts`
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Leased => "leased",
+            JobStatus::Completed => "completed",
+            JobStatus::Failed => "failed",
+        }
+    }
+}
+
+/// RAG inference to run on one log. Created when the log is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InferenceJob {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub organization_id: ObjectId,
+    pub application_id: ObjectId,
+    pub log_id: ObjectId,
+    pub status: JobStatus,
+    pub attempts: i32, // Leases handed out so far
+    pub max_attempts: i32,
+    pub available_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_id: Option<String>, // Worker holding or last holding the lease
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_until: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClaimJobsPayload {
+    pub worker_id: String,
+    pub limit: Option<i64>,      // Jobs to lease at once, 1 by default
+    pub lease_secs: Option<i64>, // Falls back to INFERENCE_LEASE_SECS
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeartbeatPayload {
+    pub worker_id: String,
+    pub lease_secs: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompleteJobPayload {
+    pub worker_id: String,
+    pub rag_inference: serde_json::Value, // Recorded as a new version of the log's inference
+    #[serde(flatten)]
+    pub metadata: InferenceMetadata,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FailJobPayload {
+    pub worker_id: String,
+    pub error: String,
+    #[serde(default = "default_retry")]
+    pub retry: bool, // `false` fails the job without using its remaining attempts
+    pub retry_after_secs: Option<i64>,
+}
+
+fn default_retry() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListJobsQuery {
+    pub status: Option<JobStatus>,
+    pub limit: Option<i64>,
+}
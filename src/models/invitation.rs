@@ -0,0 +1,57 @@
+use crate::models::membership::OrgRole;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvitationStatus {
+    Pending,
+    Accepted,
+    Revoked,
+}
+
+impl InvitationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvitationStatus::Pending => "pending",
+            InvitationStatus::Accepted => "accepted",
+            InvitationStatus::Revoked => "revoked",
+        }
+    }
+}
+
+/// An invitation for `email` to join an organization with `role`. The invitee receives a signed
+/// token carrying `nonce`; resending rotates the nonce so older links stop working.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invitation {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub organization_id: ObjectId,
+    pub email: String,
+    pub role: OrgRole,
+    pub status: InvitationStatus,
+    pub nonce: String,
+    pub invited_by: Option<ObjectId>, // None when invited with the org credentials
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_by: Option<ObjectId>,
+    pub accepted_at: Option<DateTime<Utc>>,
+}
+
+impl Invitation {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInvitationPayload {
+    pub email: String,
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcceptInvitationPayload {
+    pub token: String,
+}
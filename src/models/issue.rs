@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueStatus {
+    #[default]
+    Unresolved,
+    Resolved, // Reopens as a regression on the next event
+    Ignored,  // Reopens once `ignore_until_count` or `ignore_until` is reached
+    Muted,    // Never reopens and sends no notifications
+}
+
+impl IssueStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueStatus::Unresolved => "unresolved",
+            IssueStatus::Resolved => "resolved",
+            IssueStatus::Ignored => "ignored",
+            IssueStatus::Muted => "muted",
+        }
+    }
+}
+
+/// Logs that share a fingerprint, grouped into one issue per application.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Issue {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub organization_id: ObjectId,
+    pub application_id: ObjectId,
+    pub fingerprint: String,
+    pub title: String,   // First line of the error of the first event
+    pub culprit: String, // Route template of the first event
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub event_count: i64,
+    pub sample: IssueSample,
+    #[serde(default)]
+    pub status: IssueStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_release: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_at: Option<DateTime<Utc>>,
+    // Resolved "in next release": events from this release still count as the old problem
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_in_release: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignore_until_count: Option<i64>, // Absolute `event_count` that ends the ignore
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignore_until: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assignee_id: Option<ObjectId>,
+    #[serde(default)]
+    pub is_regression: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regressed_at: Option<DateTime<Utc>>,
+}
+
+/// The first event of an issue, kept so the issue can be shown without loading its logs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssueSample {
+    pub log_id: ObjectId,
+    pub error: String,
+    pub traceback: String,
+    pub url: String,
+    pub method: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IssueListQuery {
+    pub limit: Option<i64>,
+    pub skip: Option<u64>,
+    pub status: Option<IssueStatus>,
+}
+
+impl IssueListQuery {
+    /// Page size, 50 by default and at most 200.
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(50).clamp(1, 200)
+    }
+
+    pub fn skip(&self) -> u64 {
+        self.skip.unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateIssueStatusPayload {
+    pub status: IssueStatus,
+    /// With `resolved`: keep accepting events from the latest seen release.
+    #[serde(default)]
+    pub in_next_release: bool,
+    /// With `ignored`: reopen after this many more events.
+    pub ignore_count: Option<i64>,
+    /// With `ignored`: reopen on the first event after this time.
+    pub ignore_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssignIssuePayload {
+    pub user_id: Option<String>, // `null` unassigns
+}
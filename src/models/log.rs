@@ -1,7 +1,102 @@
 use async_graphql::*;
+use crate::models::rag_inference::RagInferenceGql;
 use chrono::{DateTime, Utc};
 use mongodb::bson::oid::ObjectId;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// One frame of a parsed traceback. Frames are ordered innermost (where the error was raised)
+/// first, whatever order the runtime printed them in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+pub struct StackFrame {
+    pub file: Option<String>,
+    pub function: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub in_app: bool, // False for standard library and third-party frames
+}
+
+/// Severity of a log, ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Debug,
+    Info,
+    #[serde(alias = "warn")]
+    Warning,
+    Error,
+    #[serde(alias = "critical")]
+    Fatal,
+}
+
+impl LogLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warning => "warning",
+            LogLevel::Error => "error",
+            LogLevel::Fatal => "fatal",
+        }
+    }
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level.trim().to_ascii_lowercase().as_str() {
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warning" | "warn" => Ok(LogLevel::Warning),
+            "error" => Ok(LogLevel::Error),
+            "fatal" | "critical" => Ok(LogLevel::Fatal),
+            other => Err(format!("Unknown log level `{}`", other)),
+        }
+    }
+}
+
+/// Structured data about the circumstances of a log. Each part is a free-form object.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogContext {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<serde_json::Map<String, serde_json::Value>>, // e.g. id, email, ip_address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<serde_json::Map<String, serde_json::Value>>, // e.g. headers, query, body
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<serde_json::Map<String, serde_json::Value>>, // e.g. name, version
+}
+
+/// Tags are sent as an object (`{"region": "eu"}`) or a list (`["region:eu", "beta"]`) and
+/// stored as a list of `key:value` strings so they can share one multikey index.
+fn deserialize_tags<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Tags {
+        List(Vec<String>),
+        Map(serde_json::Map<String, serde_json::Value>),
+    }
+
+    let tags = match Option::<Tags>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(Tags::List(tags)) => tags,
+        Some(Tags::Map(tags)) => tags
+            .into_iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(value) => format!("{}:{}", key, value),
+                value => format!("{}:{}", key, value),
+            })
+            .collect(),
+    };
+    let tags: Vec<String> = tags
+        .into_iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    Ok(Some(tags))
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogPayload {
@@ -13,14 +108,80 @@ pub struct LogPayload {
     pub application_id: Option<ObjectId>,
     pub error: String,
     pub traceback: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frames: Option<Vec<StackFrame>>, // Parsed from `traceback` on ingest
     pub url: String,
     pub method: String,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<LogLevel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_name: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_tags",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<LogContext>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub created_at: Option<DateTime<Utc>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub updated_at: Option<DateTime<Utc>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rag_inference: Option<serde_json::Value>, // Optional and can accept any structure
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag_inference_version: Option<i32>, // Version `rag_inference` holds: the accepted one, else the latest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag_inference_accepted: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag_inference_versions: Option<i32>, // Versions recorded so far, numbers the next one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rag_inference_fixed_by: Option<i32>, // Version a member marked as having fixed the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>, // Client-supplied grouping key; replaced by the computed hash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issue_id: Option<ObjectId>,
+}
+
+/// Query parameters of `GET /logs`. List values (`level`, `tags`) are comma-separated.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogListQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>, // Received at or after, second precision
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>, // Received before, second precision
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url_prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<String>, // Every tag must be present, e.g. `region:eu,beta`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>, // Full-text search over `error` and `traceback`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>, // Search language, see `services::log_query`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>, // `next_cursor` of the previous page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+}
+
+impl LogListQuery {
+    /// Page size, 100 by default and at most 1000.
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(100).clamp(1, 1000)
+    }
 }
 
 #[derive(SimpleObject)]
@@ -30,11 +191,30 @@ pub struct LogPayloadGql {
     pub application_id: Option<String>,
     pub error: String,
     pub traceback: String,
+    pub frames: Option<Vec<StackFrame>>,
     pub url: String,
     pub method: String,
+    pub level: Option<LogLevel>,
+    pub environment: Option<String>,
+    pub release: Option<String>,
+    pub server_name: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub context: Option<String>, // Represent as a JSON string in GraphQL
     pub created_at: Option<String>,
     pub updated_at: Option<String>,
-    pub rag_inference: Option<String>, // Represent as a JSON string in GraphQL
+    pub rag_inference: Option<RagInferenceGql>, // Typed by schema version, `raw` keeps the JSON
+    pub rag_inference_version: Option<i32>,
+    pub rag_inference_accepted: bool,
+    pub rag_inference_fixed_by: Option<i32>,
+    pub fingerprint: Option<String>,
+    pub issue_id: Option<String>,
+}
+
+/// A page of logs and the cursor of the next one, `None` on the last page.
+#[derive(SimpleObject)]
+pub struct LogPageGql {
+    pub logs: Vec<LogPayloadGql>,
+    pub next_cursor: Option<String>,
 }
 
 impl From<LogPayload> for LogPayloadGql {
@@ -45,11 +225,25 @@ impl From<LogPayload> for LogPayloadGql {
             application_id: log.application_id.map(|id| id.to_string()),
             error: log.error,
             traceback: log.traceback,
+            frames: log.frames,
             url: log.url,
             method: log.method,
+            level: log.level,
+            environment: log.environment,
+            release: log.release,
+            server_name: log.server_name,
+            tags: log.tags,
+            context: log
+                .context
+                .and_then(|context| serde_json::to_string(&context).ok()),
             created_at: log.created_at.map(|dt| dt.to_rfc3339()),
             updated_at: log.updated_at.map(|dt| dt.to_rfc3339()),
-            rag_inference: log.rag_inference.map(|v| v.to_string()), // Serialize the JSON to a string
+            rag_inference: log.rag_inference.map(RagInferenceGql::from),
+            rag_inference_version: log.rag_inference_version,
+            rag_inference_accepted: log.rag_inference_accepted.unwrap_or(false),
+            rag_inference_fixed_by: log.rag_inference_fixed_by,
+            fingerprint: log.fingerprint,
+            issue_id: log.issue_id.map(|id| id.to_string()),
         }
     }
 }
@@ -0,0 +1,46 @@
+use crate::models::log::LogPayloadGql;
+use async_graphql::SimpleObject;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// MinHash signature of a log's normalized error and stack frames, kept next to the log
+/// rather than in it so log responses stay small.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogSignature {
+    #[serde(rename = "_id")]
+    pub log_id: ObjectId,
+    pub organization_id: ObjectId,
+    pub application_id: ObjectId,
+    pub minhash: Vec<i64>, // One minimum per hash function, bit-cast from u64
+    pub bands: Vec<i64>,   // One key per band of the signature; logs sharing one are candidates
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SimilarLogsQuery {
+    pub limit: Option<i64>,
+    pub min_similarity: Option<f64>, // Estimated Jaccard similarity, 0.5 by default
+    pub resolved: Option<bool>,      // Only logs with an accepted or fixing inference, by default
+}
+
+impl SimilarLogsQuery {
+    /// Logs to return, 5 by default and at most 50.
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(5).clamp(1, 50) as usize
+    }
+
+    pub fn min_similarity(&self) -> f64 {
+        self.min_similarity
+            .filter(|s| (0.0..=1.0).contains(s))
+            .unwrap_or(0.5)
+    }
+
+    pub fn resolved(&self) -> bool {
+        self.resolved.unwrap_or(true)
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct SimilarLogGql {
+    pub similarity: f64,
+    pub log: LogPayloadGql,
+}
@@ -0,0 +1,57 @@
+use crate::models::api_key::ApiKeyScope;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Role of a dashboard user inside an organization, ordered from least to most privileged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrgRole {
+    Viewer, // Read logs
+    Member, // Viewer + ingest, write RAG inferences and triage issues
+    Admin,  // Member + manage applications, API keys and members
+    Owner,  // Admin + manage owners
+}
+
+impl OrgRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrgRole::Viewer => "viewer",
+            OrgRole::Member => "member",
+            OrgRole::Admin => "admin",
+            OrgRole::Owner => "owner",
+        }
+    }
+
+    /// Whether this role carries the same permission as an API key `scope`.
+    pub fn grants(&self, scope: ApiKeyScope) -> bool {
+        match scope {
+            ApiKeyScope::ReadLogs => true,
+            ApiKeyScope::Ingest | ApiKeyScope::WriteRagInference | ApiKeyScope::TriageIssues => {
+                *self >= OrgRole::Member
+            }
+            ApiKeyScope::ManageApps => *self >= OrgRole::Admin,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Membership {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub organization_id: ObjectId,
+    pub user_id: ObjectId,
+    pub role: OrgRole,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRolePayload {
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClaimOrganizationPayload {
+    pub cd_id: String,
+    pub cd_secret: String,
+}
@@ -1,5 +1,16 @@
+pub mod api_key;
 pub mod application;
+pub mod inference_job;
+pub mod invitation;
+pub mod issue;
 pub mod log;
+pub mod log_signature;
+pub mod membership;
 pub mod organization;
+pub mod rag_inference;
+pub mod saved_search;
+pub mod stats;
 pub mod otp;
+pub mod retention;
+pub mod rollup;
 pub mod user;
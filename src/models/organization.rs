@@ -1,13 +1,58 @@
+use chrono::{DateTime, Utc};
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Organization {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     pub org_name: String,
     pub admin_email: String,
-    pub admin_password: String,
+    pub admin_password_hash: String, // bcrypt hash, never the plaintext password
     pub cd_id: String,
-    pub cd_secret: String,
+    pub cd_secret_hash: String, // Salted hash of the current CD-Secret
+    #[serde(default)]
+    pub previous_secrets: Vec<RetiredSecret>, // Rotated-out secrets still inside their grace window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_rotated_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_retention_days: Option<i64>, // Applications without their own setting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>, // Set once deleted; the document goes with the last purged application
+}
+
+/// A rotated-out CD-Secret that keeps authenticating until `expires_at`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetiredSecret {
+    pub secret_hash: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateOrganizationPayload {
+    pub org_name: String,
+    pub admin_email: String,
+    pub admin_password: String,
+}
+
+/// Fields left out are unchanged.
+#[derive(Debug, Deserialize)]
+pub struct UpdateOrganizationPayload {
+    pub org_name: Option<String>,
+    pub admin_email: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransferOwnershipPayload {
+    pub user_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RotateSecretPayload {
+    pub grace_period_secs: Option<i64>, // Falls back to CD_SECRET_GRACE_PERIOD_SECS
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDefaultRetentionPayload {
+    pub default_retention_days: Option<i64>, // `null` falls back to LOG_RETENTION_DAYS
 }
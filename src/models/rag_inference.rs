@@ -0,0 +1,382 @@
+use async_graphql::SimpleObject;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// Latest `schema_version` of the `rag_inference` payload. Payloads of a version the server
+/// knows are validated on write; other versions are stored as they are and flagged.
+pub const RAG_INFERENCE_SCHEMA_VERSION: i64 = 1;
+const MAX_ROOT_CAUSE_LEN: usize = 10_000;
+const MAX_PATCH_LEN: usize = 200_000;
+const MAX_SIMILAR_LOGS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InferenceAuthorKind {
+    ApiKey,    // A worker or script using an API key
+    User,      // A signed-in member
+    OrgSecret, // The org-wide CD-ID/CD-Secret pair
+    Legacy,    // Written before inferences were versioned
+}
+
+/// Who wrote an inference version.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InferenceAuthor {
+    pub kind: InferenceAuthorKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>, // API key or user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_id: Option<String>, // Worker that completed the inference job
+}
+
+/// How an inference was produced. Every field is optional.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InferenceMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<i64>,
+}
+
+/// One RAG inference of a log. Versions are numbered from 1 per log and never overwritten.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RagInferenceVersion {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub organization_id: ObjectId,
+    pub application_id: ObjectId,
+    pub log_id: ObjectId,
+    pub version: i32,
+    pub inference: serde_json::Value,
+    #[serde(flatten)]
+    pub metadata: InferenceMetadata,
+    pub author: InferenceAuthor,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<ObjectId>, // Inference job the version completed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<i64>, // As sent in the inference, if any
+    #[serde(default)]
+    pub flagged: bool, // The schema version is unknown, so the inference was not validated
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRagInferencePayload {
+    pub inference: serde_json::Value,
+    #[serde(flatten)]
+    pub metadata: InferenceMetadata,
+}
+
+/// A member's feedback on one inference version. Saving it again replaces it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InferenceFeedback {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub organization_id: ObjectId,
+    pub application_id: ObjectId,
+    pub log_id: ObjectId,
+    pub version: i32,
+    pub user_id: ObjectId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub helpful: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub applied: Option<bool>, // The suggested patch was applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correction: Option<String>, // What the inference should have said
+    #[serde(default)]
+    pub fixed: bool, // The suggestion fixed the log's error
+    pub revision: ObjectId, // New on every save; the export pages by it
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InferenceFeedbackPayload {
+    pub helpful: Option<bool>,
+    pub applied: Option<bool>,
+    pub correction: Option<String>,
+    #[serde(default)]
+    pub fixed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedbackExportQuery {
+    pub cursor: Option<String>, // `next_cursor` of the previous page
+    pub limit: Option<i64>,
+}
+
+impl FeedbackExportQuery {
+    /// Page size, 100 by default and at most 1000.
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(100).clamp(1, 1000)
+    }
+}
+
+/// Where the inference thinks the error comes from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+pub struct SuspectedLocation {
+    pub file: String,
+    #[serde(default)]
+    pub line: Option<u32>,
+    #[serde(default)]
+    pub function: Option<String>,
+}
+
+/// An earlier log the inference found similar.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+pub struct SimilarLogRef {
+    pub log_id: String,
+    #[serde(default)]
+    pub similarity: Option<f64>, // Between 0 and 1
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// Version 1 of the `rag_inference` payload.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RagInferenceV1 {
+    pub schema_version: i64,
+    pub root_cause: String, // Summary of the root cause
+    #[serde(default)]
+    pub suspected_location: Option<SuspectedLocation>,
+    #[serde(default)]
+    pub suggested_patch: Option<String>, // Unified diff
+    pub confidence: f64, // Between 0 and 1
+    #[serde(default)]
+    pub similar_logs: Vec<SimilarLogRef>,
+}
+
+impl RagInferenceV1 {
+    /// Problems that make the payload invalid, empty if there are none.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let root_cause = self.root_cause.trim();
+        if root_cause.is_empty() || root_cause.chars().count() > MAX_ROOT_CAUSE_LEN {
+            problems.push(format!(
+                "root_cause must be between 1 and {} characters",
+                MAX_ROOT_CAUSE_LEN
+            ));
+        }
+        if let Some(location) = &self.suspected_location {
+            if location.file.trim().is_empty() {
+                problems.push("suspected_location.file must not be empty".to_string());
+            }
+            if location.line == Some(0) {
+                problems.push("suspected_location.line starts at 1".to_string());
+            }
+        }
+        if let Some(patch) = &self.suggested_patch {
+            if patch.len() > MAX_PATCH_LEN {
+                problems.push(format!(
+                    "suggested_patch must be at most {} bytes",
+                    MAX_PATCH_LEN
+                ));
+            } else if !is_unified_diff(patch) {
+                problems.push(
+                    "suggested_patch must be a unified diff with ---/+++ headers and @@ hunks"
+                        .to_string(),
+                );
+            }
+        }
+        if !(0.0..=1.0).contains(&self.confidence) {
+            problems.push("confidence must be between 0 and 1".to_string());
+        }
+        if self.similar_logs.len() > MAX_SIMILAR_LOGS {
+            problems.push(format!(
+                "similar_logs can reference at most {} logs",
+                MAX_SIMILAR_LOGS
+            ));
+        }
+        for (index, similar) in self.similar_logs.iter().enumerate() {
+            if ObjectId::parse_str(&similar.log_id).is_err() {
+                problems.push(format!("similar_logs[{}].log_id is not a log ID", index));
+            }
+            if similar
+                .similarity
+                .is_some_and(|similarity| !(0.0..=1.0).contains(&similarity))
+            {
+                problems.push(format!(
+                    "similar_logs[{}].similarity must be between 0 and 1",
+                    index
+                ));
+            }
+        }
+        problems
+    }
+}
+
+fn is_unified_diff(patch: &str) -> bool {
+    let mut lines = patch.lines();
+    let has = |prefix: &str, lines: &mut std::str::Lines| lines.any(|l| l.starts_with(prefix));
+    has("--- ", &mut lines) && has("+++ ", &mut lines) && has("@@ ", &mut lines)
+}
+
+/// `schema_version` of a `rag_inference` payload, if it has one.
+pub fn schema_version(inference: &serde_json::Value) -> Option<i64> {
+    inference.get("schema_version").and_then(|v| v.as_i64())
+}
+
+/// `rag_inference` as a GraphQL object. Payloads of a known schema version have their fields
+/// filled in; others are flagged and only carry `raw`.
+#[derive(SimpleObject)]
+pub struct RagInferenceGql {
+    pub schema_version: Option<i64>,
+    pub flagged: bool,
+    pub root_cause: Option<String>,
+    pub suspected_location: Option<SuspectedLocation>,
+    pub suggested_patch: Option<String>,
+    pub confidence: Option<f64>,
+    pub similar_logs: Vec<SimilarLogRef>,
+    pub raw: String, // The payload as stored, as JSON
+}
+
+impl From<serde_json::Value> for RagInferenceGql {
+    fn from(inference: serde_json::Value) -> Self {
+        let raw = inference.to_string();
+        let schema_version = schema_version(&inference);
+        let typed = match schema_version {
+            Some(RAG_INFERENCE_SCHEMA_VERSION) => {
+                serde_json::from_value::<RagInferenceV1>(inference).ok()
+            }
+            _ => None,
+        };
+        match typed {
+            Some(v1) => Self {
+                schema_version,
+                flagged: false,
+                root_cause: Some(v1.root_cause),
+                suspected_location: v1.suspected_location,
+                suggested_patch: v1.suggested_patch,
+                confidence: Some(v1.confidence),
+                similar_logs: v1.similar_logs,
+                raw,
+            },
+            None => Self {
+                schema_version,
+                flagged: true,
+                root_cause: None,
+                suspected_location: None,
+                suggested_patch: None,
+                confidence: None,
+                similar_logs: Vec::new(),
+                raw,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATCH: &str = "--- a/app/cache.py\n+++ b/app/cache.py\n@@ -40,3 +40,4 @@\n-    return None\n+    return self.warm()\n";
+
+    fn inference() -> RagInferenceV1 {
+        RagInferenceV1 {
+            schema_version: RAG_INFERENCE_SCHEMA_VERSION,
+            root_cause: "The cache is read before it is warmed up".to_string(),
+            suspected_location: Some(SuspectedLocation {
+                file: "app/cache.py".to_string(),
+                line: Some(42),
+                function: Some("get".to_string()),
+            }),
+            suggested_patch: Some(PATCH.to_string()),
+            confidence: 0.82,
+            similar_logs: vec![SimilarLogRef {
+                log_id: ObjectId::new().to_hex(),
+                similarity: Some(0.91),
+                note: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn accepts_a_valid_inference() {
+        assert_eq!(inference().problems(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn bounds_confidence() {
+        for confidence in [0.0, 1.0] {
+            let valid = RagInferenceV1 {
+                confidence,
+                ..inference()
+            };
+            assert!(valid.problems().is_empty());
+        }
+        for confidence in [-0.1, 1.01, f64::NAN] {
+            let invalid = RagInferenceV1 {
+                confidence,
+                ..inference()
+            };
+            assert_eq!(
+                invalid.problems(),
+                vec!["confidence must be between 0 and 1"]
+            );
+        }
+    }
+
+    #[test]
+    fn lines_start_at_one() {
+        let mut invalid = inference();
+        invalid.suspected_location.as_mut().unwrap().line = Some(0);
+
+        assert_eq!(
+            invalid.problems(),
+            vec!["suspected_location.line starts at 1"]
+        );
+    }
+
+    #[test]
+    fn requires_a_unified_diff() {
+        assert!(is_unified_diff(PATCH));
+        for patch in [
+            "just replace the return value",
+            "--- a/app/cache.py\n+++ b/app/cache.py\n",
+            "@@ -1 +1 @@\n--- a/x\n",
+            "+++ b/app/cache.py\n--- a/app/cache.py\n@@ -1 +1 @@\n",
+        ] {
+            assert!(!is_unified_diff(patch), "accepted {:?}", patch);
+            let invalid = RagInferenceV1 {
+                suggested_patch: Some(patch.to_string()),
+                ..inference()
+            };
+            assert_eq!(invalid.problems().len(), 1);
+        }
+    }
+
+    #[test]
+    fn checks_similar_logs() {
+        let mut invalid = inference();
+        invalid.similar_logs.push(SimilarLogRef {
+            log_id: "not-a-log".to_string(),
+            similarity: Some(1.5),
+            note: None,
+        });
+
+        assert_eq!(
+            invalid.problems(),
+            vec![
+                "similar_logs[1].log_id is not a log ID",
+                "similar_logs[1].similarity must be between 0 and 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let invalid = RagInferenceV1 {
+            root_cause: "  ".to_string(),
+            confidence: 2.0,
+            ..inference()
+        };
+
+        assert_eq!(invalid.problems().len(), 2);
+    }
+}
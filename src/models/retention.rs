@@ -0,0 +1,23 @@
+use serde::Serialize;
+
+/// Where the retention of an application comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionSource {
+    Application,
+    Organization,
+    Server, // LOG_RETENTION_DAYS
+}
+
+/// How much an application stores and when its oldest logs expire.
+#[derive(Debug, Serialize)]
+pub struct AppStorage {
+    pub application_id: String,
+    pub application_name: String,
+    pub retention_days: Option<i64>, // `None` keeps logs forever
+    pub retention_source: Option<RetentionSource>,
+    pub log_count: u64,
+    pub estimated_bytes: u64, // Log count times the average log size of the collection
+    pub oldest_log_at: Option<String>,
+    pub next_expiry_at: Option<String>, // When the oldest log is due for purging
+}
@@ -0,0 +1,47 @@
+use mongodb::bson::oid::ObjectId;
+
+/// Size of the buckets of a rollup collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollupGranularity {
+    Minute,
+    Hour,
+}
+
+impl RollupGranularity {
+    pub fn collection(&self) -> &'static str {
+        match self {
+            RollupGranularity::Minute => "log_rollups_minute",
+            RollupGranularity::Hour => "log_rollups_hour",
+        }
+    }
+
+    pub fn millis(&self) -> i64 {
+        match self {
+            RollupGranularity::Minute => 60_000,
+            RollupGranularity::Hour => 3_600_000,
+        }
+    }
+
+    /// Start of the bucket holding `ms` (epoch milliseconds).
+    pub fn bucket(&self, ms: i64) -> i64 {
+        ms.div_euclid(self.millis()) * self.millis()
+    }
+}
+
+/// Identifies one counter of a rollup collection. `bucket` is in epoch milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RollupKey {
+    pub application_id: ObjectId,
+    pub bucket: i64,
+    pub message_hash: String, // Hash of the normalized error message
+    pub level: Option<String>,
+    pub release: Option<String>,
+}
+
+/// What a counter adds up to, along with the fields only set when it is created.
+#[derive(Debug, Clone)]
+pub struct RollupCount {
+    pub organization_id: ObjectId,
+    pub message: String, // Issue-style title of the first error with this hash
+    pub count: i64,
+}
@@ -0,0 +1,136 @@
+use crate::models::log::LogListQuery;
+use async_graphql::{InputObject, SimpleObject};
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+/// A named set of `GET /logs` filters, owned by the user who saved it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub organization_id: ObjectId,
+    pub application_id: ObjectId,
+    pub owner_id: ObjectId,
+    pub name: String,
+    pub filters: LogListQuery, // Never holds a `cursor`
+    pub shared: bool,          // Visible to every member of the organization
+    #[serde(default)]
+    pub pinned_by: Vec<ObjectId>, // Users who opened the application on this search by default
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SavedSearch {
+    pub fn is_default_for(&self, user_id: ObjectId) -> bool {
+        self.pinned_by.contains(&user_id)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSavedSearchPayload {
+    pub name: String,
+    #[serde(default)]
+    pub filters: LogListQuery,
+    #[serde(default)]
+    pub shared: bool,
+}
+
+/// Fields left out are kept.
+#[derive(Debug, Default, Deserialize)]
+pub struct UpdateSavedSearchPayload {
+    pub name: Option<String>,
+    pub filters: Option<LogListQuery>,
+    pub shared: Option<bool>,
+}
+
+#[derive(SimpleObject)]
+pub struct SavedSearchGql {
+    pub id: Option<String>,
+    pub application_id: String,
+    pub owner_id: String,
+    pub name: String,
+    pub filters: LogFiltersGql,
+    pub shared: bool,
+    pub is_default: bool, // Pinned by the requesting user
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl SavedSearchGql {
+    pub fn new(search: SavedSearch, user_id: ObjectId) -> Self {
+        Self {
+            id: search.id.map(|id| id.to_string()),
+            application_id: search.application_id.to_string(),
+            owner_id: search.owner_id.to_string(),
+            is_default: search.is_default_for(user_id),
+            name: search.name,
+            filters: search.filters.into(),
+            shared: search.shared,
+            created_at: search.created_at.to_rfc3339(),
+            updated_at: search.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+/// The filters of `GET /logs` in GraphQL, times as RFC 3339 strings.
+#[derive(SimpleObject, InputObject, Default)]
+#[graphql(input_name = "LogFiltersInput")]
+pub struct LogFiltersGql {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub level: Option<String>,
+    pub method: Option<String>,
+    pub url_prefix: Option<String>,
+    pub environment: Option<String>,
+    pub release: Option<String>,
+    pub tags: Option<String>,
+    pub q: Option<String>,
+    pub query: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl From<LogListQuery> for LogFiltersGql {
+    fn from(filters: LogListQuery) -> Self {
+        Self {
+            from: filters.from.map(|dt| dt.to_rfc3339()),
+            to: filters.to.map(|dt| dt.to_rfc3339()),
+            level: filters.level,
+            method: filters.method,
+            url_prefix: filters.url_prefix,
+            environment: filters.environment,
+            release: filters.release,
+            tags: filters.tags,
+            q: filters.q,
+            query: filters.query,
+            limit: filters.limit,
+        }
+    }
+}
+
+impl TryFrom<LogFiltersGql> for LogListQuery {
+    type Error = String;
+
+    fn try_from(filters: LogFiltersGql) -> Result<Self, Self::Error> {
+        let time = |value: Option<String>, name: &str| {
+            value
+                .map(|v| DateTime::parse_from_rfc3339(&v).map(|dt| dt.with_timezone(&Utc)))
+                .transpose()
+                .map_err(|_| format!("`{}` must be an RFC 3339 time", name))
+        };
+        Ok(Self {
+            from: time(filters.from, "from")?,
+            to: time(filters.to, "to")?,
+            level: filters.level,
+            method: filters.method,
+            url_prefix: filters.url_prefix,
+            environment: filters.environment,
+            release: filters.release,
+            tags: filters.tags,
+            q: filters.q,
+            query: filters.query,
+            cursor: None,
+            limit: filters.limit,
+        })
+    }
+}
@@ -0,0 +1,48 @@
+use async_graphql::{Enum, SimpleObject};
+use serde::{Deserialize, Serialize};
+
+/// Log field that stats are broken down by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsDimension {
+    Level,
+    Method,
+    Url, // Without the query string
+    Release,
+    Environment,
+    ServerName,
+    Issue,
+    Tag, // A log counts once for each of its tags
+}
+
+/// Query parameters of `GET /logs/stats`, next to the filters of `GET /logs`.
+#[derive(Debug, Default, Deserialize)]
+pub struct LogStatsParams {
+    pub interval: Option<String>, // `5m`, `1h`, `1d`; without it there are no time buckets
+    pub group_by: Option<StatsDimension>,
+    pub top: Option<i64>,
+}
+
+impl LogStatsParams {
+    /// Number of series, 10 by default and at most 100.
+    pub fn top(&self) -> i64 {
+        self.top.unwrap_or(10).clamp(1, 100)
+    }
+}
+
+/// Log counts ready to chart: one series per group, each with a count per bucket.
+#[derive(Debug, Serialize, SimpleObject)]
+pub struct LogStats {
+    pub interval: Option<String>,
+    pub group_by: Option<StatsDimension>,
+    pub buckets: Vec<String>, // Start of each bucket, RFC 3339; empty without an interval
+    pub series: Vec<StatsSeries>,
+    pub total: i64, // Matching logs, including those of groups beyond `top`
+}
+
+#[derive(Debug, Serialize, SimpleObject)]
+pub struct StatsSeries {
+    pub key: Option<String>, // `None` without `group_by` and for logs missing the field
+    pub total: i64,
+    pub counts: Vec<i64>, // Aligned with `buckets`
+}
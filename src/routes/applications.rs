@@ -1,4 +1,4 @@
-use crate::handlers::application_handler;
+use crate::handlers::{api_key_handler, application_handler};
 use actix_web::web;
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
@@ -6,6 +6,26 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
         web::scope("/applications")
             .route("", web::post().to(application_handler::create_application))
             .route("", web::get().to(application_handler::get_applications))
-            .route("/{application_id}", web::delete().to(application_handler::delete_application)), // Added delete route
+            .route("/storage", web::get().to(application_handler::get_application_storage))
+            .route("/deleted", web::get().to(application_handler::list_deleted_applications))
+            .route(
+                "/deleted/{application_id}",
+                web::get().to(application_handler::get_application_deletion),
+            )
+            .route(
+                "/deleted/{application_id}/restore",
+                web::post().to(application_handler::restore_application),
+            )
+            .route("/{application_id}", web::delete().to(application_handler::delete_application)) // Added delete route
+            .route(
+                "/{application_id}/retention",
+                web::put().to(application_handler::update_application_retention),
+            )
+            .route("/{application_id}/api-keys", web::post().to(api_key_handler::create_api_key))
+            .route("/{application_id}/api-keys", web::get().to(api_key_handler::list_api_keys))
+            .route(
+                "/{application_id}/api-keys/{key_id}",
+                web::delete().to(api_key_handler::revoke_api_key),
+            ),
     );
 }
@@ -0,0 +1,24 @@
+use crate::handlers::{invitation_handler, organization_handler};
+use crate::middlewares::auth_middleware::AuthMiddleware;
+use actix_web::web;
+
+/// Routes for signed-in dashboard users. `AuthMiddleware` validates the `auth_token` cookie
+/// and inserts the `Claims` the handlers authorize with. They are resources rather than a
+/// `/dashboard` scope so `/dashboard/test` keeps working without a session.
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/dashboard/organizations")
+            .wrap(AuthMiddleware)
+            .route(web::get().to(organization_handler::list_my_organizations)),
+    )
+    .service(
+        web::resource("/dashboard/organizations/claim")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(organization_handler::claim_organization)),
+    )
+    .service(
+        web::resource("/dashboard/invitations/accept")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(invitation_handler::accept_invitation)),
+    );
+}
@@ -0,0 +1,27 @@
+use crate::handlers::{inference_job_handler, rag_inference_handler};
+use actix_web::web;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/inference/jobs")
+            .route("", web::get().to(inference_job_handler::list_jobs))
+            .route("/claim", web::post().to(inference_job_handler::claim_jobs))
+            .route("/{job_id}", web::get().to(inference_job_handler::get_job))
+            .route(
+                "/{job_id}/heartbeat",
+                web::post().to(inference_job_handler::heartbeat_job),
+            )
+            .route(
+                "/{job_id}/complete",
+                web::post().to(inference_job_handler::complete_job),
+            )
+            .route(
+                "/{job_id}/fail",
+                web::post().to(inference_job_handler::fail_job),
+            ),
+    );
+    cfg.service(web::scope("/inference/feedback").route(
+        "/export",
+        web::get().to(rag_inference_handler::export_feedback),
+    ));
+}
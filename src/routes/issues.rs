@@ -0,0 +1,22 @@
+use crate::handlers::issue_handler;
+use actix_web::web;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/issues")
+            .route("", web::get().to(issue_handler::list_issues))
+            .route("/{issue_id}", web::get().to(issue_handler::get_issue))
+            .route(
+                "/{issue_id}/logs",
+                web::get().to(issue_handler::list_issue_logs),
+            )
+            .route(
+                "/{issue_id}/status",
+                web::put().to(issue_handler::update_issue_status),
+            )
+            .route(
+                "/{issue_id}/assignee",
+                web::put().to(issue_handler::assign_issue),
+            ),
+    );
+}
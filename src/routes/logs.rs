@@ -1,12 +1,21 @@
-use crate::handlers::log_handler;
+use crate::handlers::{log_handler, rag_inference_handler};
 use actix_web::web;
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/logs")
             .route("", web::get().to(log_handler::get_all_logs)) // Get all logs
+            .route("/stats", web::get().to(log_handler::get_log_stats)) // Counts per group and time bucket
             .route("/{log_id}", web::get().to(log_handler::get_log_by_id)) // Get a log by ID
             .route("", web::post().to(log_handler::save_log)) // Save a new log
-            .route("/{log_id}/rag-inference", web::put().to(log_handler::update_rag_inference)), // Update RAG inference
+            .route("/batch", web::post().to(log_handler::save_logs_batch)) // Save many logs at once
+            .route("/{log_id}/similar", web::get().to(rag_inference_handler::similar_logs)) // Earlier resolved logs like this one
+            .route("/{log_id}/rag-inference", web::put().to(log_handler::update_rag_inference)) // Record a RAG inference version
+            .route("/{log_id}/rag-inferences", web::get().to(rag_inference_handler::list_rag_inferences)) // Inference history
+            .route("/{log_id}/rag-inferences", web::post().to(rag_inference_handler::create_rag_inference)) // Record a version with its metadata
+            .route("/{log_id}/rag-inferences/accepted", web::delete().to(rag_inference_handler::unaccept_rag_inference)) // Back to the latest version
+            .route("/{log_id}/rag-inferences/{version}/accept", web::put().to(rag_inference_handler::accept_rag_inference)) // Pin a version
+            .route("/{log_id}/rag-inferences/{version}/feedback", web::get().to(rag_inference_handler::list_feedback)) // Members' feedback on a version
+            .route("/{log_id}/rag-inferences/{version}/feedback", web::put().to(rag_inference_handler::save_feedback)), // Rate a version, mark the log fixed
     );
 }
@@ -1,19 +1,28 @@
 use actix_web::web;
 
 mod applications;
+mod dashboard;
 mod health;
+mod inference_jobs;
+mod issues;
 mod logs;
 mod organizations;
+mod saved_searches;
 mod test;
 mod users;
 mod websocket;
 
 pub fn init(cfg: &mut web::ServiceConfig) {
     logs::init_routes(cfg);
+    issues::init_routes(cfg);
+    saved_searches::init_routes(cfg);
+    inference_jobs::init_routes(cfg);
     health::init_routes(cfg);
     organizations::init_routes(cfg);
     applications::init_routes(cfg);
     websocket::init_routes(cfg);
     users::init_routes(cfg);
+    // Before `test`, whose `/dashboard` scope would otherwise take these paths
+    dashboard::init_routes(cfg);
     test::init_routes(cfg);
 }
@@ -1,4 +1,4 @@
-use crate::handlers::organization_handler;
+use crate::handlers::{invitation_handler, membership_handler, organization_handler};
 use actix_web::web;
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
@@ -11,6 +11,63 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
             .route(
                 "",
                 web::get().to(organization_handler::get_organization_details),
-            ), // Add other routes like update, delete
+            )
+            .route(
+                "",
+                web::patch().to(organization_handler::update_organization),
+            )
+            .route(
+                "",
+                web::delete().to(organization_handler::delete_organization),
+            )
+            .route(
+                "/transfer-ownership",
+                web::post().to(organization_handler::transfer_ownership),
+            )
+            .route(
+                "/secret/rotate",
+                web::post().to(organization_handler::rotate_secret),
+            )
+            .route(
+                "/secret/previous",
+                web::delete().to(organization_handler::revoke_previous_secrets),
+            )
+            .route(
+                "/retention",
+                web::put().to(organization_handler::update_default_retention),
+            )
+            .route(
+                "/{organization_id}/members",
+                web::get().to(membership_handler::list_members),
+            )
+            // Members join by accepting an invitation, so adding one invites them
+            .route(
+                "/{organization_id}/members",
+                web::post().to(invitation_handler::create_invitation),
+            )
+            .route(
+                "/{organization_id}/members/{user_id}",
+                web::put().to(membership_handler::update_member_role),
+            )
+            .route(
+                "/{organization_id}/members/{user_id}",
+                web::delete().to(membership_handler::remove_member),
+            )
+            .route(
+                "/{organization_id}/invitations",
+                web::get().to(invitation_handler::list_invitations),
+            )
+            .route(
+                "/{organization_id}/invitations",
+                web::post().to(invitation_handler::create_invitation),
+            )
+            .route(
+                "/{organization_id}/invitations/{invitation_id}/resend",
+                web::post().to(invitation_handler::resend_invitation),
+            )
+            .route(
+                "/{organization_id}/invitations/{invitation_id}",
+                web::delete().to(invitation_handler::revoke_invitation),
+            ),
     );
 }
@@ -0,0 +1,37 @@
+use crate::handlers::saved_search_handler;
+use actix_web::web;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/saved-searches")
+            .route("", web::get().to(saved_search_handler::list_saved_searches))
+            .route(
+                "",
+                web::post().to(saved_search_handler::create_saved_search),
+            )
+            .route(
+                "/{search_id}",
+                web::get().to(saved_search_handler::get_saved_search),
+            )
+            .route(
+                "/{search_id}",
+                web::put().to(saved_search_handler::update_saved_search),
+            )
+            .route(
+                "/{search_id}",
+                web::delete().to(saved_search_handler::delete_saved_search),
+            )
+            .route(
+                "/{search_id}/default",
+                web::put().to(saved_search_handler::pin_saved_search),
+            )
+            .route(
+                "/{search_id}/default",
+                web::delete().to(saved_search_handler::unpin_saved_search),
+            )
+            .route(
+                "/{search_id}/logs",
+                web::get().to(saved_search_handler::list_saved_search_logs),
+            ),
+    );
+}
@@ -0,0 +1,70 @@
+use crate::db::MongoRepo;
+use crate::models::api_key::ApiKey;
+use crate::models::application::Application;
+use crate::services::credential_service;
+use mongodb::bson::oid::ObjectId;
+use rand::RngCore;
+
+pub const API_KEY_HEADER: &str = "CD-API-Key";
+const KEY_PREFIX_TAG: &str = "cdk";
+
+/// Reasons an API key can be rejected.
+#[derive(Debug)]
+pub enum ApiKeyError {
+    Invalid,
+    ApplicationMismatch,
+    ApplicationNotFound,
+    Database,
+}
+
+/// Generates a new key of the form `cdk_<prefix>_<secret>` and returns it with its prefix.
+pub fn generate_api_key() -> (String, String) {
+    let mut prefix = [0u8; 6];
+    rand::thread_rng().fill_bytes(&mut prefix);
+    let prefix = hex::encode(prefix);
+    let key = format!(
+        "{}_{}_{}",
+        KEY_PREFIX_TAG,
+        prefix,
+        credential_service::generate_secret()
+    );
+    (key, prefix)
+}
+
+/// Resolves an API key and the application it belongs to.
+/// When `expected_app_id` is given the key must belong to that application.
+pub async fn authenticate_api_key(
+    data: &MongoRepo,
+    token: &str,
+    expected_app_id: Option<&str>,
+) -> Result<(ApiKey, Application), ApiKeyError> {
+    let token = token.trim().trim_matches('"');
+    let prefix = match token.split('_').collect::<Vec<_>>().as_slice() {
+        [KEY_PREFIX_TAG, prefix, _] => prefix.to_string(),
+        _ => return Err(ApiKeyError::Invalid),
+    };
+
+    let key = match data.get_active_api_key_by_prefix(&prefix).await {
+        Ok(Some(key)) if credential_service::verify_secret(token, &key.key_hash) => key,
+        Ok(_) => {
+            log::warn!("Rejected API key with prefix: {}", prefix);
+            return Err(ApiKeyError::Invalid);
+        }
+        Err(e) => {
+            log::error!("Database error during API key lookup: {}", e);
+            return Err(ApiKeyError::Database);
+        }
+    };
+
+    if let Some(expected) = expected_app_id {
+        if ObjectId::parse_str(expected.trim()).ok() != Some(key.application_id) {
+            return Err(ApiKeyError::ApplicationMismatch);
+        }
+    }
+
+    match data.get_application_by_id(key.application_id).await {
+        Ok(Some(app)) if app.organization_id == Some(key.organization_id) => Ok((key, app)),
+        Ok(_) => Err(ApiKeyError::ApplicationNotFound),
+        Err(_) => Err(ApiKeyError::Database),
+    }
+}
@@ -0,0 +1,206 @@
+use crate::db::MongoRepo;
+use crate::models::application::{AppDeletion, Application, DeletionStatus};
+use crate::models::rollup::RollupGranularity;
+use crate::services::organization_service;
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use chrono::{Duration, Utc};
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+
+const DEFAULT_GRACE_HOURS: i64 = 72;
+const DEFAULT_PURGE_INTERVAL_SECS: u64 = 60;
+/// Documents deleted per request, so a large application never holds one long delete.
+const PURGE_BATCH: i64 = 1000;
+/// How long a server owns a purge without reporting progress before another may resume it.
+const LEASE_SECS: i64 = 300;
+
+#[derive(Debug)]
+pub enum AppDeletionError {
+    NotFound,
+    PurgeStarted,
+    Database,
+}
+
+impl fmt::Display for AppDeletionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppDeletionError::NotFound => write!(f, "Deleted application not found"),
+            AppDeletionError::PurgeStarted => write!(
+                f,
+                "The grace period is over and the application's data is being purged"
+            ),
+            AppDeletionError::Database => write!(f, "Failed to update the application"),
+        }
+    }
+}
+
+impl ResponseError for AppDeletionError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppDeletionError::NotFound => StatusCode::NOT_FOUND,
+            AppDeletionError::PurgeStarted => StatusCode::CONFLICT,
+            AppDeletionError::Database => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+/// Time between the deletion of an application and the purge of its data, from
+/// `APP_DELETE_GRACE_HOURS`.
+pub fn grace_period() -> Duration {
+    let hours = env::var("APP_DELETE_GRACE_HOURS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n >= 0)
+        .unwrap_or(DEFAULT_GRACE_HOURS);
+    Duration::hours(hours)
+}
+
+/// Seconds between two looks for applications due for purging, from
+/// `APP_PURGE_INTERVAL_SECS`.
+pub fn purge_interval_secs() -> u64 {
+    env::var("APP_PURGE_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_PURGE_INTERVAL_SECS)
+}
+
+/// Every collection holding data of an application, with the filter selecting it.
+fn dependents(app_id: ObjectId) -> Vec<(&'static str, Document)> {
+    let by_app = doc! { "application_id": app_id };
+    vec![
+        ("logs", by_app.clone()),
+        ("issues", by_app.clone()),
+        ("saved_searches", by_app.clone()),
+        ("api_keys", by_app.clone()),
+        ("inference_jobs", by_app.clone()),
+        ("rag_inferences", by_app.clone()),
+        ("inference_feedback", by_app.clone()),
+        ("log_signatures", by_app.clone()),
+        (RollupGranularity::Minute.collection(), by_app.clone()),
+        (RollupGranularity::Hour.collection(), by_app),
+        ("rollup_coverage", doc! { "_id": app_id.to_hex() }),
+    ]
+}
+
+/// Hides the application and schedules the purge of its data after the grace period.
+/// Returns `None` if it was already deleted.
+pub async fn delete(
+    data: &MongoRepo,
+    app_id: ObjectId,
+) -> Result<Option<AppDeletion>, mongodb::error::Error> {
+    let now = Utc::now();
+    let deletion = AppDeletion {
+        status: DeletionStatus::Pending,
+        requested_at: now,
+        purge_at: now + grace_period(),
+        started_at: None,
+        completed_at: None,
+        purged: BTreeMap::new(),
+        lease_until: None,
+    };
+    let deleted = data.soft_delete_application(app_id, &deletion).await?;
+    Ok(deleted.then_some(deletion))
+}
+
+/// Brings back an application deleted less than the grace period ago.
+pub async fn undo(
+    data: &MongoRepo,
+    org_id: ObjectId,
+    app_id: ObjectId,
+) -> Result<(), AppDeletionError> {
+    match data.undo_application_deletion(org_id, app_id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => match data.get_deleted_application(org_id, app_id).await {
+            Ok(Some(_)) => Err(AppDeletionError::PurgeStarted),
+            Ok(None) => Err(AppDeletionError::NotFound),
+            Err(_) => Err(AppDeletionError::Database),
+        },
+        Err(_) => Err(AppDeletionError::Database),
+    }
+}
+
+/// Deletion state of an application, with the documents left to purge while it is purging.
+pub async fn progress(
+    data: &MongoRepo,
+    app: &Application,
+    with_remaining: bool,
+) -> Result<serde_json::Value, mongodb::error::Error> {
+    let mut remaining = serde_json::Map::new();
+    let app_id = app.id.unwrap_or_default();
+    let purging = app
+        .deletion
+        .as_ref()
+        .is_some_and(|d| d.status == DeletionStatus::Purging);
+    if with_remaining && purging {
+        for (collection, filter) in dependents(app_id) {
+            let count = data.count_documents_in(collection, filter).await?;
+            remaining.insert(collection.to_string(), count.into());
+        }
+    }
+
+    let mut json = serde_json::json!({
+        "application_id": app_id.to_hex(),
+        "application_name": app.application_name,
+        "deletion": app.deletion,
+    });
+    if !remaining.is_empty() {
+        json["remaining"] = remaining.into();
+    }
+    Ok(json)
+}
+
+/// Purges every deleted application whose grace period is over.
+pub async fn purge_due(data: &MongoRepo) -> Result<(), mongodb::error::Error> {
+    let lease = || Utc::now() + Duration::seconds(LEASE_SECS);
+    while let Some(app) = data.claim_app_deletion(lease()).await? {
+        let Some(app_id) = app.id else {
+            continue;
+        };
+        log::info!("Purging data of deleted App ID: {}", app_id);
+
+        for (collection, filter) in dependents(app_id) {
+            loop {
+                let deleted = data
+                    .delete_batch(collection, filter.clone(), PURGE_BATCH)
+                    .await?;
+                if deleted == 0 {
+                    break;
+                }
+                data.record_purged_batch(app_id, collection, deleted, lease())
+                    .await?;
+            }
+        }
+
+        data.complete_app_deletion(app_id).await?;
+        log::info!("Purged data of deleted App ID: {}", app_id);
+    }
+    Ok(())
+}
+
+/// Purges deleted applications every `APP_PURGE_INTERVAL_SECS` in the background, then the
+/// deleted organizations left without applications.
+pub fn spawn_purge_job(data: MongoRepo) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(purge_interval_secs()));
+        loop {
+            interval.tick().await;
+            if let Err(e) = purge_due(&data).await {
+                log::error!("Failed to purge deleted applications: {}", e);
+                continue;
+            }
+            if let Err(e) = organization_service::purge_deleted(&data).await {
+                log::error!("Failed to purge deleted organizations: {}", e);
+            }
+        }
+    });
+}
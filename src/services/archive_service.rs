@@ -0,0 +1,426 @@
+use crate::db::MongoRepo;
+use crate::services::s3_client::S3Client;
+use chrono::{NaiveDate, TimeZone, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use mongodb::bson::{self, oid::ObjectId, Bson, Document};
+use std::env;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+/// Logs read, written to one archive file and deleted at a time.
+const ARCHIVE_BATCH: i64 = 10_000;
+/// Logs inserted at a time when restoring.
+const RESTORE_BATCH: usize = 1000;
+const DEFAULT_RESTORE_DAYS: i64 = 7;
+
+/// Where archives are written.
+#[derive(Debug, Clone)]
+pub enum ArchiveTarget {
+    Local(PathBuf),
+    S3(S3Client),
+}
+
+/// Archival settings, read from the `LOG_ARCHIVE*` variables.
+#[derive(Debug, Clone)]
+pub struct ArchiveConfig {
+    pub target: ArchiveTarget,
+    pub parquet: bool,     // Also write a Parquet file next to each NDJSON one
+    pub restore_days: i64, // How long restored logs are kept
+}
+
+/// Archival settings, `None` when `LOG_ARCHIVE` is unset. An incomplete configuration is an
+/// error rather than `None` so expired logs are not deleted without their archive.
+pub fn config() -> Result<Option<ArchiveConfig>, String> {
+    let target = match env::var("LOG_ARCHIVE").ok().as_deref().map(str::trim) {
+        None | Some("") => return Ok(None),
+        Some("local") => ArchiveTarget::Local(PathBuf::from(
+            env::var("LOG_ARCHIVE_DIR").unwrap_or_else(|_| "archive".to_string()),
+        )),
+        Some("s3") => {
+            let var = |name: &str| {
+                env::var(name)
+                    .ok()
+                    .filter(|v| !v.is_empty())
+                    .ok_or_else(|| format!("{} is required when LOG_ARCHIVE=s3", name))
+            };
+            ArchiveTarget::S3(S3Client::new(
+                &var("LOG_ARCHIVE_S3_ENDPOINT")?,
+                &var("LOG_ARCHIVE_S3_BUCKET")?,
+                &env::var("LOG_ARCHIVE_S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                &var("LOG_ARCHIVE_S3_ACCESS_KEY")?,
+                &var("LOG_ARCHIVE_S3_SECRET_KEY")?,
+            ))
+        }
+        Some(other) => {
+            return Err(format!(
+                "Unknown LOG_ARCHIVE `{}`, expected `local` or `s3`",
+                other
+            ))
+        }
+    };
+
+    let parquet = env::var("LOG_ARCHIVE_PARQUET").is_ok_and(|v| v == "true" || v == "1");
+    if parquet && !cfg!(feature = "parquet") {
+        return Err("LOG_ARCHIVE_PARQUET needs a build with the `parquet` feature".to_string());
+    }
+    let restore_days = env::var("LOG_ARCHIVE_RESTORE_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_RESTORE_DAYS);
+
+    Ok(Some(ArchiveConfig {
+        target,
+        parquet,
+        restore_days,
+    }))
+}
+
+impl ArchiveTarget {
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), String> {
+        match self {
+            ArchiveTarget::Local(dir) => {
+                let path = dir.join(key);
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+                tokio::fs::write(&path, body)
+                    .await
+                    .map_err(|e| format!("{}: {}", path.display(), e))
+            }
+            ArchiveTarget::S3(client) => client.put_object(key, body).await,
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, String> {
+        match self {
+            ArchiveTarget::Local(dir) => {
+                let path = dir.join(key);
+                tokio::fs::read(&path)
+                    .await
+                    .map_err(|e| format!("{}: {}", path.display(), e))
+            }
+            ArchiveTarget::S3(client) => client.get_object(key).await,
+        }
+    }
+
+    /// Keys of the archive files under `prefix`, a directory path ending with `/`.
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        match self {
+            ArchiveTarget::Local(dir) => {
+                let mut keys = Vec::new();
+                let mut pending = vec![prefix.trim_end_matches('/').to_string()];
+                while let Some(relative) = pending.pop() {
+                    let mut entries = match tokio::fs::read_dir(dir.join(&relative)).await {
+                        Ok(entries) => entries,
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                        Err(e) => return Err(e.to_string()),
+                    };
+                    while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+                        let key = format!("{}/{}", relative, entry.file_name().to_string_lossy());
+                        let is_dir = entry.file_type().await.map_err(|e| e.to_string())?.is_dir();
+                        if is_dir {
+                            pending.push(key);
+                        } else {
+                            keys.push(key);
+                        }
+                    }
+                }
+                keys.sort();
+                Ok(keys)
+            }
+            ArchiveTarget::S3(client) => client.list_objects(prefix).await,
+        }
+    }
+}
+
+/// Directory of the archives of an application: `org=<id>/app=<id>/`.
+fn app_prefix(org_id: ObjectId, app_id: ObjectId) -> String {
+    format!("org={}/app={}/", org_id.to_hex(), app_id.to_hex())
+}
+
+/// Day a log was received, from its id.
+fn log_day(log: &Document) -> Option<NaiveDate> {
+    let id = log.get_object_id("_id").ok()?;
+    Utc.timestamp_millis_opt(id.timestamp().timestamp_millis())
+        .single()
+        .map(|time| time.date_naive())
+}
+
+/// Writes the logs of an application received before the time of `before` to the archive,
+/// then deletes them. Logs are only deleted once their file is written. Returns how many were
+/// archived.
+pub async fn archive_expired(
+    data: &MongoRepo,
+    config: &ArchiveConfig,
+    org_id: ObjectId,
+    app_id: ObjectId,
+    before: ObjectId,
+) -> Result<u64, String> {
+    let mut archived = 0;
+    loop {
+        let mut logs = data
+            .find_logs_before(app_id, before, ARCHIVE_BATCH)
+            .await
+            .map_err(|e| e.to_string())?;
+        let Some(day) = logs.first().and_then(log_day) else {
+            return Ok(archived);
+        };
+
+        // One file per day partition
+        let same_day = logs
+            .iter()
+            .position(|log| log_day(log) != Some(day))
+            .unwrap_or(logs.len());
+        logs.truncate(same_day);
+        let (Ok(first), Ok(last)) = (
+            logs[0].get_object_id("_id"),
+            logs[logs.len() - 1].get_object_id("_id"),
+        ) else {
+            return Err("Archived log without an id".to_string());
+        };
+
+        // Named after the first log, which is deleted once written, so names never repeat
+        let key = format!(
+            "{}day={}/part-{}",
+            app_prefix(org_id, app_id),
+            day.format("%Y-%m-%d"),
+            first.to_hex()
+        );
+        config
+            .target
+            .put(&format!("{}.ndjson.gz", key), encode_ndjson(&logs)?)
+            .await?;
+        if config.parquet {
+            config
+                .target
+                .put(&format!("{}.parquet", key), parquet::encode(&logs)?)
+                .await?;
+        }
+
+        let deleted = data
+            .delete_log_range(app_id, first, last)
+            .await
+            .map_err(|e| e.to_string())?;
+        archived += deleted;
+    }
+}
+
+fn encode_ndjson(logs: &[Document]) -> Result<Vec<u8>, String> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    for log in logs {
+        // Canonical extended JSON keeps every BSON type for the restore
+        let line = Bson::Document(log.clone()).into_canonical_extjson();
+        serde_json::to_writer(&mut encoder, &line).map_err(|e| e.to_string())?;
+        encoder.write_all(b"\n").map_err(|e| e.to_string())?;
+    }
+    encoder.finish().map_err(|e| e.to_string())
+}
+
+fn decode_ndjson(body: &[u8]) -> Result<Vec<Document>, String> {
+    let mut text = String::new();
+    GzDecoder::new(body)
+        .read_to_string(&mut text)
+        .map_err(|e| e.to_string())?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let value: serde_json::Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+            match Bson::try_from(value).map_err(|e| e.to_string())? {
+                Bson::Document(log) => Ok(log),
+                _ => Err("Archived line is not a log".to_string()),
+            }
+        })
+        .collect()
+}
+
+/// What a restore re-imported.
+#[derive(Debug)]
+pub struct RestoreSummary {
+    pub files: usize,
+    pub logs: u64,
+}
+
+/// Re-imports the archived logs of an application received from `from` to `to` (inclusive
+/// days, UTC) into `logs`. Restored logs are marked with `restored_at` and kept for
+/// `LOG_ARCHIVE_RESTORE_DAYS` whatever the retention.
+pub async fn restore(
+    data: &MongoRepo,
+    config: &ArchiveConfig,
+    app_id: ObjectId,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<RestoreSummary, String> {
+    let app = data
+        .get_application_by_id(app_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Application not found")?;
+    let org_id = app
+        .organization_id
+        .ok_or("Application has no organization")?;
+
+    let keys = config.target.list(&app_prefix(org_id, app_id)).await?;
+    let keys: Vec<String> = keys
+        .into_iter()
+        .filter(|key| key.ends_with(".ndjson.gz"))
+        .filter(|key| {
+            key.split('/')
+                .find_map(|part| part.strip_prefix("day="))
+                .and_then(|day| NaiveDate::parse_from_str(day, "%Y-%m-%d").ok())
+                .is_some_and(|day| from <= day && day <= to)
+        })
+        .collect();
+
+    // A BSON date so the purge can compare it
+    let restored_at = bson::DateTime::now();
+    let mut summary = RestoreSummary {
+        files: keys.len(),
+        logs: 0,
+    };
+    for key in &keys {
+        let mut logs = decode_ndjson(&config.target.get(key).await?)?;
+        for log in logs.iter_mut() {
+            log.insert("restored_at", restored_at);
+        }
+        while !logs.is_empty() {
+            let rest = logs.split_off(logs.len().min(RESTORE_BATCH));
+            summary.logs += data
+                .insert_log_documents(logs)
+                .await
+                .map_err(|e| e.to_string())?;
+            logs = rest;
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(feature = "parquet")]
+mod parquet {
+    use mongodb::bson::{Bson, Document};
+    use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+    use std::sync::Arc;
+
+    /// Flat columns for analytics; `document` holds the whole log as extended JSON.
+    const SCHEMA: &str = "
+        message log {
+            REQUIRED BINARY id (UTF8);
+            REQUIRED INT64 received_at (TIMESTAMP_MILLIS);
+            OPTIONAL BINARY level (UTF8);
+            OPTIONAL BINARY method (UTF8);
+            OPTIONAL BINARY url (UTF8);
+            OPTIONAL BINARY error (UTF8);
+            OPTIONAL BINARY release (UTF8);
+            OPTIONAL BINARY environment (UTF8);
+            OPTIONAL BINARY server_name (UTF8);
+            OPTIONAL BINARY issue_id (UTF8);
+            REQUIRED BINARY document (UTF8);
+        }
+    ";
+    const STRING_FIELDS: [&str; 8] = [
+        "level",
+        "method",
+        "url",
+        "error",
+        "release",
+        "environment",
+        "server_name",
+        "issue_id",
+    ];
+
+    pub fn encode(logs: &[Document]) -> Result<Vec<u8>, String> {
+        let to_string = |e: parquet::errors::ParquetError| e.to_string();
+        let schema = Arc::new(parse_message_type(SCHEMA).map_err(to_string)?);
+        let properties = Arc::new(WriterProperties::builder().build());
+        let mut writer =
+            SerializedFileWriter::new(Vec::new(), schema, properties).map_err(to_string)?;
+        let mut row_group = writer.next_row_group().map_err(to_string)?;
+
+        let mut index = 0;
+        while let Some(mut column) = row_group.next_column().map_err(to_string)? {
+            match index {
+                0 => {
+                    let ids: Vec<ByteArray> = logs
+                        .iter()
+                        .map(|log| match log.get("_id") {
+                            Some(Bson::ObjectId(id)) => id.to_hex().as_str().into(),
+                            _ => "".into(),
+                        })
+                        .collect();
+                    column
+                        .typed::<ByteArrayType>()
+                        .write_batch(&ids, None, None)
+                        .map_err(to_string)?;
+                }
+                1 => {
+                    let received: Vec<i64> = logs
+                        .iter()
+                        .map(|log| match log.get("_id") {
+                            Some(Bson::ObjectId(id)) => id.timestamp().timestamp_millis(),
+                            _ => 0,
+                        })
+                        .collect();
+                    column
+                        .typed::<Int64Type>()
+                        .write_batch(&received, None, None)
+                        .map_err(to_string)?;
+                }
+                10 => {
+                    let documents: Vec<ByteArray> = logs
+                        .iter()
+                        .map(|log| {
+                            Bson::Document(log.clone())
+                                .into_canonical_extjson()
+                                .to_string()
+                                .as_str()
+                                .into()
+                        })
+                        .collect();
+                    column
+                        .typed::<ByteArrayType>()
+                        .write_batch(&documents, None, None)
+                        .map_err(to_string)?;
+                }
+                _ => {
+                    let field = STRING_FIELDS[index - 2];
+                    let mut values: Vec<ByteArray> = Vec::new();
+                    let mut levels = Vec::with_capacity(logs.len());
+                    for log in logs {
+                        let value = match log.get(field) {
+                            Some(Bson::String(value)) => Some(value.clone()),
+                            Some(Bson::ObjectId(id)) => Some(id.to_hex()),
+                            _ => None,
+                        };
+                        levels.push(i16::from(value.is_some()));
+                        values.extend(value.map(|v| ByteArray::from(v.as_str())));
+                    }
+                    column
+                        .typed::<ByteArrayType>()
+                        .write_batch(&values, Some(&levels), None)
+                        .map_err(to_string)?;
+                }
+            }
+            column.close().map_err(to_string)?;
+            index += 1;
+        }
+        row_group.close().map_err(to_string)?;
+        writer.into_inner().map_err(to_string)
+    }
+}
+
+/// Without the `parquet` feature `config` refuses `LOG_ARCHIVE_PARQUET`, so this never runs.
+#[cfg(not(feature = "parquet"))]
+mod parquet {
+    use mongodb::bson::Document;
+
+    pub fn encode(_logs: &[Document]) -> Result<Vec<u8>, String> {
+        Err("Built without the `parquet` feature".to_string())
+    }
+}
@@ -0,0 +1,155 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::env;
+use subtle::ConstantTimeEq;
+
+const HASH_SCHEME: &str = "sha256";
+const DEFAULT_GRACE_PERIOD_SECS: i64 = 24 * 60 * 60;
+/// Longest a rotated-out secret can keep working: 30 days.
+const MAX_GRACE_PERIOD_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// Generates a new random CD-Secret (256 bits, hex encoded).
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Generates a new public CD-ID of the form `cd_<32 hex chars>`.
+pub fn generate_cd_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("cd_{}", hex::encode(bytes))
+}
+
+/// Hashes a secret with a fresh random salt.
+/// The result has the form `sha256$<salt>$<digest>` and is what gets stored in Mongo.
+pub fn hash_secret(secret: &str) -> String {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let digest = digest(&salt, secret);
+    format!(
+        "{}${}${}",
+        HASH_SCHEME,
+        hex::encode(salt),
+        hex::encode(digest)
+    )
+}
+
+/// Checks a secret against a stored hash in constant time.
+pub fn verify_secret(secret: &str, stored_hash: &str) -> bool {
+    let mut parts = stored_hash.splitn(3, '$');
+    let (Some(scheme), Some(salt), Some(expected)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return false;
+    };
+    if scheme != HASH_SCHEME {
+        return false;
+    }
+    let (Ok(salt), Ok(expected)) = (hex::decode(salt), hex::decode(expected)) else {
+        return false;
+    };
+
+    digest(&salt, secret).ct_eq(expected.as_slice()).into()
+}
+
+/// How long a rotated-out secret keeps working, from `CD_SECRET_GRACE_PERIOD_SECS` (default 24h).
+pub fn default_grace_period() -> chrono::Duration {
+    let secs = env::var("CD_SECRET_GRACE_PERIOD_SECS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_GRACE_PERIOD_SECS);
+    grace_period(secs)
+}
+
+/// A grace period of `secs`, clamped to between zero and `MAX_GRACE_PERIOD_SECS`.
+pub fn grace_period(secs: i64) -> chrono::Duration {
+    chrono::Duration::seconds(secs.clamp(0, MAX_GRACE_PERIOD_SECS))
+}
+
+fn digest(salt: &[u8], secret: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(secret.as_bytes());
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_round_trip() {
+        let secret = generate_secret();
+        let hash = hash_secret(&secret);
+
+        let parts: Vec<&str> = hash.split('$').collect();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], HASH_SCHEME);
+        assert_eq!(parts[1].len(), 32);
+        assert_eq!(parts[2].len(), 64);
+        assert!(verify_secret(&secret, &hash));
+    }
+
+    #[test]
+    fn salts_every_hash() {
+        let secret = generate_secret();
+        let (a, b) = (hash_secret(&secret), hash_secret(&secret));
+
+        assert_ne!(a, b);
+        assert!(verify_secret(&secret, &a));
+        assert!(verify_secret(&secret, &b));
+    }
+
+    #[test]
+    fn rejects_wrong_secrets() {
+        let secret = generate_secret();
+        let hash = hash_secret(&secret);
+
+        assert!(!verify_secret(&generate_secret(), &hash));
+        assert!(!verify_secret("", &hash));
+        assert!(!verify_secret(&secret[1..], &hash));
+    }
+
+    #[test]
+    fn rejects_malformed_hashes() {
+        let secret = generate_secret();
+        let hash = hash_secret(&secret);
+        let (_, rest) = hash.split_once('$').unwrap();
+        let (salt, digest) = rest.split_once('$').unwrap();
+
+        for stored in [
+            String::new(),
+            secret.clone(),
+            format!("{}${}", HASH_SCHEME, salt),
+            format!("md5${}${}", salt, digest),
+            format!("{}$not-hex${}", HASH_SCHEME, digest),
+            format!("{}${}$not-hex", HASH_SCHEME, salt),
+            format!("{}${}${}", HASH_SCHEME, salt, &digest[..62]),
+            format!("{}${}${}$", HASH_SCHEME, salt, digest),
+        ] {
+            assert!(!verify_secret(&secret, &stored), "accepted {:?}", stored);
+        }
+    }
+
+    #[test]
+    fn clamps_the_grace_period() {
+        assert_eq!(grace_period(3600), chrono::Duration::hours(1));
+        assert_eq!(grace_period(0), chrono::Duration::zero());
+        assert_eq!(grace_period(-60), chrono::Duration::zero());
+        assert_eq!(
+            grace_period(MAX_GRACE_PERIOD_SECS),
+            chrono::Duration::days(30)
+        );
+        assert_eq!(grace_period(i64::MAX), chrono::Duration::days(30));
+    }
+
+    #[test]
+    fn generates_credentials() {
+        let cd_id = generate_cd_id();
+        assert!(cd_id.starts_with("cd_"));
+        assert_eq!(cd_id.len(), 35);
+        assert_eq!(generate_secret().len(), 64);
+        assert_ne!(generate_secret(), generate_secret());
+    }
+}
@@ -7,6 +7,12 @@ pub struct EmailService {
     api_key: String,
 }
 
+impl Default for EmailService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EmailService {
     pub fn new() -> Self {
         let sender_email = "admin@neocadmium.softwarescompound.in".to_string();
@@ -0,0 +1,300 @@
+use crate::models::log::{LogPayload, StackFrame};
+use crate::services::traceback_parser;
+use sha2::{Digest, Sha256};
+
+/// Number of stack frames that take part in grouping.
+const FRAME_COUNT: usize = 5;
+const TITLE_MAX_CHARS: usize = 200;
+
+/// Computes the grouping key of a log. A client-supplied fingerprint wins; otherwise the
+/// normalized error, the top frames and the route template of the URL are hashed.
+pub fn compute_fingerprint(log: &LogPayload) -> String {
+    if let Some(custom) = log
+        .fingerprint
+        .as_deref()
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+    {
+        return hash_parts(&["custom", custom]);
+    }
+
+    let error = normalize_message(&log.error);
+    let frames = match &log.frames {
+        Some(frames) => top_frames(frames),
+        None => top_frames(&traceback_parser::parse(&log.traceback)),
+    };
+    let frames = frames.join("\n");
+    let route = route_template(&log.url);
+    hash_parts(&["default", &error, &frames, &route])
+}
+
+/// Groups errors by their normalized message alone, whatever the stack or URL.
+pub fn message_hash(error: &str) -> String {
+    hash_parts(&["message", &normalize_message(error)])
+}
+
+fn hash_parts(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0u8]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Human readable issue title: the first line of the error, shortened.
+pub fn issue_title(error: &str) -> String {
+    let line = error.lines().map(str::trim).find(|l| !l.is_empty());
+    let line = line.unwrap_or("<no error message>");
+    if line.chars().count() > TITLE_MAX_CHARS {
+        let mut title: String = line.chars().take(TITLE_MAX_CHARS).collect();
+        title.push('…');
+        title
+    } else {
+        line.to_string()
+    }
+}
+
+/// Replaces the variable parts of a message (numbers, ids, addresses, quoted values)
+/// with placeholders so that occurrences of the same error compare equal.
+pub fn normalize_message(message: &str) -> String {
+    let chars: Vec<char> = message.chars().collect();
+    let mut out = String::with_capacity(message.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '"' || c == '\'' {
+            let after_word = i > 0 && chars[i - 1].is_alphanumeric();
+            let close = chars[i + 1..]
+                .iter()
+                .position(|&n| n == c || n == '\n')
+                .map(|p| p + i + 1);
+            if let Some(end) = close.filter(|&end| !after_word && chars[end] == c) {
+                out.push_str("<str>");
+                i = end + 1;
+                continue;
+            }
+            out.push(c);
+            i += 1;
+        } else if c.is_alphanumeric() {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '-' || chars[i] == '_')
+            {
+                i += 1;
+            }
+            let token: String = chars[start..i].iter().collect();
+            out.push_str(classify_token(&token).unwrap_or(&token));
+        } else if c.is_whitespace() {
+            if !out.ends_with(' ') {
+                out.push(' ');
+            }
+            i += 1;
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+
+    out.trim().to_string()
+}
+
+/// Placeholder for tokens that vary between occurrences, `None` for regular words.
+fn classify_token(token: &str) -> Option<&'static str> {
+    let is_hex = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_hexdigit());
+
+    if token.chars().all(|c| c.is_ascii_digit()) {
+        Some("<n>")
+    } else if token.strip_prefix("0x").is_some_and(is_hex) {
+        Some("<hex>")
+    } else if is_uuid(token) {
+        Some("<uuid>")
+    } else if token.len() >= 16 && is_hex(token) && token.chars().any(|c| c.is_ascii_digit()) {
+        Some("<hex>")
+    } else {
+        None
+    }
+}
+
+fn is_uuid(token: &str) -> bool {
+    token.len() == 36
+        && token.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Reduces a URL to its path with id-like segments replaced, e.g.
+/// `https://api.example.com/users/42/orders?page=2` becomes `/users/{id}/orders`.
+pub fn route_template(url: &str) -> String {
+    let url = url.trim();
+    let without_scheme = match url.find("://") {
+        Some(pos) => &url[pos + 3..],
+        None => url,
+    };
+    let path = if url.contains("://") {
+        without_scheme
+            .find('/')
+            .map(|pos| &without_scheme[pos..])
+            .unwrap_or("/")
+    } else {
+        without_scheme
+    };
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+
+    let segments: Vec<&str> = path
+        .split('/')
+        .map(|segment| {
+            if is_id_segment(segment) {
+                "{id}"
+            } else {
+                segment
+            }
+        })
+        .collect();
+    segments.join("/")
+}
+
+fn is_id_segment(segment: &str) -> bool {
+    if segment.is_empty() {
+        return false;
+    }
+    let digits = segment.chars().filter(|c| c.is_ascii_digit()).count();
+    segment.chars().all(|c| c.is_ascii_digit())
+        || is_uuid(segment)
+        || (segment.len() >= 16 && segment.chars().all(|c| c.is_ascii_hexdigit()) && digits > 0)
+        || (segment.len() >= 20 && digits > 0)
+}
+
+/// Identifies the innermost frames by file and function, leaving out line numbers so that
+/// unrelated edits do not split issues. In-app frames are preferred when there are any.
+pub fn top_frames(frames: &[StackFrame]) -> Vec<String> {
+    let in_app = frames.iter().any(|f| f.in_app);
+    let mut top: Vec<String> = frames
+        .iter()
+        .filter(|f| f.in_app || !in_app)
+        .take(FRAME_COUNT)
+        .map(|f| {
+            format!(
+                "{}:{}",
+                f.file.as_deref().unwrap_or("?"),
+                f.function.as_deref().unwrap_or("?")
+            )
+        })
+        .collect();
+    top.dedup();
+    top
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACEBACK: &str = r#"Traceback (most recent call last):
+  File "/app/orders.py", line 42, in load_order
+KeyError: 42"#;
+
+    fn log(error: &str, traceback: &str, url: &str) -> LogPayload {
+        serde_json::from_value(serde_json::json!({
+            "error": error,
+            "traceback": traceback,
+            "url": url,
+            "method": "GET",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn normalizes_variable_parts() {
+        assert_eq!(
+            normalize_message("Order 42 not found for user 7"),
+            "Order <n> not found for user <n>"
+        );
+        assert_eq!(
+            normalize_message("Segfault at 0x7ffd5e8c and 0XZZ"),
+            "Segfault at <hex> and 0XZZ"
+        );
+        assert_eq!(
+            normalize_message("No session 550e8400-e29b-41d4-a716-446655440000"),
+            "No session <uuid>"
+        );
+        assert_eq!(
+            normalize_message("Document 64f1c2a9e4b0a1b2c3d4e5f6 missing"),
+            "Document <hex> missing"
+        );
+        assert_eq!(
+            normalize_message("KeyError: 'user_id' in \"orders\"  table"),
+            "KeyError: <str> in <str> table"
+        );
+        // Apostrophes inside words and short hex-like words are kept
+        assert_eq!(
+            normalize_message("Can't decode deadbeef"),
+            "Can't decode deadbeef"
+        );
+    }
+
+    #[test]
+    fn same_error_with_other_ids_gets_the_same_fingerprint() {
+        let a = log(
+            "Order 42 not found (trace 0x1f2e, request 550e8400-e29b-41d4-a716-446655440000)",
+            TRACEBACK,
+            "https://shop.example.com/orders/42?page=2",
+        );
+        let b = log(
+            "Order 1337 not found (trace 0xabc, request 123e4567-e89b-12d3-a456-426614174000)",
+            TRACEBACK,
+            "/orders/1337",
+        );
+
+        assert_eq!(compute_fingerprint(&a), compute_fingerprint(&b));
+        assert_eq!(message_hash(&a.error), message_hash(&b.error));
+    }
+
+    #[test]
+    fn different_errors_get_different_fingerprints() {
+        let a = log("Order 42 not found", TRACEBACK, "/orders/42");
+        let other_message = log("Order 42 was cancelled", TRACEBACK, "/orders/42");
+        let other_route = log("Order 42 not found", TRACEBACK, "/carts/42");
+        let other_frames = log("Order 42 not found", "", "/orders/42");
+
+        let fingerprint = compute_fingerprint(&a);
+        for other in [other_message, other_route, other_frames] {
+            assert_ne!(fingerprint, compute_fingerprint(&other));
+        }
+    }
+
+    #[test]
+    fn client_fingerprint_takes_precedence() {
+        let mut a = log("Order 42 not found", TRACEBACK, "/orders/42");
+        let mut b = log("Payment declined", "", "/checkout");
+        a.fingerprint = Some("checkout-flow".to_string());
+        b.fingerprint = Some("  checkout-flow ".to_string());
+
+        assert_eq!(compute_fingerprint(&a), compute_fingerprint(&b));
+        assert_ne!(
+            compute_fingerprint(&a),
+            compute_fingerprint(&log("Order 42 not found", TRACEBACK, "/orders/42"))
+        );
+
+        // A blank one is ignored
+        b.fingerprint = Some("  ".to_string());
+        assert_eq!(
+            compute_fingerprint(&b),
+            compute_fingerprint(&log("Payment declined", "", "/checkout"))
+        );
+    }
+
+    #[test]
+    fn templates_routes() {
+        assert_eq!(
+            route_template("https://api.example.com/users/42/orders?page=2#top"),
+            "/users/{id}/orders"
+        );
+        assert_eq!(
+            route_template("/files/550e8400-e29b-41d4-a716-446655440000/download"),
+            "/files/{id}/download"
+        );
+        assert_eq!(route_template("https://example.com"), "/");
+    }
+}
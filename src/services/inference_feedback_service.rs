@@ -0,0 +1,226 @@
+use crate::db::MongoRepo;
+use crate::extractors::tenant::AuthenticatedApp;
+use crate::models::rag_inference::{
+    FeedbackExportQuery, InferenceFeedback, InferenceFeedbackPayload,
+};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use std::collections::HashMap;
+use std::fmt;
+
+const MAX_CORRECTION_LEN: usize = 10_000;
+
+#[derive(Debug)]
+pub enum FeedbackError {
+    NotAMember,
+    CorrectionTooLong,
+    InvalidCursor,
+    VersionNotFound,
+    Database,
+}
+
+impl fmt::Display for FeedbackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeedbackError::NotAMember => {
+                write!(f, "Feedback can only be given by signed-in members")
+            }
+            FeedbackError::CorrectionTooLong => write!(
+                f,
+                "correction must be at most {} characters",
+                MAX_CORRECTION_LEN
+            ),
+            FeedbackError::InvalidCursor => write!(f, "Invalid cursor"),
+            FeedbackError::VersionNotFound => write!(f, "RAG inference version not found"),
+            FeedbackError::Database => write!(f, "Failed to update inference feedback"),
+        }
+    }
+}
+
+impl ResponseError for FeedbackError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            FeedbackError::NotAMember => StatusCode::FORBIDDEN,
+            FeedbackError::CorrectionTooLong | FeedbackError::InvalidCursor => {
+                StatusCode::BAD_REQUEST
+            }
+            FeedbackError::VersionNotFound => StatusCode::NOT_FOUND,
+            FeedbackError::Database => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+impl From<mongodb::error::Error> for FeedbackError {
+    fn from(_: mongodb::error::Error) -> Self {
+        FeedbackError::Database
+    }
+}
+
+pub fn feedback_json(feedback: &InferenceFeedback) -> serde_json::Value {
+    serde_json::json!({
+        "id": feedback.id.map(|id| id.to_hex()),
+        "log_id": feedback.log_id.to_hex(),
+        "version": feedback.version,
+        "user_id": feedback.user_id.to_hex(),
+        "helpful": feedback.helpful,
+        "applied": feedback.applied,
+        "correction": feedback.correction,
+        "fixed": feedback.fixed,
+        "created_at": feedback.created_at.to_rfc3339(),
+        "updated_at": feedback.updated_at.to_rfc3339(),
+    })
+}
+
+/// Saves the signed-in member's feedback on an inference version, replacing what they said
+/// before, and keeps the log's "fixed by" mark in line with it.
+pub async fn save(
+    data: &MongoRepo,
+    auth: &AuthenticatedApp,
+    log_id: ObjectId,
+    version: i32,
+    payload: InferenceFeedbackPayload,
+) -> Result<InferenceFeedback, FeedbackError> {
+    let user_id = auth.access.user_id().ok_or(FeedbackError::NotAMember)?;
+    let correction = payload
+        .correction
+        .map(|correction| correction.trim().to_string())
+        .filter(|correction| !correction.is_empty());
+    if correction
+        .as_ref()
+        .is_some_and(|correction| correction.chars().count() > MAX_CORRECTION_LEN)
+    {
+        return Err(FeedbackError::CorrectionTooLong);
+    }
+
+    let app_id = auth.app_id();
+    data.get_rag_inference(app_id, log_id, Some(version))
+        .await?
+        .ok_or(FeedbackError::VersionNotFound)?;
+
+    let now = Utc::now();
+    let feedback = InferenceFeedback {
+        id: None,
+        organization_id: auth.org_id(),
+        application_id: app_id,
+        log_id,
+        version,
+        user_id,
+        helpful: payload.helpful,
+        applied: payload.applied,
+        correction,
+        fixed: payload.fixed,
+        revision: ObjectId::new(),
+        created_at: now,
+        updated_at: now,
+    };
+    let saved = data
+        .save_inference_feedback(&feedback)
+        .await?
+        .ok_or(FeedbackError::Database)?;
+
+    // The mark stays while any member still says this version fixed the log
+    let fixed = saved.fixed || data.inference_marked_fixed(app_id, log_id, version).await?;
+    data.set_log_fixed_by(app_id, log_id, version, fixed)
+        .await?;
+    Ok(saved)
+}
+
+/// Feedback on a log's inference versions, or on one of them.
+pub async fn list(
+    data: &MongoRepo,
+    app_id: ObjectId,
+    log_id: ObjectId,
+    version: Option<i32>,
+) -> Result<Vec<serde_json::Value>, FeedbackError> {
+    let feedback = data
+        .list_inference_feedback(app_id, log_id, version)
+        .await?;
+    Ok(feedback.iter().map(feedback_json).collect())
+}
+
+/// A page of the application's feedback in the order it was saved, each entry with the
+/// inference version it rates and the log it was inferred from. Feedback saved again shows
+/// up again in a later page.
+pub async fn export(
+    data: &MongoRepo,
+    app_id: ObjectId,
+    query: &FeedbackExportQuery,
+) -> Result<serde_json::Value, FeedbackError> {
+    let after = match query.cursor.as_deref().filter(|c| !c.is_empty()) {
+        Some(cursor) => {
+            Some(ObjectId::parse_str(cursor).map_err(|_| FeedbackError::InvalidCursor)?)
+        }
+        None => None,
+    };
+    let limit = query.limit();
+    let feedback = data.export_inference_feedback(app_id, after, limit).await?;
+
+    let mut log_ids: Vec<ObjectId> = feedback.iter().map(|f| f.log_id).collect();
+    log_ids.sort();
+    log_ids.dedup();
+    let logs: HashMap<ObjectId, _> = data
+        .get_logs_by_ids(app_id, &log_ids)
+        .await?
+        .into_iter()
+        .filter_map(|log| Some((log.id?, log)))
+        .collect();
+    let keys: Vec<(ObjectId, i32)> = feedback.iter().map(|f| (f.log_id, f.version)).collect();
+    let versions: HashMap<(ObjectId, i32), _> = data
+        .get_rag_inferences(app_id, &keys)
+        .await?
+        .into_iter()
+        .map(|version| ((version.log_id, version.version), version))
+        .collect();
+
+    let entries: Vec<_> = feedback
+        .iter()
+        .map(|f| {
+            let mut entry = feedback_json(f);
+            entry["inference"] =
+                versions
+                    .get(&(f.log_id, f.version))
+                    .map_or(serde_json::Value::Null, |version| {
+                        serde_json::json!({
+                            "inference": version.inference,
+                            "schema_version": version.schema_version,
+                            "flagged": version.flagged,
+                            "model": version.metadata.model,
+                            "prompt_version": version.metadata.prompt_version,
+                            "created_at": version.created_at.to_rfc3339(),
+                        })
+                    });
+            entry["log"] = logs.get(&f.log_id).map_or(serde_json::Value::Null, |log| {
+                serde_json::json!({
+                    "error": log.error,
+                    "traceback": log.traceback,
+                    "frames": log.frames,
+                    "url": log.url,
+                    "method": log.method,
+                    "level": log.level,
+                    "environment": log.environment,
+                    "release": log.release,
+                    "fingerprint": log.fingerprint,
+                })
+            });
+            entry
+        })
+        .collect();
+
+    // A short page is the last one for now; workers come back later with the same cursor
+    let next_cursor = feedback
+        .last()
+        .map(|f| f.revision.to_hex())
+        .or_else(|| after.map(|after| after.to_hex()));
+    Ok(serde_json::json!({
+        "feedback": entries,
+        "next_cursor": next_cursor,
+        "has_more": feedback.len() as i64 == limit,
+    }))
+}
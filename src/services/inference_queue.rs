@@ -0,0 +1,379 @@
+use crate::db::MongoRepo;
+use crate::extractors::tenant::AuthenticatedApp;
+use crate::models::inference_job::{
+    ClaimJobsPayload, CompleteJobPayload, FailJobPayload, InferenceJob, JobStatus,
+};
+use crate::models::log::LogPayload;
+use crate::services::rag_inference_service::{self, RagInferenceError};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use chrono::{Duration, Utc};
+use mongodb::bson::{self, doc, oid::ObjectId};
+use std::env;
+use std::fmt;
+
+const DEFAULT_LEASE_SECS: i64 = 300;
+const MAX_LEASE_SECS: i64 = 3600;
+const DEFAULT_MAX_ATTEMPTS: i32 = 3;
+const DEFAULT_RETRY_DELAY_SECS: i64 = 30;
+/// Longest a failed job waits before it is retried.
+const MAX_RETRY_DELAY_SECS: i64 = 24 * 60 * 60;
+/// Most jobs one claim can lease.
+pub const MAX_CLAIM: i64 = 100;
+const MAX_WORKER_ID_LEN: usize = 128;
+const MAX_ERROR_LEN: usize = 2000;
+
+#[derive(Debug)]
+pub enum InferenceJobError {
+    InvalidWorkerId,
+    InvalidLeaseSecs,
+    InvalidRetryAfter,
+    InvalidInference(String), // The job stays leased so the worker can retry or fail it
+    NotFound,
+    LeaseLost,
+    Database,
+}
+
+impl fmt::Display for InferenceJobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InferenceJobError::InvalidWorkerId => write!(
+                f,
+                "worker_id must be between 1 and {} characters",
+                MAX_WORKER_ID_LEN
+            ),
+            InferenceJobError::InvalidLeaseSecs => {
+                write!(f, "lease_secs must be between 1 and {}", MAX_LEASE_SECS)
+            }
+            InferenceJobError::InvalidRetryAfter => write!(
+                f,
+                "retry_after_secs must be between 0 and {}",
+                MAX_RETRY_DELAY_SECS
+            ),
+            InferenceJobError::InvalidInference(reason) => {
+                write!(f, "Invalid RAG inference: {}", reason)
+            }
+            InferenceJobError::NotFound => write!(f, "Inference job not found"),
+            InferenceJobError::LeaseLost => write!(
+                f,
+                "The job is not leased to this worker anymore; its lease expired or it was completed"
+            ),
+            InferenceJobError::Database => write!(f, "Failed to update the inference job"),
+        }
+    }
+}
+
+impl ResponseError for InferenceJobError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            InferenceJobError::InvalidWorkerId
+            | InferenceJobError::InvalidLeaseSecs
+            | InferenceJobError::InvalidRetryAfter
+            | InferenceJobError::InvalidInference(_) => StatusCode::BAD_REQUEST,
+            InferenceJobError::NotFound => StatusCode::NOT_FOUND,
+            InferenceJobError::LeaseLost => StatusCode::CONFLICT,
+            InferenceJobError::Database => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+/// How long a claimed job stays with its worker without a heartbeat, from
+/// `INFERENCE_LEASE_SECS`.
+pub fn default_lease_secs() -> i64 {
+    env::var("INFERENCE_LEASE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| (1..=MAX_LEASE_SECS).contains(n))
+        .unwrap_or(DEFAULT_LEASE_SECS)
+}
+
+/// Leases a job gets before it is failed, from `INFERENCE_MAX_ATTEMPTS`.
+pub fn max_attempts() -> i32 {
+    env::var("INFERENCE_MAX_ATTEMPTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_ATTEMPTS)
+}
+
+/// Delay before the first retry of a failed job, doubled on each attempt, from
+/// `INFERENCE_RETRY_DELAY_SECS`.
+pub fn retry_delay_secs() -> i64 {
+    env::var("INFERENCE_RETRY_DELAY_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| (0..=MAX_RETRY_DELAY_SECS).contains(n))
+        .unwrap_or(DEFAULT_RETRY_DELAY_SECS)
+}
+
+fn validate_worker_id(worker_id: &str) -> Result<&str, InferenceJobError> {
+    let worker_id = worker_id.trim();
+    if worker_id.is_empty() || worker_id.len() > MAX_WORKER_ID_LEN {
+        return Err(InferenceJobError::InvalidWorkerId);
+    }
+    Ok(worker_id)
+}
+
+fn lease_duration(lease_secs: Option<i64>) -> Result<Duration, InferenceJobError> {
+    match lease_secs {
+        Some(secs) if !(1..=MAX_LEASE_SECS).contains(&secs) => {
+            Err(InferenceJobError::InvalidLeaseSecs)
+        }
+        Some(secs) => Ok(Duration::seconds(secs)),
+        None => Ok(Duration::seconds(default_lease_secs())),
+    }
+}
+
+pub fn job_json(job: &InferenceJob) -> serde_json::Value {
+    serde_json::json!({
+        "id": job.id.map(|id| id.to_hex()),
+        "application_id": job.application_id.to_hex(),
+        "log_id": job.log_id.to_hex(),
+        "status": job.status,
+        "attempts": job.attempts,
+        "max_attempts": job.max_attempts,
+        "available_at": job.available_at.to_rfc3339(),
+        "worker_id": job.worker_id,
+        "lease_until": job.lease_until.map(|at| at.to_rfc3339()),
+        "last_error": job.last_error,
+        "created_at": job.created_at.to_rfc3339(),
+        "updated_at": job.updated_at.to_rfc3339(),
+        "completed_at": job.completed_at.map(|at| at.to_rfc3339()),
+    })
+}
+
+/// Queues an inference job for every stored log. Failures are logged: the logs are stored
+/// and can still get an inference through `PUT /logs/{id}/rag-inference`.
+pub async fn enqueue(data: &MongoRepo, logs: &[&LogPayload]) {
+    let now = Utc::now();
+    let max_attempts = max_attempts();
+    let jobs: Vec<InferenceJob> = logs
+        .iter()
+        .filter_map(|log| {
+            Some(InferenceJob {
+                id: Some(ObjectId::new()),
+                organization_id: log.organization_id?,
+                application_id: log.application_id?,
+                log_id: log.id?,
+                status: JobStatus::Queued,
+                attempts: 0,
+                max_attempts,
+                available_at: now,
+                worker_id: None,
+                lease_until: None,
+                last_error: None,
+                created_at: now,
+                updated_at: now,
+                completed_at: None,
+            })
+        })
+        .collect();
+    let count = jobs.len();
+    if let Err(e) = data.enqueue_inference_jobs(jobs).await {
+        log::error!("Failed to queue {} inference jobs: {}", count, e);
+    }
+}
+
+/// Leases up to `limit` jobs of the application to a worker, each with the log to run on.
+/// Jobs whose log is gone (purged by retention) are failed instead.
+pub async fn claim(
+    data: &MongoRepo,
+    app_id: ObjectId,
+    payload: ClaimJobsPayload,
+) -> Result<Vec<serde_json::Value>, InferenceJobError> {
+    let worker_id = validate_worker_id(&payload.worker_id)?;
+    let lease = lease_duration(payload.lease_secs)?;
+    let limit = payload.limit.unwrap_or(1).clamp(1, MAX_CLAIM);
+
+    // Crashed workers' jobs on their last attempt are failed rather than leased again
+    data.fail_exhausted_inference_jobs(app_id)
+        .await
+        .map_err(|_| InferenceJobError::Database)?;
+
+    let mut jobs = Vec::new();
+    while (jobs.len() as i64) < limit {
+        match data
+            .claim_inference_job(app_id, worker_id, Utc::now() + lease)
+            .await
+        {
+            Ok(Some(job)) => jobs.push(job),
+            Ok(None) => break,
+            Err(_) => return Err(InferenceJobError::Database),
+        }
+    }
+    if jobs.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let log_ids: Vec<ObjectId> = jobs.iter().map(|job| job.log_id).collect();
+    let logs = data
+        .list_logs(
+            doc! { "_id": { "$in": log_ids }, "application_id": app_id },
+            jobs.len() as i64,
+        )
+        .await
+        .map_err(|_| InferenceJobError::Database)?;
+
+    let mut claimed = Vec::with_capacity(jobs.len());
+    for job in jobs {
+        let Some(log) = logs.iter().find(|log| log.id == Some(job.log_id)) else {
+            let update = finish_update(JobStatus::Failed, Some("Log no longer exists"));
+            if let Some(job_id) = job.id {
+                data.update_leased_inference_job(app_id, job_id, worker_id, update)
+                    .await
+                    .map_err(|_| InferenceJobError::Database)?;
+            }
+            continue;
+        };
+        let mut json = job_json(&job);
+        json["log"] = serde_json::to_value(log).unwrap_or_default();
+        claimed.push(json);
+    }
+    Ok(claimed)
+}
+
+/// Extends the lease of a job the worker holds.
+pub async fn heartbeat(
+    data: &MongoRepo,
+    app_id: ObjectId,
+    job_id: ObjectId,
+    worker_id: &str,
+    lease_secs: Option<i64>,
+) -> Result<InferenceJob, InferenceJobError> {
+    let worker_id = validate_worker_id(worker_id)?;
+    let lease = lease_duration(lease_secs)?;
+    let now = Utc::now();
+    let update = doc! {
+        "$set": {
+            "lease_until": (now + lease).to_rfc3339(),
+            "updated_at": now.to_rfc3339(),
+        }
+    };
+    leased_update(data, app_id, job_id, worker_id, update).await
+}
+
+/// Records the worker's inference as a new version of the log's inference and completes the
+/// job. The job fails instead if the log is gone.
+pub async fn complete(
+    data: &MongoRepo,
+    auth: &AuthenticatedApp,
+    job_id: ObjectId,
+    payload: CompleteJobPayload,
+) -> Result<InferenceJob, InferenceJobError> {
+    let app_id = auth.app_id();
+    let worker_id = validate_worker_id(&payload.worker_id)?;
+    let job = match data.get_inference_job(app_id, job_id).await {
+        Ok(Some(job)) => job,
+        Ok(None) => return Err(InferenceJobError::NotFound),
+        Err(_) => return Err(InferenceJobError::Database),
+    };
+    if job.status != JobStatus::Leased || job.worker_id.as_deref() != Some(worker_id) {
+        return Err(InferenceJobError::LeaseLost);
+    }
+
+    let author = rag_inference_service::author(&auth.access, Some(worker_id));
+    let recorded = rag_inference_service::record(
+        data,
+        auth,
+        job.log_id,
+        payload.rag_inference,
+        payload.metadata,
+        author,
+        Some(job_id),
+    )
+    .await;
+    let update = match recorded {
+        Ok(_) => finish_update(JobStatus::Completed, None),
+        Err(RagInferenceError::LogNotFound) => {
+            finish_update(JobStatus::Failed, Some("Log no longer exists"))
+        }
+        Err(RagInferenceError::Invalid(reason)) => {
+            return Err(InferenceJobError::InvalidInference(reason))
+        }
+        Err(_) => return Err(InferenceJobError::Database),
+    };
+    leased_update(data, app_id, job_id, worker_id, update).await
+}
+
+/// Records a failed attempt. The job is queued again after a backoff unless it used up its
+/// attempts or the worker says retrying is pointless.
+pub async fn fail(
+    data: &MongoRepo,
+    app_id: ObjectId,
+    job_id: ObjectId,
+    payload: FailJobPayload,
+) -> Result<InferenceJob, InferenceJobError> {
+    let worker_id = validate_worker_id(&payload.worker_id)?;
+    let job = match data.get_inference_job(app_id, job_id).await {
+        Ok(Some(job)) => job,
+        Ok(None) => return Err(InferenceJobError::NotFound),
+        Err(_) => return Err(InferenceJobError::Database),
+    };
+    let error: String = payload.error.chars().take(MAX_ERROR_LEN).collect();
+
+    let update = if payload.retry && job.attempts < job.max_attempts {
+        let delay = match payload.retry_after_secs {
+            Some(secs) if !(0..=MAX_RETRY_DELAY_SECS).contains(&secs) => {
+                return Err(InferenceJobError::InvalidRetryAfter)
+            }
+            Some(secs) => secs,
+            None => retry_delay_secs()
+                .saturating_mul(1 << (job.attempts - 1).clamp(0, 10))
+                .min(MAX_RETRY_DELAY_SECS),
+        };
+        let now = Utc::now();
+        let available_at = Duration::try_seconds(delay)
+            .and_then(|delay| now.checked_add_signed(delay))
+            .ok_or(InferenceJobError::InvalidRetryAfter)?;
+        doc! {
+            "$set": {
+                "status": JobStatus::Queued.as_str(),
+                "available_at": available_at.to_rfc3339(),
+                "last_error": error,
+                "updated_at": now.to_rfc3339(),
+            },
+            "$unset": { "lease_until": "" },
+        }
+    } else {
+        finish_update(JobStatus::Failed, Some(&error))
+    };
+    leased_update(data, app_id, job_id, worker_id, update).await
+}
+
+fn finish_update(status: JobStatus, error: Option<&str>) -> bson::Document {
+    let now = Utc::now().to_rfc3339();
+    let mut set = doc! { "status": status.as_str(), "updated_at": &now };
+    if status == JobStatus::Completed {
+        set.insert("completed_at", &now);
+    }
+    if let Some(error) = error {
+        set.insert("last_error", error);
+    }
+    doc! { "$set": set, "$unset": { "lease_until": "" } }
+}
+
+async fn leased_update(
+    data: &MongoRepo,
+    app_id: ObjectId,
+    job_id: ObjectId,
+    worker_id: &str,
+    update: bson::Document,
+) -> Result<InferenceJob, InferenceJobError> {
+    match data
+        .update_leased_inference_job(app_id, job_id, worker_id, update)
+        .await
+    {
+        Ok(Some(job)) => Ok(job),
+        Ok(None) => match data.get_inference_job(app_id, job_id).await {
+            Ok(Some(_)) => Err(InferenceJobError::LeaseLost),
+            Ok(None) => Err(InferenceJobError::NotFound),
+            Err(_) => Err(InferenceJobError::Database),
+        },
+        Err(_) => Err(InferenceJobError::Database),
+    }
+}
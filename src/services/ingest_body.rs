@@ -0,0 +1,303 @@
+use actix_web::{
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use futures_util::StreamExt;
+use std::io::Read;
+use std::{env, fmt};
+
+const DEFAULT_MAX_COMPRESSED_BYTES: usize = 1024 * 1024;
+
+/// Why an ingest body was rejected.
+#[derive(Debug)]
+pub enum IngestBodyError {
+    TooLarge(usize),
+    DecompressedTooLarge(usize),
+    UnsupportedEncoding(String),
+    InvalidEncoding(&'static str),
+    Read,
+}
+
+impl fmt::Display for IngestBodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IngestBodyError::TooLarge(limit) => {
+                write!(f, "Request body exceeds the limit of {} bytes", limit)
+            }
+            IngestBodyError::DecompressedTooLarge(limit) => {
+                write!(f, "Decompressed body exceeds the limit of {} bytes", limit)
+            }
+            IngestBodyError::UnsupportedEncoding(encoding) => {
+                write!(f, "Unsupported Content-Encoding `{}`", encoding)
+            }
+            IngestBodyError::InvalidEncoding(encoding) => {
+                write!(f, "Body is not valid {} data", encoding)
+            }
+            IngestBodyError::Read => write!(f, "Failed to read request body"),
+        }
+    }
+}
+
+impl ResponseError for IngestBodyError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            IngestBodyError::TooLarge(_) | IngestBodyError::DecompressedTooLarge(_) => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+            IngestBodyError::UnsupportedEncoding(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            IngestBodyError::InvalidEncoding(_) | IngestBodyError::Read => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Encoding {
+    Gzip,
+    Deflate,
+    Zstd,
+}
+
+impl Encoding {
+    fn name(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Zstd => "zstd",
+        }
+    }
+}
+
+/// Limit on the bytes received for a compressed body, from `INGEST_MAX_COMPRESSED_BYTES`.
+pub fn max_compressed_bytes() -> usize {
+    env::var("INGEST_MAX_COMPRESSED_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_MAX_COMPRESSED_BYTES)
+}
+
+/// Reads an ingest body and undoes its `Content-Encoding` (gzip, deflate or zstd).
+///
+/// `max_bytes` limits the decoded body. Compressed bodies are additionally limited to
+/// `max_compressed_bytes()` on the wire, and decoding stops as soon as the output passes
+/// `max_bytes`, so a small zip bomb is rejected without being inflated in memory.
+pub async fn read_ingest_body(
+    req: &HttpRequest,
+    payload: web::Payload,
+    max_bytes: usize,
+) -> Result<web::Bytes, IngestBodyError> {
+    let encodings = content_encodings(req)?;
+    let wire_limit = if encodings.is_empty() {
+        max_bytes
+    } else {
+        max_compressed_bytes()
+    };
+    let body = read_limited(payload, wire_limit).await?;
+    if encodings.is_empty() {
+        return Ok(body);
+    }
+
+    // Decoding is CPU bound, keep it off the async workers
+    web::block(move || {
+        let mut body = body.to_vec();
+        // Encodings are listed in the order they were applied
+        for encoding in encodings.into_iter().rev() {
+            body = decode(&body, encoding, max_bytes)?;
+        }
+        Ok(web::Bytes::from(body))
+    })
+    .await
+    .map_err(|_| IngestBodyError::Read)?
+}
+
+fn content_encodings(req: &HttpRequest) -> Result<Vec<Encoding>, IngestBodyError> {
+    let Some(value) = req.headers().get(header::CONTENT_ENCODING) else {
+        return Ok(Vec::new());
+    };
+    let value = value
+        .to_str()
+        .map_err(|_| IngestBodyError::UnsupportedEncoding("<invalid>".to_string()))?;
+
+    let mut encodings = Vec::new();
+    for encoding in value.split(',').map(|e| e.trim().to_ascii_lowercase()) {
+        match encoding.as_str() {
+            "" | "identity" => {}
+            "gzip" | "x-gzip" => encodings.push(Encoding::Gzip),
+            "deflate" => encodings.push(Encoding::Deflate),
+            "zstd" => encodings.push(Encoding::Zstd),
+            _ => return Err(IngestBodyError::UnsupportedEncoding(encoding)),
+        }
+    }
+    Ok(encodings)
+}
+
+async fn read_limited(
+    mut payload: web::Payload,
+    limit: usize,
+) -> Result<web::Bytes, IngestBodyError> {
+    let mut bytes = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|_| IngestBodyError::Read)?;
+        if bytes.len() + chunk.len() > limit {
+            return Err(IngestBodyError::TooLarge(limit));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes.freeze())
+}
+
+fn decode(body: &[u8], encoding: Encoding, limit: usize) -> Result<Vec<u8>, IngestBodyError> {
+    let decoder: Box<dyn Read + '_> = match encoding {
+        Encoding::Gzip => Box::new(flate2::read::MultiGzDecoder::new(body)),
+        // HTTP `deflate` is zlib-wrapped, but some clients send raw deflate streams
+        Encoding::Deflate if is_zlib(body) => Box::new(flate2::read::ZlibDecoder::new(body)),
+        Encoding::Deflate => Box::new(flate2::read::DeflateDecoder::new(body)),
+        Encoding::Zstd => Box::new(
+            zstd::stream::read::Decoder::new(body)
+                .map_err(|_| IngestBodyError::InvalidEncoding(encoding.name()))?,
+        ),
+    };
+
+    let mut decoded = Vec::new();
+    decoder
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decoded)
+        .map_err(|_| IngestBodyError::InvalidEncoding(encoding.name()))?;
+    if decoded.len() > limit {
+        return Err(IngestBodyError::DecompressedTooLarge(limit));
+    }
+    Ok(decoded)
+}
+
+/// A zlib header is two bytes: CM = 8 in the low nibble and a checksum divisible by 31.
+fn is_zlib(body: &[u8]) -> bool {
+    body.len() >= 2
+        && body[0] & 0x0f == 8
+        && (u16::from(body[0]) << 8 | u16::from(body[1])) % 31 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test::TestRequest, FromRequest};
+    use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    const BODY: &[u8] =
+        br#"{"error": "KeyError: 'id'", "traceback": "", "url": "/", "method": "GET"}"#;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn raw_deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zstd(data: &[u8]) -> Vec<u8> {
+        zstd::stream::encode_all(data, 3).unwrap()
+    }
+
+    async fn read(
+        encoding: &str,
+        body: Vec<u8>,
+        max_bytes: usize,
+    ) -> Result<web::Bytes, IngestBodyError> {
+        let (req, mut payload) = TestRequest::default()
+            .insert_header((header::CONTENT_ENCODING, encoding))
+            .set_payload(body)
+            .to_http_parts();
+        let payload = web::Payload::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        read_ingest_body(&req, payload, max_bytes).await
+    }
+
+    #[test]
+    fn round_trips_every_encoding() {
+        assert_eq!(decode(&gzip(BODY), Encoding::Gzip, 1024).unwrap(), BODY);
+        assert_eq!(decode(&zlib(BODY), Encoding::Deflate, 1024).unwrap(), BODY);
+        assert_eq!(
+            decode(&raw_deflate(BODY), Encoding::Deflate, 1024).unwrap(),
+            BODY
+        );
+        assert_eq!(decode(&zstd(BODY), Encoding::Zstd, 1024).unwrap(), BODY);
+    }
+
+    #[test]
+    fn tells_zlib_from_raw_deflate() {
+        assert!(is_zlib(&zlib(BODY)));
+        assert!(!is_zlib(&raw_deflate(BODY)));
+        assert!(!is_zlib(&[0x78]));
+    }
+
+    #[test]
+    fn stops_a_bomb_just_over_the_limit() {
+        let limit = 64 * 1024;
+        let bomb = vec![0u8; limit + 1];
+
+        for (encoding, body) in [
+            (Encoding::Gzip, gzip(&bomb)),
+            (Encoding::Deflate, zlib(&bomb)),
+            (Encoding::Deflate, raw_deflate(&bomb)),
+            (Encoding::Zstd, zstd(&bomb)),
+        ] {
+            assert!(body.len() < 1024);
+            let error = decode(&body, encoding, limit).unwrap_err();
+            assert!(matches!(error, IngestBodyError::DecompressedTooLarge(l) if l == limit));
+            assert_eq!(error.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+            assert_eq!(decode(&body, encoding, limit + 1).unwrap().len(), limit + 1);
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_data() {
+        let error = decode(b"not gzip", Encoding::Gzip, 1024).unwrap_err();
+        assert!(matches!(error, IngestBodyError::InvalidEncoding("gzip")));
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn decodes_stacked_encodings_in_reverse() {
+        // Applied gzip first, then zstd
+        let body = zstd(&gzip(BODY));
+        assert_eq!(read("gzip, zstd", body, 1024).await.unwrap(), BODY);
+
+        let body = gzip(&zstd(BODY));
+        assert!(read("gzip, zstd", body, 1024).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn passes_identity_bodies_through() {
+        assert_eq!(read("identity", BODY.to_vec(), 1024).await.unwrap(), BODY);
+        assert!(matches!(
+            read("identity", BODY.to_vec(), 8).await,
+            Err(IngestBodyError::TooLarge(8))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn rejects_unknown_encodings() {
+        let error = read("gzip, br", gzip(BODY), 1024).await.unwrap_err();
+
+        assert!(matches!(&error, IngestBodyError::UnsupportedEncoding(e) if e == "br"));
+        assert_eq!(error.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+}
@@ -0,0 +1,229 @@
+use crate::db::{self, MongoRepo};
+use crate::models::invitation::{Invitation, InvitationStatus};
+use crate::models::membership::{Membership, OrgRole};
+use crate::models::user::User;
+use crate::services::email_service::EmailService;
+use crate::services::jwt_service::{self, InvitationClaims};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use chrono::{DateTime, Duration, Utc};
+use mongodb::bson::oid::ObjectId;
+use rand::RngCore;
+use std::{env, fmt, fs};
+
+/// Reasons an invitation cannot be accepted.
+#[derive(Debug)]
+pub enum InvitationError {
+    InvalidToken,
+    NotFound,
+    Expired,
+    NoLongerValid,
+    EmailMismatch,
+    AlreadyMember,
+    Database,
+}
+
+impl fmt::Display for InvitationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvitationError::InvalidToken => write!(f, "Invalid invitation token"),
+            InvitationError::NotFound => write!(f, "Invitation not found"),
+            InvitationError::Expired => write!(f, "Invitation has expired"),
+            InvitationError::NoLongerValid => {
+                write!(f, "Invitation was revoked, already used or resent")
+            }
+            InvitationError::EmailMismatch => {
+                write!(f, "Invitation was sent to a different email address")
+            }
+            InvitationError::AlreadyMember => write!(f, "Already a member of this organization"),
+            InvitationError::Database => write!(f, "Failed to accept invitation"),
+        }
+    }
+}
+
+impl ResponseError for InvitationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            InvitationError::InvalidToken => StatusCode::BAD_REQUEST,
+            InvitationError::NotFound => StatusCode::NOT_FOUND,
+            InvitationError::Expired | InvitationError::NoLongerValid => StatusCode::GONE,
+            InvitationError::EmailMismatch => StatusCode::FORBIDDEN,
+            InvitationError::AlreadyMember => StatusCode::CONFLICT,
+            InvitationError::Database => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+/// How long an invitation stays valid, configurable through `INVITATION_TTL_HOURS`.
+pub fn invitation_ttl() -> Duration {
+    let hours = env::var("INVITATION_TTL_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|h| *h > 0)
+        .unwrap_or(72);
+    Duration::hours(hours)
+}
+
+pub fn generate_nonce() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Emails are matched case-insensitively, so invitations store them normalized.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+pub fn new_invitation(
+    org_id: ObjectId,
+    email: &str,
+    role: OrgRole,
+    invited_by: Option<ObjectId>,
+) -> Invitation {
+    let now = Utc::now();
+    Invitation {
+        id: Some(ObjectId::new()),
+        organization_id: org_id,
+        email: normalize_email(email),
+        role,
+        status: InvitationStatus::Pending,
+        nonce: generate_nonce(),
+        invited_by,
+        created_at: now,
+        expires_at: now + invitation_ttl(),
+        accepted_by: None,
+        accepted_at: None,
+    }
+}
+
+/// Signs a token for the invitation's current nonce and expiry.
+pub fn issue_token(invitation: &Invitation) -> String {
+    jwt_service::generate_invitation_token(&InvitationClaims {
+        sub: invitation.id.unwrap().to_hex(),
+        org: invitation.organization_id.to_hex(),
+        email: invitation.email.clone(),
+        nonce: invitation.nonce.clone(),
+        exp: invitation.expires_at.timestamp() as usize,
+        aud: jwt_service::INVITATION_AUDIENCE.to_string(),
+    })
+}
+
+/// Sends the invitation email with an accept link to the dashboard (`DASHBOARD_URL`).
+pub async fn send_invitation_email(
+    invitation: &Invitation,
+    org_name: &str,
+    token: &str,
+) -> Result<(), String> {
+    let dashboard_url =
+        env::var("DASHBOARD_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+    let invite_link = format!(
+        "{}/invitations/accept?token={}",
+        dashboard_url.trim_end_matches('/'),
+        token
+    );
+
+    let base_path = env::current_dir().map_err(|e| e.to_string())?;
+    let template_path = base_path.join("src/templates/invitation_template.html");
+    let email_body = fs::read_to_string(template_path)
+        .map_err(|e| format!("Failed to read invitation email template: {}", e))?
+        .replace("{{ORG_NAME}}", &escape_html(org_name))
+        .replace("{{ROLE}}", invitation.role.as_str())
+        .replace("{{INVITE_LINK}}", &invite_link)
+        .replace("{{EXPIRES_AT}}", &format_expiry(invitation.expires_at));
+
+    EmailService::new()
+        .send_email(
+            &invitation.email,
+            &format!("You have been invited to join {}", org_name),
+            &email_body,
+        )
+        .await
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_expiry(expires_at: DateTime<Utc>) -> String {
+    expires_at.format("%B %-d, %Y %H:%M UTC").to_string()
+}
+
+/// Accepts an invitation token on behalf of `user` and creates the membership.
+pub async fn accept_invitation(
+    data: &MongoRepo,
+    token: &str,
+    user: &User,
+) -> Result<Membership, InvitationError> {
+    let claims =
+        jwt_service::decode_invitation_token(token).map_err(|_| InvitationError::InvalidToken)?;
+    let invitation_id =
+        ObjectId::parse_str(&claims.sub).map_err(|_| InvitationError::InvalidToken)?;
+
+    let invitation = data
+        .get_invitation_by_id(invitation_id)
+        .await
+        .map_err(|_| InvitationError::Database)?
+        .ok_or(InvitationError::NotFound)?;
+
+    if invitation.status != InvitationStatus::Pending || invitation.nonce != claims.nonce {
+        return Err(InvitationError::NoLongerValid);
+    }
+    if invitation.is_expired() {
+        return Err(InvitationError::Expired);
+    }
+    if normalize_email(&user.email) != invitation.email {
+        return Err(InvitationError::EmailMismatch);
+    }
+
+    let user_id = user.id.ok_or(InvitationError::Database)?;
+    let existing = data
+        .get_membership(invitation.organization_id, user_id)
+        .await
+        .map_err(|_| InvitationError::Database)?;
+    if existing.is_some() {
+        return Err(InvitationError::AlreadyMember);
+    }
+
+    // A unique index on (organization_id, user_id) settles concurrent accepts
+    let membership = Membership {
+        id: Some(ObjectId::new()),
+        organization_id: invitation.organization_id,
+        user_id,
+        role: invitation.role,
+        created_at: Utc::now(),
+    };
+    data.create_membership(membership.clone())
+        .await
+        .map_err(|e| {
+            if db::is_duplicate_key(&e) {
+                InvitationError::AlreadyMember
+            } else {
+                InvitationError::Database
+            }
+        })?;
+
+    // The invitation may have been revoked or resent meanwhile; undo the membership if so
+    let claimed = data
+        .mark_invitation_accepted(invitation_id, &invitation.nonce, user_id)
+        .await;
+    if !matches!(claimed, Ok(true)) {
+        let _ = data
+            .delete_membership(invitation.organization_id, user_id)
+            .await;
+        return Err(match claimed {
+            Ok(_) => InvitationError::NoLongerValid,
+            Err(_) => InvitationError::Database,
+        });
+    }
+    Ok(membership)
+}
@@ -0,0 +1,52 @@
+use crate::db::MongoRepo;
+use crate::models::issue::{Issue, IssueStatus};
+use crate::websocket::server::WebSocketServer;
+use chrono::Utc;
+
+/// Applies the issue workflow to `issue` after it received an event from `release`.
+///
+/// - Resolved issues reopen as regressions. Issues resolved "in next release" only do so for
+///   events from a different release; events without a release cannot tell and are ignored.
+/// - Ignored issues reopen once the event count or date they were ignored until is reached.
+/// - Muted and unresolved issues are left alone.
+pub async fn apply_event(
+    data: &MongoRepo,
+    websocket_server: &WebSocketServer,
+    issue: &Issue,
+    release: Option<&str>,
+) -> Result<(), mongodb::error::Error> {
+    let Some(issue_id) = issue.id else {
+        return Ok(());
+    };
+
+    match issue.status {
+        IssueStatus::Resolved => {
+            if let Some(resolved_in) = issue.resolved_in_release.as_deref() {
+                if release.is_none_or(|r| r == resolved_in) {
+                    return Ok(());
+                }
+            }
+            if data
+                .reopen_issue(issue_id, IssueStatus::Resolved, true)
+                .await?
+            {
+                log::info!("Issue {} regressed", issue_id);
+                websocket_server
+                    .push_issue_regression(issue.organization_id, issue.application_id, issue_id)
+                    .await;
+            }
+        }
+        IssueStatus::Ignored => {
+            let count_reached = issue
+                .ignore_until_count
+                .is_some_and(|count| issue.event_count >= count);
+            let date_reached = issue.ignore_until.is_some_and(|until| until <= Utc::now());
+            if count_reached || date_reached {
+                data.reopen_issue(issue_id, IssueStatus::Ignored, false)
+                    .await?;
+            }
+        }
+        IssueStatus::Unresolved | IssueStatus::Muted => {}
+    }
+    Ok(())
+}
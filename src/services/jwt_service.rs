@@ -1,12 +1,24 @@
+use crate::middlewares::auth_middleware::Claims;
 use chrono::{Duration, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use std::env;
 
+/// Audience of session tokens. Session and invitation tokens share `JWT_SECRET`, so each kind
+/// names its audience and is only accepted where that audience is expected.
+pub const SESSION_AUDIENCE: &str = "cadmium-session";
+/// Audience of organization invitation tokens.
+pub const INVITATION_AUDIENCE: &str = "cadmium-invitation";
+
+/// Claims of an organization invitation token. `sub` is the invitation id.
 #[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    sub: String,
-    exp: usize,
+pub struct InvitationClaims {
+    pub sub: String,
+    pub org: String,
+    pub email: String,
+    pub nonce: String,
+    pub exp: usize,
+    pub aud: String, // `INVITATION_AUDIENCE`
 }
 
 pub fn generate_jwt(email: &str) -> String {
@@ -15,6 +27,7 @@ pub fn generate_jwt(email: &str) -> String {
     let claims = Claims {
         sub: email.to_string(),
         exp: expiration.timestamp() as usize,
+        aud: SESSION_AUDIENCE.to_string(),
     };
     encode(
         &Header::default(),
@@ -24,14 +37,44 @@ pub fn generate_jwt(email: &str) -> String {
     .unwrap()
 }
 
-pub fn validate_jwt(token: &str) -> Result<String, String> {
+/// Decodes and validates a session token, returning its claims.
+pub fn decode_claims(token: &str) -> Result<Claims, String> {
     let secret = env::var("JWT_SECRET").map_err(|_| "JWT_SECRET not set".to_string())?;
-    let validation = Validation::default();
+    let mut validation = Validation::default();
+    validation.set_audience(&[SESSION_AUDIENCE]);
     let decoded = decode::<Claims>(
         token,
         &DecodingKey::from_secret(secret.as_ref()),
         &validation,
     )
     .map_err(|_| "Invalid token".to_string())?;
-    Ok(decoded.claims.sub)
+    Ok(decoded.claims)
+}
+
+pub fn validate_jwt(token: &str) -> Result<String, String> {
+    decode_claims(token).map(|claims| claims.sub)
+}
+
+pub fn generate_invitation_token(claims: &InvitationClaims) -> String {
+    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    encode(
+        &Header::default(),
+        claims,
+        &EncodingKey::from_secret(secret.as_ref()),
+    )
+    .unwrap()
+}
+
+/// Decodes and validates an invitation token, including its expiry.
+pub fn decode_invitation_token(token: &str) -> Result<InvitationClaims, String> {
+    let secret = env::var("JWT_SECRET").map_err(|_| "JWT_SECRET not set".to_string())?;
+    let mut validation = Validation::default();
+    validation.set_audience(&[INVITATION_AUDIENCE]);
+    let decoded = decode::<InvitationClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_ref()),
+        &validation,
+    )
+    .map_err(|_| "Invalid invitation token".to_string())?;
+    Ok(decoded.claims)
 }
@@ -0,0 +1,616 @@
+use crate::models::log::LogLevel;
+use crate::services::log_search;
+use chrono::{DateTime, Duration, Utc};
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document, Regex};
+use std::fmt;
+
+/// A parse or compile error, `position` being the character offset it refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl QueryError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        QueryError {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub position: usize,
+    pub negated: bool,
+    pub kind: TermKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TermKind {
+    Field(FieldFilter),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldFilter {
+    Level(Comparison, LogLevel),
+    Environment(Pattern),
+    Release(Pattern),
+    Method(String),
+    Url(Pattern),
+    ServerName(Pattern),
+    Tag(Pattern), // `tag:region:eu`
+    User(String), // `context.user.id`
+    Issue(ObjectId),
+    Since(TimeBound),
+    Until(TimeBound),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+/// A string value; `*` matches any run of characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    Exact(String),
+    Prefix(String), // Only a trailing `*`
+    Glob(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBound {
+    Ago(Duration), // `24h`, `30m`, `7d`
+    At(DateTime<Utc>),
+}
+
+const LEVELS: [LogLevel; 5] = [
+    LogLevel::Debug,
+    LogLevel::Info,
+    LogLevel::Warning,
+    LogLevel::Error,
+    LogLevel::Fatal,
+];
+
+/// Parses a search such as `level:error env:prod url:/api/* "KeyError" -method:GET since:24h`.
+///
+/// A query is a list of terms that must all match. A term is `field:value` or free text, and a
+/// leading `-` negates it. Values and text containing spaces are quoted (`"connection reset"`).
+/// Free text is searched in `error` and `traceback`.
+pub fn parse(input: &str) -> Result<Query, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut terms = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let position = i;
+        let negated = chars[i] == '-';
+        if negated {
+            i += 1;
+            if i == chars.len() || chars[i].is_whitespace() {
+                return Err(QueryError::new(position, "Expected a term after `-`"));
+            }
+        }
+
+        let kind = if chars[i] == '"' {
+            let (text, end) = read_quoted(&chars, i)?;
+            i = end;
+            TermKind::Text(text)
+        } else {
+            let start = i;
+            while i < chars.len() && !chars[i].is_whitespace() && chars[i] != ':' {
+                if chars[i] == '"' {
+                    return Err(QueryError::new(i, "Unexpected `\"` inside a word"));
+                }
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            if i < chars.len() && chars[i] == ':' {
+                let value_position = i + 1;
+                let (value, end) = if chars.get(value_position) == Some(&'"') {
+                    read_quoted(&chars, value_position)?
+                } else {
+                    let mut end = value_position;
+                    while end < chars.len() && !chars[end].is_whitespace() {
+                        end += 1;
+                    }
+                    (chars[value_position..end].iter().collect(), end)
+                };
+                i = end;
+                if value.is_empty() {
+                    return Err(QueryError::new(
+                        value_position,
+                        format!("Expected a value for `{}`", word),
+                    ));
+                }
+                TermKind::Field(parse_field(&word, start, &value, value_position)?)
+            } else {
+                TermKind::Text(word)
+            }
+        };
+
+        if negated
+            && matches!(
+                kind,
+                TermKind::Field(FieldFilter::Since(_) | FieldFilter::Until(_))
+            )
+        {
+            return Err(QueryError::new(position, "Time bounds cannot be negated"));
+        }
+        terms.push(Term {
+            position,
+            negated,
+            kind,
+        });
+    }
+
+    Ok(Query { terms })
+}
+
+/// Reads the string starting with the quote at `start`, returning it unescaped and the index
+/// after the closing quote. `\"` and `\\` are the only escapes.
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize), QueryError> {
+    let mut value = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '"' => return Ok((value, i + 1)),
+            '\\' if matches!(chars.get(i + 1), Some('"' | '\\')) => {
+                value.push(chars[i + 1]);
+                i += 2;
+            }
+            c => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err(QueryError::new(start, "Unterminated quote"))
+}
+
+fn parse_field(
+    field: &str,
+    field_position: usize,
+    value: &str,
+    value_position: usize,
+) -> Result<FieldFilter, QueryError> {
+    let filter = match field.to_ascii_lowercase().as_str() {
+        "level" => {
+            let (comparison, level) = match value.char_indices().find(|(_, c)| c.is_alphabetic()) {
+                Some((0, _)) => (Comparison::Eq, value),
+                Some((n, _)) => {
+                    let comparison = match &value[..n] {
+                        ">" => Comparison::Gt,
+                        ">=" => Comparison::Gte,
+                        "<" => Comparison::Lt,
+                        "<=" => Comparison::Lte,
+                        other => {
+                            return Err(QueryError::new(
+                                value_position,
+                                format!("Unknown comparison `{}`", other),
+                            ))
+                        }
+                    };
+                    (comparison, &value[n..])
+                }
+                None => (Comparison::Eq, value),
+            };
+            let level = level
+                .parse()
+                .map_err(|message| QueryError::new(value_position, message))?;
+            FieldFilter::Level(comparison, level)
+        }
+        "env" | "environment" => FieldFilter::Environment(pattern(value)),
+        "release" => FieldFilter::Release(pattern(value)),
+        "method" => FieldFilter::Method(value.to_ascii_uppercase()),
+        "url" => FieldFilter::Url(pattern(value)),
+        "server" | "server_name" => FieldFilter::ServerName(pattern(value)),
+        "tag" | "tags" => FieldFilter::Tag(pattern(value)),
+        "user" => FieldFilter::User(value.to_string()),
+        "issue" => FieldFilter::Issue(
+            ObjectId::parse_str(value)
+                .map_err(|_| QueryError::new(value_position, "Invalid issue ID"))?,
+        ),
+        "since" => FieldFilter::Since(time_bound(value, value_position)?),
+        "until" => FieldFilter::Until(time_bound(value, value_position)?),
+        _ => {
+            return Err(QueryError::new(
+                field_position,
+                format!(
+                    "Unknown field `{}`; quote the term to search for it as text",
+                    field
+                ),
+            ))
+        }
+    };
+    Ok(filter)
+}
+
+fn pattern(value: &str) -> Pattern {
+    match value.strip_suffix('*') {
+        Some(prefix) if !prefix.contains('*') => Pattern::Prefix(prefix.to_string()),
+        _ if value.contains('*') => Pattern::Glob(value.to_string()),
+        _ => Pattern::Exact(value.to_string()),
+    }
+}
+
+/// `30s`, `15m`, `24h`, `7d`, `2w` ago, or an RFC 3339 time.
+fn time_bound(value: &str, position: usize) -> Result<TimeBound, QueryError> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Ok(TimeBound::At(at.with_timezone(&Utc)));
+    }
+
+    let split = value.len() - value.chars().last().map_or(0, char::len_utf8);
+    let amount: i64 = value[..split].parse().map_err(|_| {
+        QueryError::new(
+            position,
+            "Expected a duration such as `24h` or an RFC 3339 time",
+        )
+    })?;
+    let duration = match &value[split..] {
+        "s" => Duration::try_seconds(amount),
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => {
+            return Err(QueryError::new(
+                position + split,
+                "Expected a unit: s, m, h, d or w",
+            ))
+        }
+    };
+    duration
+        .filter(|d| *d >= Duration::zero())
+        .map(TimeBound::Ago)
+        .ok_or_else(|| QueryError::new(position, "Duration out of range"))
+}
+
+/// A query compiled to Mongo. Free text is kept apart because a filter can hold only one
+/// top-level `$text`.
+#[derive(Debug, Default)]
+pub struct CompiledQuery {
+    pub clauses: Vec<Document>,
+    pub text: Option<String>,
+}
+
+/// Compiles a query, resolving relative times against `now`.
+pub fn compile(query: &Query, now: DateTime<Utc>) -> Result<CompiledQuery, QueryError> {
+    let mut compiled = CompiledQuery::default();
+    let mut phrases = Vec::new();
+
+    for term in &query.terms {
+        let clause = match &term.kind {
+            // Quoted so that all text terms must match rather than any of them
+            TermKind::Text(text) if !term.negated => {
+                phrases.push(format!("\"{}\"", text.replace('"', " ")));
+                continue;
+            }
+            // Negated terms are not supported inside `$text`'s phrases, a regex is exact
+            TermKind::Text(text) => {
+                let text = Regex {
+                    pattern: log_search::escape_regex(text),
+                    options: "i".to_string(),
+                };
+                doc! { "$or": [ { "error": text.clone() }, { "traceback": text } ] }
+            }
+            TermKind::Field(filter) => compile_field(filter, term.position, now)?,
+        };
+        compiled.clauses.push(if term.negated {
+            doc! { "$nor": [clause] }
+        } else {
+            clause
+        });
+    }
+
+    if !phrases.is_empty() {
+        compiled.text = Some(phrases.join(" "));
+    }
+    Ok(compiled)
+}
+
+fn compile_field(
+    filter: &FieldFilter,
+    position: usize,
+    now: DateTime<Utc>,
+) -> Result<Document, QueryError> {
+    let clause = match filter {
+        FieldFilter::Level(comparison, level) => {
+            let levels: Vec<&str> = LEVELS
+                .iter()
+                .filter(|l| match comparison {
+                    Comparison::Eq => *l == level,
+                    Comparison::Gt => *l > level,
+                    Comparison::Gte => *l >= level,
+                    Comparison::Lt => *l < level,
+                    Comparison::Lte => *l <= level,
+                })
+                .map(LogLevel::as_str)
+                .collect();
+            doc! { "level": { "$in": levels } }
+        }
+        FieldFilter::Environment(value) => doc! { "environment": pattern_bson(value) },
+        FieldFilter::Release(value) => doc! { "release": pattern_bson(value) },
+        FieldFilter::Method(method) => doc! { "method": method },
+        FieldFilter::Url(value) => doc! { "url": pattern_bson(value) },
+        FieldFilter::ServerName(value) => doc! { "server_name": pattern_bson(value) },
+        FieldFilter::Tag(value) => doc! { "tags": pattern_bson(value) },
+        // Context values are free-form JSON, so numeric ids may be stored as numbers
+        FieldFilter::User(user) => match user.parse::<i64>() {
+            Ok(n) => doc! { "context.user.id": { "$in": [Bson::String(user.clone()), n] } },
+            Err(_) => doc! { "context.user.id": user },
+        },
+        FieldFilter::Issue(issue_id) => doc! { "issue_id": issue_id },
+        FieldFilter::Since(bound) => doc! { "_id": { "$gte": time_id(bound, position, now)? } },
+        FieldFilter::Until(bound) => doc! { "_id": { "$lt": time_id(bound, position, now)? } },
+    };
+    Ok(clause)
+}
+
+fn pattern_bson(value: &Pattern) -> Bson {
+    let pattern = match value {
+        Pattern::Exact(value) => return Bson::String(value.clone()),
+        // Anchored without a trailing `.*` so an index can bound the scan
+        Pattern::Prefix(prefix) => format!("^{}", log_search::escape_regex(prefix)),
+        Pattern::Glob(glob) => {
+            let parts: Vec<String> = glob.split('*').map(log_search::escape_regex).collect();
+            format!("^{}$", parts.join(".*"))
+        }
+    };
+    Bson::RegularExpression(Regex {
+        pattern,
+        options: String::new(),
+    })
+}
+
+impl TimeBound {
+    pub fn resolve(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            TimeBound::Ago(duration) => now.checked_sub_signed(*duration),
+            TimeBound::At(at) => Some(*at),
+        }
+    }
+}
+
+/// The range selected by the `since` and `until` terms of a query, the narrowest one if a
+/// bound is given more than once.
+pub fn time_range(
+    query: &Query,
+    now: DateTime<Utc>,
+) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    let (mut since, mut until) = (None, None);
+    for term in &query.terms {
+        match &term.kind {
+            TermKind::Field(FieldFilter::Since(bound)) => {
+                since = since.max(bound.resolve(now));
+            }
+            TermKind::Field(FieldFilter::Until(bound)) => {
+                if let Some(at) = bound.resolve(now) {
+                    until = Some(until.map_or(at, |until: DateTime<Utc>| until.min(at)));
+                }
+            }
+            _ => {}
+        }
+    }
+    (since, until)
+}
+
+fn time_id(bound: &TimeBound, position: usize, now: DateTime<Utc>) -> Result<ObjectId, QueryError> {
+    bound
+        .resolve(now)
+        .and_then(|at| log_search::id_at(at.timestamp()))
+        .ok_or_else(|| QueryError::new(position, "Time must be between 1970 and 2106"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap()
+    }
+
+    fn kinds(input: &str) -> Vec<(bool, TermKind)> {
+        parse(input)
+            .unwrap()
+            .terms
+            .into_iter()
+            .map(|term| (term.negated, term.kind))
+            .collect()
+    }
+
+    fn error_at(input: &str) -> usize {
+        parse(input).unwrap_err().position
+    }
+
+    fn compile_error_at(input: &str) -> usize {
+        compile(&parse(input).unwrap(), now()).unwrap_err().position
+    }
+
+    #[test]
+    fn parses_fields_and_text() {
+        assert_eq!(
+            kinds("level:>=warning env:prod* url:/api/*/users KeyError -method:get since:24h"),
+            vec![
+                (
+                    false,
+                    TermKind::Field(FieldFilter::Level(Comparison::Gte, LogLevel::Warning))
+                ),
+                (
+                    false,
+                    TermKind::Field(FieldFilter::Environment(Pattern::Prefix(
+                        "prod".to_string()
+                    )))
+                ),
+                (
+                    false,
+                    TermKind::Field(FieldFilter::Url(Pattern::Glob("/api/*/users".to_string())))
+                ),
+                (false, TermKind::Text("KeyError".to_string())),
+                (
+                    true,
+                    TermKind::Field(FieldFilter::Method("GET".to_string()))
+                ),
+                (
+                    false,
+                    TermKind::Field(FieldFilter::Since(TimeBound::Ago(Duration::hours(24))))
+                ),
+            ]
+        );
+        assert_eq!(parse("   ").unwrap().terms, Vec::new());
+    }
+
+    #[test]
+    fn parses_quoted_text_and_values() {
+        assert_eq!(
+            kinds(r#""connection \"reset\" \\ now" -"timed out" release:"1.0 beta" tag:region:eu"#),
+            vec![
+                (
+                    false,
+                    TermKind::Text(r#"connection "reset" \ now"#.to_string())
+                ),
+                (true, TermKind::Text("timed out".to_string())),
+                (
+                    false,
+                    TermKind::Field(FieldFilter::Release(Pattern::Exact("1.0 beta".to_string())))
+                ),
+                (
+                    false,
+                    TermKind::Field(FieldFilter::Tag(Pattern::Exact("region:eu".to_string())))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_time_bounds() {
+        let at = Utc.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap();
+        assert_eq!(
+            kinds("since:2024-04-01T00:00:00Z until:2w"),
+            vec![
+                (
+                    false,
+                    TermKind::Field(FieldFilter::Since(TimeBound::At(at)))
+                ),
+                (
+                    false,
+                    TermKind::Field(FieldFilter::Until(TimeBound::Ago(Duration::weeks(2))))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_parse_error_positions() {
+        assert_eq!(error_at("level:error -"), 12);
+        assert_eq!(error_at("- error"), 0);
+        assert_eq!(error_at("key\"error"), 3);
+        assert_eq!(error_at("env:"), 4);
+        assert_eq!(error_at("url:/x \"unterminated"), 7);
+        assert_eq!(error_at("env:\"prod"), 4);
+        assert_eq!(error_at("level:=>error"), 6);
+        assert_eq!(error_at("level:loud"), 6);
+        assert_eq!(error_at("a issue:123"), 8);
+        assert_eq!(error_at("a foo:bar"), 2);
+        assert_eq!(error_at("since:yesterday"), 6);
+        assert_eq!(error_at("since:24y"), 8);
+        assert_eq!(error_at("since:-1h"), 6);
+        assert_eq!(error_at("until:99999999999999w"), 6);
+        assert_eq!(error_at("env:prod -since:1h"), 9);
+        assert_eq!(
+            parse("a foo:bar").unwrap_err().to_string(),
+            "Unknown field `foo`; quote the term to search for it as text at position 2"
+        );
+    }
+
+    #[test]
+    fn reports_compile_error_positions() {
+        assert_eq!(compile_error_at("env:prod since:100000w"), 9);
+        assert_eq!(compile_error_at("until:2200-01-01T00:00:00Z"), 0);
+    }
+
+    #[test]
+    fn compiles_fields() {
+        let query = parse("level:>=error env:prod* -method:get user:42 url:/a/*/b").unwrap();
+        let compiled = compile(&query, now()).unwrap();
+
+        assert_eq!(compiled.text, None);
+        assert_eq!(
+            compiled.clauses,
+            vec![
+                doc! { "level": { "$in": ["error", "fatal"] } },
+                doc! { "environment": Regex { pattern: "^prod".to_string(), options: String::new() } },
+                doc! { "$nor": [{ "method": "GET" }] },
+                doc! { "context.user.id": { "$in": ["42", 42_i64] } },
+                doc! { "url": Regex { pattern: "^\\/a\\/.*\\/b$".to_string(), options: String::new() } },
+            ]
+        );
+    }
+
+    #[test]
+    fn compiles_text_to_phrases() {
+        let query = parse(r#"KeyError "connection reset" -timeout"#).unwrap();
+        let compiled = compile(&query, now()).unwrap();
+
+        assert_eq!(
+            compiled.text.as_deref(),
+            Some(r#""KeyError" "connection reset""#)
+        );
+        let timeout = Regex {
+            pattern: "timeout".to_string(),
+            options: "i".to_string(),
+        };
+        assert_eq!(
+            compiled.clauses,
+            vec![doc! {
+                "$nor": [{ "$or": [{ "error": timeout.clone() }, { "traceback": timeout }] }]
+            }]
+        );
+    }
+
+    #[test]
+    fn compiles_time_bounds_to_ids() {
+        let query = parse("since:1h").unwrap();
+        let compiled = compile(&query, now()).unwrap();
+        let since = log_search::id_at((now() - Duration::hours(1)).timestamp()).unwrap();
+
+        assert_eq!(compiled.clauses, vec![doc! { "_id": { "$gte": since } }]);
+    }
+
+    #[test]
+    fn resolves_the_narrowest_time_range() {
+        let query = parse("since:24h since:1h until:2024-05-01T11:45:00Z until:30m").unwrap();
+        let (since, until) = time_range(&query, now());
+
+        assert_eq!(since, Some(now() - Duration::hours(1)));
+        assert_eq!(until, Some(now() - Duration::minutes(30)));
+        assert_eq!(
+            time_range(&parse("level:error").unwrap(), now()),
+            (None, None)
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use crate::db::MongoRepo;
+use crate::models::log::{LogLevel, LogListQuery, LogPayload};
+use crate::services::log_query::{self, QueryError};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use chrono::Utc;
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document};
+use std::fmt;
+
+/// Why the parameters of a log search were rejected.
+#[derive(Debug)]
+pub enum LogSearchError {
+    InvalidLevel(String),
+    InvalidCursor,
+    TimeOutOfRange,
+    Query(QueryError),
+}
+
+impl fmt::Display for LogSearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogSearchError::InvalidLevel(message) => write!(f, "{}", message),
+            LogSearchError::InvalidCursor => write!(f, "Invalid cursor"),
+            LogSearchError::TimeOutOfRange => {
+                write!(f, "`from` and `to` must be between 1970 and 2106")
+            }
+            LogSearchError::Query(e) => write!(f, "Invalid query: {}", e),
+        }
+    }
+}
+
+impl ResponseError for LogSearchError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut body = serde_json::json!({ "error": self.to_string() });
+        if let LogSearchError::Query(e) = self {
+            body["position"] = e.position.into();
+        }
+        HttpResponse::build(self.status_code()).json(body)
+    }
+}
+
+/// Builds the Mongo filter for a page of the logs of `app_id`.
+///
+/// Time bounds and the cursor are ranges on `_id`: ids are generated on ingest, so their
+/// timestamp is the time the log was received, and the `_id` order is the page order.
+pub fn build_filter(app_id: ObjectId, query: &LogListQuery) -> Result<Document, LogSearchError> {
+    let mut filter = doc! { "application_id": app_id };
+
+    let mut id_range = Document::new();
+    if let Some(from) = query.from {
+        let from = id_at(from.timestamp()).ok_or(LogSearchError::TimeOutOfRange)?;
+        id_range.insert("$gte", from);
+    }
+    let mut upper = None;
+    if let Some(to) = query.to {
+        // Round up so a `to` inside a second keeps the logs received earlier in that second
+        let seconds = to.timestamp() + i64::from(to.timestamp_subsec_nanos() > 0);
+        upper = Some(id_at(seconds).ok_or(LogSearchError::TimeOutOfRange)?);
+    }
+    if let Some(cursor) = non_empty(&query.cursor) {
+        let cursor = decode_cursor(cursor)?;
+        upper = Some(upper.map_or(cursor, |to| to.min(cursor)));
+    }
+    if let Some(upper) = upper {
+        id_range.insert("$lt", upper);
+    }
+    if !id_range.is_empty() {
+        filter.insert("_id", id_range);
+    }
+
+    if let Some(levels) = non_empty(&query.level) {
+        let levels = split_list(levels)
+            .map(|level| level.parse::<LogLevel>().map(|l| l.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(LogSearchError::InvalidLevel)?;
+        filter.insert("level", doc! { "$in": levels });
+    }
+    if let Some(method) = non_empty(&query.method) {
+        filter.insert("method", method.to_ascii_uppercase());
+    }
+    if let Some(prefix) = non_empty(&query.url_prefix) {
+        // Anchored so the url index bounds the scan
+        filter.insert(
+            "url",
+            Bson::RegularExpression(mongodb::bson::Regex {
+                pattern: format!("^{}", escape_regex(prefix)),
+                options: String::new(),
+            }),
+        );
+    }
+    if let Some(environment) = non_empty(&query.environment) {
+        filter.insert("environment", environment);
+    }
+    if let Some(release) = non_empty(&query.release) {
+        filter.insert("release", release);
+    }
+    if let Some(tags) = non_empty(&query.tags) {
+        filter.insert(
+            "tags",
+            doc! { "$all": split_list(tags).collect::<Vec<_>>() },
+        );
+    }
+
+    let mut text: Vec<&str> = non_empty(&query.q).into_iter().collect();
+    let compiled = match non_empty(&query.query) {
+        Some(input) => log_query::parse(input)
+            .and_then(|parsed| log_query::compile(&parsed, Utc::now()))
+            .map_err(LogSearchError::Query)?,
+        None => Default::default(),
+    };
+    text.extend(compiled.text.as_deref());
+    if !compiled.clauses.is_empty() {
+        filter.insert("$and", compiled.clauses);
+    }
+    if !text.is_empty() {
+        filter.insert("$text", doc! { "$search": text.join(" ") });
+    }
+    Ok(filter)
+}
+
+/// Fetches up to `limit` logs matching `filter`, newest first, with the cursor of the next
+/// page if there is one.
+pub async fn fetch_page(
+    data: &MongoRepo,
+    filter: Document,
+    limit: i64,
+) -> Result<(Vec<LogPayload>, Option<String>), mongodb::error::Error> {
+    // One extra log tells whether there is a next page
+    let mut logs = data.list_logs(filter, limit + 1).await?;
+    let next_cursor = if logs.len() as i64 > limit {
+        logs.truncate(limit as usize);
+        logs.last().and_then(|log| log.id).map(encode_cursor)
+    } else {
+        None
+    };
+    Ok((logs, next_cursor))
+}
+
+/// The cursor of the page after `last`, the oldest log of the current page.
+fn encode_cursor(last: ObjectId) -> String {
+    last.to_hex()
+}
+
+fn decode_cursor(cursor: &str) -> Result<ObjectId, LogSearchError> {
+    ObjectId::parse_str(cursor).map_err(|_| LogSearchError::InvalidCursor)
+}
+
+/// The smallest id generated at `seconds` since the epoch.
+pub(crate) fn id_at(seconds: i64) -> Option<ObjectId> {
+    let seconds = u32::try_from(seconds).ok()?;
+    let mut bytes = [0u8; 12];
+    bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+    Some(ObjectId::from_bytes(bytes))
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Escapes the characters that have a meaning in a PCRE pattern.
+pub fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\^$.|?*+()[]{}/".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -1,22 +1,89 @@
 use crate::db::MongoRepo; // Fixes missing `MongoRepo`
 use crate::models::log::LogPayload; // Fixes missing `LogPayload`
+use crate::services::{
+    fingerprint_service, inference_queue, issue_service, similarity_service, traceback_parser,
+};
+use crate::services::rollup_service::RollupBuffer;
 use crate::services::websocket_queue::WebSocketQueue;
 use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::error::{BulkWriteFailure, ErrorKind};
+use mongodb::options::InsertManyOptions;
+use std::collections::HashMap;
+use std::env;
 use crate::websocket::server::WebSocketServer; // Fixes unresolved `WebSocketServer`
 use actix_web::web; // Fixes `use of undeclared crate or module 'web'` // Fixes unresolved `websocket_queue`
 
+/// Default body limits of the ingest endpoints, after decompression. Overridable through
+/// `LOG_MAX_BYTES`, `LOG_BATCH_MAX_ITEMS` and `LOG_BATCH_MAX_BYTES`.
+const DEFAULT_LOG_MAX_BYTES: usize = 1024 * 1024;
+const DEFAULT_BATCH_MAX_ITEMS: usize = 1000;
+const DEFAULT_BATCH_MAX_BYTES: usize = 5 * 1024 * 1024;
+
+pub fn log_max_bytes() -> usize {
+    env::var("LOG_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_LOG_MAX_BYTES)
+}
+
+pub fn batch_max_items() -> usize {
+    env::var("LOG_BATCH_MAX_ITEMS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_BATCH_MAX_ITEMS)
+}
+
+pub fn batch_max_bytes() -> usize {
+    env::var("LOG_BATCH_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_BATCH_MAX_BYTES)
+}
+
+/// Fills in the server-side fields of a log: id, timestamps, parsed frames and fingerprint.
+fn prepare_log(log: &mut LogPayload) {
+    // Set created_at and updated_at if not already provided
+    let now = Utc::now();
+    log.created_at = log.created_at.or(Some(now));
+    log.updated_at = log.updated_at.or(Some(now));
+
+    if log.frames.is_none() {
+        let frames = traceback_parser::parse(&log.traceback);
+        log.frames = (!frames.is_empty()).then_some(frames);
+    }
+
+    // Inference versions are only recorded through the inference endpoints
+    log.rag_inference_version = None;
+    log.rag_inference_accepted = None;
+    log.rag_inference_versions = None;
+    log.rag_inference_fixed_by = None;
+
+    // Linked to its issue once stored
+    log.issue_id = None;
+
+    // The id is assigned before inserting so the issue can point at the log as a sample
+    log.id = Some(ObjectId::new());
+    log.fingerprint = Some(fingerprint_service::compute_fingerprint(log));
+}
+
 pub async fn process_log(
     mut log: LogPayload,
     data: web::Data<MongoRepo>,
     websocket_server: web::Data<WebSocketServer>,
-    websocket_queue: web::Data<WebSocketQueue>,
+    _websocket_queue: web::Data<WebSocketQueue>,
+    rollups: web::Data<RollupBuffer>,
 ) -> Result<(), String> {
     log::info!("Processing log: {:?}", log);
-    // Set created_at and updated_at if not already provided
-    let now = Utc::now();
-    log.created_at = log.created_at.or(Some(now));
-    log.updated_at = log.updated_at.or(Some(now));
+    let org_id = log.organization_id.ok_or("Organization ID missing")?;
+    let app_id = log.application_id.ok_or("Application ID missing")?;
+
+    prepare_log(&mut log);
 
+    // Stored first so the issue only counts events that were kept
     let collection = data.db.collection::<LogPayload>("logs");
     let inserted_log = collection
         .insert_one(&log, None)
@@ -29,13 +96,20 @@ pub async fn process_log(
 
     log::info!("Log inserted with ID: {}", log_id);
     println!("Log inserted with ID: {}", log_id);
+    rollups.record(&log).await;
+    inference_queue::enqueue(&data, &[&log]).await;
+    similarity_service::store(&data, &[&log]).await;
 
-    // Attempt to deliver the log via WebSocket
-    let org_id = log.organization_id.ok_or("Organization ID missing")?;
-    let app_id = log.application_id.ok_or("Application ID missing")?;
+    // Group into an issue and reopen resolved or expired ignored ones; failures must not fail
+    // the ingest
+    let fingerprint = log.fingerprint.clone().unwrap_or_default();
+    if let Err(e) = record_issue(&data, &websocket_server, &[&log], &fingerprint).await {
+        log::error!("Failed to record issue event for log {}: {}", log_id, e);
+    }
 
+    // Attempt to deliver the log via WebSocket
     if !websocket_server
-        .push_log_id(org_id.clone(), app_id.clone(), log_id.clone())
+        .push_log_id(org_id, app_id, log_id)
         .await
     {
         // // If no connection is available, add to the retry queue
@@ -49,3 +123,103 @@ pub async fn process_log(
 
     Ok(())
 }
+
+/// Counts stored logs of one fingerprint on their issue, links them to it and reopens it
+/// if the events call for that.
+async fn record_issue(
+    data: &MongoRepo,
+    websocket_server: &WebSocketServer,
+    logs: &[&LogPayload],
+    fingerprint: &str,
+) -> Result<(), mongodb::error::Error> {
+    let Some(first) = logs.first() else {
+        return Ok(());
+    };
+    let issue = data
+        .record_issue_event(first, fingerprint, logs.len() as i64)
+        .await?;
+    if let (Some(issue_id), Some(app_id)) = (issue.id, first.application_id) {
+        let log_ids: Vec<ObjectId> = logs.iter().filter_map(|log| log.id).collect();
+        data.set_logs_issue(app_id, &log_ids, issue_id).await?;
+    }
+    issue_service::apply_event(data, websocket_server, &issue, first.release.as_deref()).await
+}
+
+/// Stores a batch of logs of one application with a single `insert_many`. Issues are updated
+/// once per distinct fingerprint and the WebSocket connections get a single batch message.
+/// Returns the id or error of every log, in input order.
+pub async fn process_log_batch(
+    mut logs: Vec<LogPayload>,
+    org_id: ObjectId,
+    app_id: ObjectId,
+    data: &MongoRepo,
+    websocket_server: &WebSocketServer,
+    rollups: &RollupBuffer,
+) -> Vec<Result<ObjectId, String>> {
+    let mut results: Vec<Result<ObjectId, String>> = Vec::with_capacity(logs.len());
+    for log in logs.iter_mut() {
+        log.organization_id = Some(org_id);
+        log.application_id = Some(app_id);
+        prepare_log(log);
+        results.push(Ok(log.id.unwrap()));
+    }
+
+    // Unordered so one failure doesn't stop the rest
+    let collection = data.db.collection::<LogPayload>("logs");
+    let options = InsertManyOptions::builder().ordered(false).build();
+    if let Err(e) = collection.insert_many(&logs, options).await {
+        match e.kind.as_ref() {
+            ErrorKind::BulkWrite(BulkWriteFailure {
+                write_errors: Some(write_errors),
+                ..
+            }) => {
+                for write_error in write_errors {
+                    if let Some(result) = results.get_mut(write_error.index) {
+                        *result = Err(write_error.message.clone());
+                    }
+                }
+            }
+            _ => {
+                for result in results.iter_mut() {
+                    *result = Err(e.to_string());
+                }
+            }
+        }
+    }
+    log::info!(
+        "Inserted {} of {} logs for App ID: {}",
+        results.iter().filter(|r| r.is_ok()).count(),
+        results.len(),
+        app_id
+    );
+    let stored: Vec<&LogPayload> = logs
+        .iter()
+        .zip(&results)
+        .filter(|(_, r)| r.is_ok())
+        .map(|(log, _)| log)
+        .collect();
+    for log in &stored {
+        rollups.record(log).await;
+    }
+    inference_queue::enqueue(data, &stored).await;
+    similarity_service::store(data, &stored).await;
+
+    // One issue update per fingerprint, counting all of its stored events
+    let mut groups: HashMap<String, Vec<&LogPayload>> = HashMap::new();
+    for log in &stored {
+        let fingerprint = log.fingerprint.clone().unwrap_or_default();
+        groups.entry(fingerprint).or_default().push(log);
+    }
+    for (fingerprint, group) in &groups {
+        if let Err(e) = record_issue(data, websocket_server, group, fingerprint).await {
+            log::error!("Failed to record issue events for fingerprint {}: {}", fingerprint, e);
+        }
+    }
+
+    let log_ids: Vec<ObjectId> = results.iter().filter_map(|r| r.as_ref().ok()).copied().collect();
+    if !log_ids.is_empty() {
+        websocket_server.push_log_ids(org_id, app_id, log_ids).await;
+    }
+
+    results
+}
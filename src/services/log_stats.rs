@@ -0,0 +1,486 @@
+use crate::db::MongoRepo;
+use crate::models::log::LogListQuery;
+use crate::models::rollup::RollupGranularity;
+use crate::models::stats::{LogStats, LogStatsParams, StatsDimension, StatsSeries};
+use crate::services::log_query;
+use crate::services::log_search::{self, LogSearchError};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use mongodb::bson::{self, doc, oid::ObjectId, Bson, Document};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+/// Most buckets a histogram can have.
+const MAX_BUCKETS: i64 = 1000;
+
+/// Why a stats request was rejected.
+#[derive(Debug)]
+pub enum LogStatsError {
+    InvalidInterval,
+    TooManyBuckets,
+    Search(LogSearchError),
+    Database,
+}
+
+impl fmt::Display for LogStatsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogStatsError::InvalidInterval => write!(
+                f,
+                "`interval` must be a number of minutes, hours, days or weeks, e.g. `5m` or `1h`"
+            ),
+            LogStatsError::TooManyBuckets => write!(
+                f,
+                "More than {} buckets; use a larger interval or a shorter time range",
+                MAX_BUCKETS
+            ),
+            LogStatsError::Search(e) => write!(f, "{}", e),
+            LogStatsError::Database => write!(f, "Failed to compute stats"),
+        }
+    }
+}
+
+impl ResponseError for LogStatsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            LogStatsError::InvalidInterval | LogStatsError::TooManyBuckets => {
+                StatusCode::BAD_REQUEST
+            }
+            LogStatsError::Search(e) => e.status_code(),
+            LogStatsError::Database => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            LogStatsError::Search(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).json(serde_json::json!({
+                "error": self.to_string()
+            })),
+        }
+    }
+}
+
+/// Counts the logs of `app_id` matching `filters`, per group of `params.group_by` and per
+/// `params.interval` bucket of the time the logs were received.
+pub async fn log_stats(
+    data: &MongoRepo,
+    app_id: ObjectId,
+    filters: &LogListQuery,
+    params: &LogStatsParams,
+) -> Result<LogStats, LogStatsError> {
+    let now = Utc::now();
+    let interval = params.interval.as_deref().map(parse_interval).transpose()?;
+
+    let mut filters = LogListQuery {
+        cursor: None,
+        ..filters.clone()
+    };
+
+    // The time range, if the filters give one, decides where the series start and end
+    let (mut from, mut to) = (filters.from, filters.to);
+    if let Some(query) = filters.query.as_deref().filter(|q| !q.trim().is_empty()) {
+        let query =
+            log_query::parse(query).map_err(|e| LogStatsError::Search(LogSearchError::Query(e)))?;
+        let (since, until) = log_query::time_range(&query, now);
+        from = from.max(since);
+        to = match (to, until) {
+            (Some(to), Some(until)) => Some(to.min(until)),
+            (to, until) => to.or(until),
+        };
+    }
+    if let Some(interval) = interval {
+        let end = to.unwrap_or(now);
+        // Without a lower bound, the most recent buckets the limit allows rather than all logs
+        let start = match from {
+            Some(from) => from,
+            None => {
+                let start = default_start(interval, end);
+                (from, filters.from) = (Some(start), Some(start));
+                start
+            }
+        };
+        bucket_range(interval, start, end)?;
+    }
+    let filter = log_search::build_filter(app_id, &filters).map_err(LogStatsError::Search)?;
+
+    let source = match rollup_granularity(&filters, params.group_by, interval) {
+        Some(granularity) => {
+            // The retention purge deletes expired rollups with the logs, to the hour
+            let covered = data
+                .rollup_coverage(Some(app_id))
+                .await
+                .map_err(|_| LogStatsError::Database)?;
+            let start = filters.from.unwrap_or_default();
+            match covered {
+                Some(since) if since <= start => StatsSource::Rollups(granularity),
+                _ => StatsSource::Logs,
+            }
+        }
+        None => StatsSource::Logs,
+    };
+
+    let result = match source {
+        StatsSource::Logs => {
+            let pipeline = stats_pipeline(filter, params.group_by, interval, params.top(), source);
+            data.aggregate_logs(pipeline).await
+        }
+        StatsSource::Rollups(granularity) => {
+            let filter = rollup_filter(filter, &filters);
+            let pipeline = stats_pipeline(filter, params.group_by, interval, params.top(), source);
+            data.aggregate_rollups(granularity, pipeline).await
+        }
+    }
+    .map_err(|_| LogStatsError::Database)?;
+    let result = result.into_iter().next().unwrap_or_default();
+
+    let total = result
+        .get_array("total")
+        .ok()
+        .and_then(|total| total.first())
+        .and_then(Bson::as_document)
+        .and_then(|total| total.get("n"))
+        .and_then(as_i64)
+        .unwrap_or(0);
+
+    let mut groups = Vec::new();
+    let mut observed = BTreeSet::new();
+    for group in result.get_array("series").map(Vec::as_slice).unwrap_or(&[]) {
+        let Some(group) = group.as_document() else {
+            continue;
+        };
+        let key = match group.get("_id") {
+            Some(Bson::String(key)) => Some(key.clone()),
+            Some(Bson::Null) | None => None,
+            Some(other) => Some(other.to_string()),
+        };
+        let group_total = group.get("total").and_then(as_i64).unwrap_or(0);
+        let mut counts = HashMap::new();
+        for bucket in group.get_array("buckets").map(Vec::as_slice).unwrap_or(&[]) {
+            let Some(bucket) = bucket.as_document() else {
+                continue;
+            };
+            if let (Some(start), Some(count)) = (
+                bucket.get("t").and_then(as_i64),
+                bucket.get("c").and_then(as_i64),
+            ) {
+                observed.insert(start);
+                counts.insert(start, count);
+            }
+        }
+        groups.push((key, group_total, counts));
+    }
+
+    // Fill the buckets without logs so every series has the same length
+    let buckets: Vec<i64> = match interval {
+        None => Vec::new(),
+        Some(interval) => {
+            let start = match from {
+                Some(from) => Some(from),
+                None => observed.first().map(|ms| millis_to_time(*ms)),
+            };
+            let end = match (to, from) {
+                (Some(to), _) => Some(to - Duration::milliseconds(1)),
+                (None, Some(_)) => Some(now),
+                (None, None) => observed.last().map(|ms| millis_to_time(*ms)),
+            };
+            match (start, end) {
+                (Some(start), Some(end)) if start <= end => bucket_range(interval, start, end)?,
+                _ => Vec::new(),
+            }
+        }
+    };
+
+    let series = groups
+        .into_iter()
+        .map(|(key, total, counts)| StatsSeries {
+            key,
+            total,
+            counts: buckets
+                .iter()
+                .map(|start| counts.get(start).copied().unwrap_or(0))
+                .collect(),
+        })
+        .collect();
+
+    Ok(LogStats {
+        interval: params.interval.as_deref().map(|i| i.trim().to_string()),
+        group_by: params.group_by,
+        buckets: buckets
+            .iter()
+            .map(|ms| millis_to_time(*ms).to_rfc3339())
+            .collect(),
+        series,
+        total,
+    })
+}
+
+/// `5m`, `1h`, `1d`, `1w`, at least one minute.
+fn parse_interval(value: &str) -> Result<Duration, LogStatsError> {
+    let value = value.trim();
+    let split = value.len() - value.chars().last().map_or(0, char::len_utf8);
+    let amount: i64 = value[..split]
+        .parse()
+        .map_err(|_| LogStatsError::InvalidInterval)?;
+    let interval = match &value[split..] {
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => None,
+    };
+    interval
+        .filter(|i| *i >= Duration::minutes(1))
+        .ok_or(LogStatsError::InvalidInterval)
+}
+
+/// Start of the earliest of the last `MAX_BUCKETS` buckets up to the one holding `end`.
+fn default_start(interval: Duration, end: DateTime<Utc>) -> DateTime<Utc> {
+    let step = interval.num_milliseconds();
+    let last = end.timestamp_millis().div_euclid(step) * step;
+    // Logs have no earlier times than the epoch of their ids
+    millis_to_time(
+        last.saturating_sub(step.saturating_mul(MAX_BUCKETS - 1))
+            .max(0),
+    )
+}
+
+/// Start of every bucket from the one holding `start` to the one holding `end`, in epoch
+/// milliseconds. Buckets are aligned on multiples of the interval since the epoch (UTC).
+fn bucket_range(
+    interval: Duration,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Vec<i64>, LogStatsError> {
+    let step = interval.num_milliseconds();
+    let first = start.timestamp_millis().div_euclid(step) * step;
+    let last = end.timestamp_millis().div_euclid(step) * step;
+    if (last - first) / step + 1 > MAX_BUCKETS {
+        return Err(LogStatsError::TooManyBuckets);
+    }
+    Ok((first..=last).step_by(step as usize).collect())
+}
+
+/// Where counts are read from: the raw logs, or the rollups when they keep every field the
+/// request filters or groups on.
+#[derive(Debug, Clone, Copy)]
+enum StatsSource {
+    Logs,
+    Rollups(RollupGranularity),
+}
+
+/// Rollups to answer the request from, if any. They only keep the level and the release, and
+/// their buckets must line up with the interval and the time range.
+fn rollup_granularity(
+    filters: &LogListQuery,
+    group_by: Option<StatsDimension>,
+    interval: Option<Duration>,
+) -> Option<RollupGranularity> {
+    let unsupported = [
+        &filters.method,
+        &filters.url_prefix,
+        &filters.environment,
+        &filters.tags,
+        &filters.q,
+        &filters.query,
+    ];
+    if unsupported
+        .iter()
+        .any(|value| value.as_deref().is_some_and(|v| !v.trim().is_empty()))
+    {
+        return None;
+    }
+    if !matches!(
+        group_by,
+        None | Some(StatsDimension::Level) | Some(StatsDimension::Release)
+    ) {
+        return None;
+    }
+
+    [RollupGranularity::Hour, RollupGranularity::Minute]
+        .into_iter()
+        .find(|granularity| {
+            let step = granularity.millis();
+            let aligned = |time: Option<DateTime<Utc>>| {
+                time.is_none_or(|time| time.timestamp_millis() % step == 0)
+            };
+            interval.is_none_or(|i| i.num_milliseconds() % step == 0)
+                && aligned(filters.from)
+                && aligned(filters.to)
+        })
+}
+
+/// Turns a log filter into a rollup filter: the `_id` range becomes a bucket range, the
+/// application, level and release conditions apply as they are.
+fn rollup_filter(mut filter: Document, filters: &LogListQuery) -> Document {
+    filter.remove("_id");
+    let mut bucket = Document::new();
+    if let Some(from) = filters.from {
+        bucket.insert("$gte", bson::DateTime::from_millis(from.timestamp_millis()));
+    }
+    if let Some(to) = filters.to {
+        bucket.insert("$lt", bson::DateTime::from_millis(to.timestamp_millis()));
+    }
+    if !bucket.is_empty() {
+        filter.insert("bucket", bucket);
+    }
+    filter
+}
+
+fn stats_pipeline(
+    filter: Document,
+    group_by: Option<StatsDimension>,
+    interval: Option<Duration>,
+    top: i64,
+    source: StatsSource,
+) -> Vec<Document> {
+    let key = match group_by {
+        None => Bson::Null,
+        Some(StatsDimension::Level) => "$level".into(),
+        Some(StatsDimension::Method) => "$method".into(),
+        Some(StatsDimension::Url) => {
+            doc! { "$arrayElemAt": [ { "$split": ["$url", "?"] }, 0 ] }.into()
+        }
+        Some(StatsDimension::Release) => "$release".into(),
+        Some(StatsDimension::Environment) => "$environment".into(),
+        Some(StatsDimension::ServerName) => "$server_name".into(),
+        Some(StatsDimension::Issue) => doc! { "$toString": "$issue_id" }.into(),
+        Some(StatsDimension::Tag) => "$tags".into(),
+    };
+    // Ids are generated on ingest, so their timestamp is the time the log was received
+    let (received, weight) = match source {
+        StatsSource::Logs => (doc! { "$toLong": { "$toDate": "$_id" } }, Bson::from(1)),
+        StatsSource::Rollups(_) => (doc! { "$toLong": "$bucket" }, "$count".into()),
+    };
+    let bucket = match interval {
+        None => Bson::Null,
+        Some(interval) => doc! {
+            "$subtract": [
+                received.clone(),
+                { "$mod": [received, interval.num_milliseconds()] },
+            ]
+        }
+        .into(),
+    };
+
+    let mut series = Vec::new();
+    if group_by == Some(StatsDimension::Tag) {
+        series.push(doc! { "$unwind": "$tags" });
+    }
+    series.extend([
+        doc! { "$group": {
+            "_id": { "key": key, "bucket": bucket },
+            "count": { "$sum": weight.clone() },
+        } },
+        doc! { "$group": {
+            "_id": "$_id.key",
+            "total": { "$sum": "$count" },
+            "buckets": { "$push": { "t": "$_id.bucket", "c": "$count" } },
+        } },
+        doc! { "$sort": { "total": -1, "_id": 1 } },
+        doc! { "$limit": top },
+    ]);
+
+    vec![
+        doc! { "$match": filter },
+        doc! { "$facet": {
+            "total": [ { "$group": { "_id": Bson::Null, "n": { "$sum": weight } } } ],
+            "series": series,
+        } },
+    ]
+}
+
+fn as_i64(value: &Bson) -> Option<i64> {
+    match value {
+        Bson::Int32(n) => Some(i64::from(*n)),
+        Bson::Int64(n) => Some(*n),
+        Bson::Double(n) => Some(*n as i64),
+        _ => None,
+    }
+}
+
+fn millis_to_time(ms: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(ms).single().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(ms: i64) -> DateTime<Utc> {
+        millis_to_time(ms)
+    }
+
+    #[test]
+    fn parses_intervals() {
+        assert_eq!(parse_interval("5m").unwrap(), Duration::minutes(5));
+        assert_eq!(parse_interval(" 1h ").unwrap(), Duration::hours(1));
+        assert_eq!(parse_interval("2d").unwrap(), Duration::days(2));
+        assert_eq!(parse_interval("1w").unwrap(), Duration::weeks(1));
+    }
+
+    #[test]
+    fn rejects_invalid_intervals() {
+        for value in [
+            "",
+            "m",
+            "30s",
+            "0m",
+            "-1h",
+            "1.5h",
+            "1y",
+            "h1",
+            "99999999999999999w",
+        ] {
+            assert!(
+                matches!(parse_interval(value), Err(LogStatsError::InvalidInterval)),
+                "accepted {:?}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn aligns_buckets_on_the_interval() {
+        let hour = Duration::hours(1).num_milliseconds();
+        let buckets = bucket_range(Duration::hours(1), at(hour + 1), at(3 * hour + 5)).unwrap();
+
+        assert_eq!(buckets, vec![hour, 2 * hour, 3 * hour]);
+        assert_eq!(
+            bucket_range(Duration::hours(1), at(hour), at(hour)).unwrap(),
+            vec![hour]
+        );
+    }
+
+    #[test]
+    fn limits_the_number_of_buckets() {
+        let minute = Duration::minutes(1).num_milliseconds();
+        let end = at(MAX_BUCKETS * minute - 1);
+
+        assert_eq!(
+            bucket_range(Duration::minutes(1), at(0), end)
+                .unwrap()
+                .len() as i64,
+            MAX_BUCKETS
+        );
+        assert!(matches!(
+            bucket_range(Duration::minutes(1), at(0), end + Duration::minutes(1)),
+            Err(LogStatsError::TooManyBuckets)
+        ));
+    }
+
+    #[test]
+    fn defaults_to_the_last_buckets_allowed() {
+        let interval = Duration::minutes(1);
+        let end = at(5_000 * interval.num_milliseconds() + 30_000);
+        let start = default_start(interval, end);
+
+        assert_eq!(start, at(4_001 * interval.num_milliseconds()));
+        assert_eq!(
+            bucket_range(interval, start, end).unwrap().len() as i64,
+            MAX_BUCKETS
+        );
+        // Never before the epoch, however large the interval
+        assert_eq!(default_start(Duration::weeks(1), end), at(0));
+    }
+}
@@ -1,5 +1,27 @@
+pub mod api_key_service;
+pub mod app_deletion_service;
+pub mod archive_service;
+pub mod credential_service;
 pub mod email_service;
+pub mod fingerprint_service;
+pub mod inference_feedback_service;
+pub mod inference_queue;
+pub mod ingest_body;
+pub mod invitation_service;
+pub mod issue_service;
 pub mod jwt_service;
+pub mod log_query;
+pub mod log_search;
 pub mod log_service;
+pub mod log_stats;
+pub mod organization_service;
 pub mod otp_service;
+pub mod rag_inference_service;
+pub mod retention_service;
+pub mod rollup_service;
+pub mod s3_client;
+pub mod saved_search_service;
+pub mod similarity_service;
+pub mod tenant_cache;
+pub mod traceback_parser;
 pub mod websocket_queue;
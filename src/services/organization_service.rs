@@ -0,0 +1,183 @@
+use crate::db::MongoRepo;
+use crate::models::membership::OrgRole;
+use crate::models::organization::UpdateOrganizationPayload;
+use crate::services::app_deletion_service;
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use chrono::{DateTime, Utc};
+use mongodb::bson::{oid::ObjectId, Document};
+use std::fmt;
+
+const MAX_ORG_NAME_LEN: usize = 100;
+
+#[derive(Debug)]
+pub enum OrganizationError {
+    InvalidName,
+    InvalidEmail,
+    EmptyUpdate,
+    NotAMember,
+    AlreadyOwner,
+    NotFound,
+    Database,
+}
+
+impl fmt::Display for OrganizationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrganizationError::InvalidName => write!(
+                f,
+                "Organization name must be between 1 and {} characters",
+                MAX_ORG_NAME_LEN
+            ),
+            OrganizationError::InvalidEmail => write!(f, "Invalid admin email"),
+            OrganizationError::EmptyUpdate => write!(f, "Nothing to update"),
+            OrganizationError::NotAMember => {
+                write!(f, "The new owner must be a member of the organization")
+            }
+            OrganizationError::AlreadyOwner => write!(f, "User is already an owner"),
+            OrganizationError::NotFound => write!(f, "Organization not found"),
+            OrganizationError::Database => write!(f, "Failed to update the organization"),
+        }
+    }
+}
+
+impl ResponseError for OrganizationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            OrganizationError::InvalidName
+            | OrganizationError::InvalidEmail
+            | OrganizationError::EmptyUpdate => StatusCode::BAD_REQUEST,
+            OrganizationError::NotAMember => StatusCode::NOT_FOUND,
+            OrganizationError::AlreadyOwner => StatusCode::CONFLICT,
+            OrganizationError::NotFound => StatusCode::NOT_FOUND,
+            OrganizationError::Database => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+pub fn validate_name(name: &str) -> Result<String, OrganizationError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_ORG_NAME_LEN {
+        return Err(OrganizationError::InvalidName);
+    }
+    Ok(name.to_string())
+}
+
+pub fn validate_email(email: &str) -> Result<String, OrganizationError> {
+    let email = email.trim().to_lowercase();
+    if email.is_empty() || !email.contains('@') {
+        return Err(OrganizationError::InvalidEmail);
+    }
+    Ok(email)
+}
+
+/// Renames the organization and/or changes its admin email.
+pub async fn update(
+    data: &MongoRepo,
+    org_id: ObjectId,
+    payload: UpdateOrganizationPayload,
+) -> Result<(), OrganizationError> {
+    let mut set = Document::new();
+    if let Some(name) = payload.org_name {
+        set.insert("org_name", validate_name(&name)?);
+    }
+    if let Some(email) = payload.admin_email {
+        set.insert("admin_email", validate_email(&email)?);
+    }
+    if set.is_empty() {
+        return Err(OrganizationError::EmptyUpdate);
+    }
+
+    match data.update_organization(org_id, set).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(OrganizationError::NotFound),
+        Err(_) => Err(OrganizationError::Database),
+    }
+}
+
+/// Makes a member the owner. `previous_owner` (the signed-in owner handing over) becomes an
+/// admin; with org credentials every other owner does.
+pub async fn transfer_ownership(
+    data: &MongoRepo,
+    org_id: ObjectId,
+    user_id: ObjectId,
+    previous_owner: Option<ObjectId>,
+) -> Result<(), OrganizationError> {
+    let membership = match data.get_membership(org_id, user_id).await {
+        Ok(Some(membership)) => membership,
+        Ok(None) => return Err(OrganizationError::NotAMember),
+        Err(_) => return Err(OrganizationError::Database),
+    };
+    if membership.role == OrgRole::Owner && previous_owner.is_some() {
+        return Err(OrganizationError::AlreadyOwner);
+    }
+
+    // Promote first, so the organization never goes without an owner
+    data.update_membership_role(org_id, user_id, OrgRole::Owner)
+        .await
+        .map_err(|_| OrganizationError::Database)?;
+    let demoted = match previous_owner {
+        Some(previous) => data
+            .update_membership_role(org_id, previous, OrgRole::Admin)
+            .await
+            .map(|_| ()),
+        None => data.demote_other_owners(org_id, user_id).await.map(|_| ()),
+    };
+    demoted.map_err(|_| OrganizationError::Database)
+}
+
+/// Deletes the organization and every one of its applications. The organization stops
+/// authenticating right away; its applications are purged after the grace period, and the
+/// organization's own records with the last of them. Returns how many applications were
+/// deleted and when their purge starts, or `None` if the organization was already deleted.
+pub async fn delete(
+    data: &MongoRepo,
+    org_id: ObjectId,
+) -> Result<Option<(u64, DateTime<Utc>)>, mongodb::error::Error> {
+    if !data.mark_organization_deleted(org_id).await? {
+        return Ok(None);
+    }
+    let deleted = delete_applications(data, org_id).await?;
+    Ok(Some((
+        deleted,
+        Utc::now() + app_deletion_service::grace_period(),
+    )))
+}
+
+async fn delete_applications(
+    data: &MongoRepo,
+    org_id: ObjectId,
+) -> Result<u64, mongodb::error::Error> {
+    let mut deleted = 0;
+    for app in data.list_applications(Some(org_id)).await? {
+        let Some(app_id) = app.id else {
+            continue;
+        };
+        if app_deletion_service::delete(data, app_id).await?.is_some() {
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+/// Removes the records of deleted organizations whose applications are all purged.
+pub async fn purge_deleted(data: &MongoRepo) -> Result<(), mongodb::error::Error> {
+    for org in data.list_deleted_organizations().await? {
+        let Some(org_id) = org.id else {
+            continue;
+        };
+        // Applications created while the organization was being deleted go the same way
+        delete_applications(data, org_id).await?;
+        if data.count_unpurged_applications(org_id).await? > 0 {
+            continue;
+        }
+        data.delete_organization_records(org_id).await?;
+        log::info!("Purged deleted Org ID: {}", org_id);
+    }
+    Ok(())
+}
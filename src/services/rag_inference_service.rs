@@ -0,0 +1,297 @@
+use crate::db::MongoRepo;
+use crate::extractors::tenant::{Access, AuthenticatedApp};
+use crate::models::rag_inference::{
+    schema_version, InferenceAuthor, InferenceAuthorKind, InferenceMetadata, RagInferenceV1,
+    RagInferenceVersion, RAG_INFERENCE_SCHEMA_VERSION,
+};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum RagInferenceError {
+    LogNotFound,
+    VersionNotFound,
+    Invalid(String),
+    Database,
+}
+
+impl fmt::Display for RagInferenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RagInferenceError::LogNotFound => write!(f, "Log not found"),
+            RagInferenceError::VersionNotFound => write!(f, "RAG inference version not found"),
+            RagInferenceError::Invalid(reason) => write!(f, "Invalid RAG inference: {}", reason),
+            RagInferenceError::Database => write!(f, "Failed to update RAG inference"),
+        }
+    }
+}
+
+impl ResponseError for RagInferenceError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            RagInferenceError::LogNotFound | RagInferenceError::VersionNotFound => {
+                StatusCode::NOT_FOUND
+            }
+            RagInferenceError::Invalid(_) => StatusCode::BAD_REQUEST,
+            RagInferenceError::Database => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+impl From<mongodb::error::Error> for RagInferenceError {
+    fn from(_: mongodb::error::Error) -> Self {
+        RagInferenceError::Database
+    }
+}
+
+/// The author of an inference written with `access`, through the job queue when `worker_id`
+/// is set.
+pub fn author(access: &Access, worker_id: Option<&str>) -> InferenceAuthor {
+    let (kind, id) = match access {
+        Access::ApiKey { key_id, .. } => (InferenceAuthorKind::ApiKey, Some(*key_id)),
+        Access::Member { user_id, .. } => (InferenceAuthorKind::User, Some(*user_id)),
+        Access::OrgSecret { .. } => (InferenceAuthorKind::OrgSecret, None),
+    };
+    InferenceAuthor {
+        kind,
+        id,
+        worker_id: worker_id.map(str::to_string),
+    }
+}
+
+pub fn version_json(version: &RagInferenceVersion, shown: Option<i32>) -> serde_json::Value {
+    serde_json::json!({
+        "id": version.id.map(|id| id.to_hex()),
+        "log_id": version.log_id.to_hex(),
+        "version": version.version,
+        "inference": version.inference,
+        "model": version.metadata.model,
+        "prompt_version": version.metadata.prompt_version,
+        "latency_ms": version.metadata.latency_ms,
+        "prompt_tokens": version.metadata.prompt_tokens,
+        "completion_tokens": version.metadata.completion_tokens,
+        "author": {
+            "kind": version.author.kind,
+            "id": version.author.id.map(|id| id.to_hex()),
+            "worker_id": version.author.worker_id,
+        },
+        "job_id": version.job_id.map(|id| id.to_hex()),
+        "schema_version": version.schema_version,
+        "flagged": version.flagged,
+        "created_at": version.created_at.to_rfc3339(),
+        "current": shown == Some(version.version),
+    })
+}
+
+/// Checks an inference against its schema version. Returns whether it has to be flagged:
+/// inferences of a version this server does not know are kept unvalidated. Anything but an
+/// object with an integer `schema_version` is refused.
+pub fn validate(inference: &serde_json::Value) -> Result<bool, RagInferenceError> {
+    if !inference.is_object() {
+        return Err(RagInferenceError::Invalid(
+            "the inference must be a JSON object".to_string(),
+        ));
+    }
+    match schema_version(inference) {
+        None => {
+            return Err(RagInferenceError::Invalid(
+                "schema_version must be an integer".to_string(),
+            ))
+        }
+        Some(RAG_INFERENCE_SCHEMA_VERSION) => {}
+        Some(_) => return Ok(true),
+    }
+    let typed: RagInferenceV1 = serde_json::from_value(inference.clone())
+        .map_err(|e| RagInferenceError::Invalid(e.to_string()))?;
+    let problems = typed.problems();
+    if problems.is_empty() {
+        Ok(false)
+    } else {
+        Err(RagInferenceError::Invalid(problems.join("; ")))
+    }
+}
+
+/// Records a new inference version of a log. The log shows it unless a version was accepted.
+pub async fn record(
+    data: &MongoRepo,
+    auth: &AuthenticatedApp,
+    log_id: ObjectId,
+    inference: serde_json::Value,
+    metadata: InferenceMetadata,
+    author: InferenceAuthor,
+    job_id: Option<ObjectId>,
+) -> Result<RagInferenceVersion, RagInferenceError> {
+    let flagged = validate(&inference)?;
+    let app_id = auth.app_id();
+    let log = data
+        .get_log(app_id, log_id)
+        .await?
+        .ok_or(RagInferenceError::LogNotFound)?;
+
+    // Keep what the log got before versions existed as version 1
+    if let (Some(legacy), None) = (&log.rag_inference, log.rag_inference_versions) {
+        if data.claim_legacy_rag_inference(app_id, log_id).await? {
+            let legacy = RagInferenceVersion {
+                id: Some(ObjectId::new()),
+                organization_id: auth.org_id(),
+                application_id: app_id,
+                log_id,
+                version: 1,
+                inference: legacy.clone(),
+                metadata: InferenceMetadata::default(),
+                author: InferenceAuthor {
+                    kind: InferenceAuthorKind::Legacy,
+                    id: None,
+                    worker_id: None,
+                },
+                job_id: None,
+                schema_version: schema_version(legacy),
+                // Stored before validation existed, so never rejected
+                flagged: validate(legacy).unwrap_or(true),
+                created_at: log.updated_at.unwrap_or_else(Utc::now),
+            };
+            data.insert_rag_inference(&legacy).await?;
+        }
+    }
+
+    let number = data
+        .next_rag_inference_version(app_id, log_id)
+        .await?
+        .ok_or(RagInferenceError::LogNotFound)?;
+    let version = RagInferenceVersion {
+        id: Some(ObjectId::new()),
+        organization_id: auth.org_id(),
+        application_id: app_id,
+        log_id,
+        version: number,
+        schema_version: schema_version(&inference),
+        inference,
+        metadata,
+        author,
+        job_id,
+        flagged,
+        created_at: Utc::now(),
+    };
+    data.insert_rag_inference(&version).await?;
+    data.show_latest_rag_inference(app_id, log_id, number, &version.inference)
+        .await?;
+    Ok(version)
+}
+
+/// Every inference version of a log, newest first, and the version the log shows.
+pub async fn history(
+    data: &MongoRepo,
+    app_id: ObjectId,
+    log_id: ObjectId,
+) -> Result<serde_json::Value, RagInferenceError> {
+    let log = data
+        .get_log(app_id, log_id)
+        .await?
+        .ok_or(RagInferenceError::LogNotFound)?;
+    let versions = data.list_rag_inferences(app_id, log_id).await?;
+    let versions: Vec<_> = versions
+        .iter()
+        .map(|version| version_json(version, log.rag_inference_version))
+        .collect();
+    Ok(serde_json::json!({
+        "log_id": log_id.to_hex(),
+        "current_version": log.rag_inference_version,
+        "accepted": log.rag_inference_accepted.unwrap_or(false),
+        "fixed_by": log.rag_inference_fixed_by,
+        "versions": versions,
+    }))
+}
+
+/// Makes the log show `version` whatever is recorded after it.
+pub async fn accept(
+    data: &MongoRepo,
+    app_id: ObjectId,
+    log_id: ObjectId,
+    version: i32,
+) -> Result<RagInferenceVersion, RagInferenceError> {
+    let version = data
+        .get_rag_inference(app_id, log_id, Some(version))
+        .await?
+        .ok_or(RagInferenceError::VersionNotFound)?;
+    if !data
+        .set_log_rag_inference(app_id, log_id, &version, true)
+        .await?
+    {
+        return Err(RagInferenceError::LogNotFound);
+    }
+    Ok(version)
+}
+
+/// Withdraws the accepted version: the log shows the latest one again.
+pub async fn unaccept(
+    data: &MongoRepo,
+    app_id: ObjectId,
+    log_id: ObjectId,
+) -> Result<RagInferenceVersion, RagInferenceError> {
+    let latest = data
+        .get_rag_inference(app_id, log_id, None)
+        .await?
+        .ok_or(RagInferenceError::VersionNotFound)?;
+    if !data
+        .set_log_rag_inference(app_id, log_id, &latest, false)
+        .await?
+    {
+        return Err(RagInferenceError::LogNotFound);
+    }
+    Ok(latest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn validates_known_versions() {
+        let valid = json!({ "schema_version": 1, "root_cause": "Cold cache", "confidence": 0.5 });
+        assert!(!validate(&valid).unwrap());
+
+        let invalid = json!({ "schema_version": 1, "root_cause": "Cold cache", "confidence": 5 });
+        assert!(matches!(
+            validate(&invalid),
+            Err(RagInferenceError::Invalid(reason)) if reason == "confidence must be between 0 and 1"
+        ));
+        assert!(matches!(
+            validate(&json!({ "schema_version": 1 })),
+            Err(RagInferenceError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn flags_unknown_versions() {
+        assert!(validate(&json!({ "schema_version": 2, "anything": [] })).unwrap());
+    }
+
+    #[test]
+    fn refuses_unversioned_payloads() {
+        for inference in [
+            json!({ "root_cause": "Cold cache", "confidence": 0.5 }),
+            json!({ "schema_version": "1" }),
+            json!({ "schema_version": 1.5 }),
+            json!("Cold cache"),
+            json!([1, 2]),
+            json!(null),
+        ] {
+            let error = validate(&inference).unwrap_err();
+            assert_eq!(
+                error.status_code(),
+                StatusCode::BAD_REQUEST,
+                "{}",
+                inference
+            );
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use crate::db::MongoRepo;
+use crate::models::application::Application;
+use crate::models::organization::Organization;
+use crate::models::retention::{AppStorage, RetentionSource};
+use crate::models::rollup::RollupGranularity;
+use crate::services::{archive_service, log_search};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use mongodb::bson::oid::ObjectId;
+use std::collections::hash_map::{Entry, HashMap};
+use std::env;
+use std::fmt;
+
+/// Longest retention that can be configured, about ten years.
+pub const MAX_RETENTION_DAYS: i64 = 3650;
+const DEFAULT_PURGE_INTERVAL_SECS: u64 = 3600;
+
+#[derive(Debug)]
+pub enum RetentionError {
+    InvalidDays,
+    NotFound,
+    Database,
+}
+
+impl fmt::Display for RetentionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetentionError::InvalidDays => write!(
+                f,
+                "Retention must be between 1 and {} days, or null to inherit the default",
+                MAX_RETENTION_DAYS
+            ),
+            RetentionError::NotFound => write!(f, "Not found"),
+            RetentionError::Database => write!(f, "Failed to update retention"),
+        }
+    }
+}
+
+impl ResponseError for RetentionError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            RetentionError::InvalidDays => StatusCode::BAD_REQUEST,
+            RetentionError::NotFound => StatusCode::NOT_FOUND,
+            RetentionError::Database => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+/// Retention of applications whose organization has no default, from `LOG_RETENTION_DAYS`.
+/// Unset, logs are kept forever.
+pub fn server_retention_days() -> Option<i64> {
+    env::var("LOG_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+}
+
+/// Seconds between two purges of expired logs, from `RETENTION_PURGE_INTERVAL_SECS`.
+pub fn purge_interval_secs() -> u64 {
+    env::var("RETENTION_PURGE_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_PURGE_INTERVAL_SECS)
+}
+
+pub fn validate_days(days: Option<i64>) -> Result<Option<i64>, RetentionError> {
+    match days {
+        Some(days) if !(1..=MAX_RETENTION_DAYS).contains(&days) => Err(RetentionError::InvalidDays),
+        days => Ok(days),
+    }
+}
+
+/// Retention that applies to an application: its own, else its organization's default,
+/// else the server's.
+pub fn effective_retention(
+    app: &Application,
+    org: Option<&Organization>,
+) -> Option<(i64, RetentionSource)> {
+    app.retention_days
+        .map(|days| (days, RetentionSource::Application))
+        .or_else(|| {
+            org.and_then(|org| org.default_retention_days)
+                .map(|days| (days, RetentionSource::Organization))
+        })
+        .or_else(|| server_retention_days().map(|days| (days, RetentionSource::Server)))
+}
+
+pub async fn set_application_retention(
+    data: &MongoRepo,
+    app_id: ObjectId,
+    days: Option<i64>,
+) -> Result<(), RetentionError> {
+    let days = validate_days(days)?;
+    match data.set_application_retention(app_id, days).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(RetentionError::NotFound),
+        Err(_) => Err(RetentionError::Database),
+    }
+}
+
+pub async fn set_org_default_retention(
+    data: &MongoRepo,
+    org_id: ObjectId,
+    days: Option<i64>,
+) -> Result<(), RetentionError> {
+    let days = validate_days(days)?;
+    match data.set_org_default_retention(org_id, days).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(RetentionError::NotFound),
+        Err(_) => Err(RetentionError::Database),
+    }
+}
+
+/// Deletes the logs that outlived the retention of their application, archiving them first
+/// when `LOG_ARCHIVE` is set, and the restored logs whose time is up. Returns how many.
+pub async fn purge_expired(data: &MongoRepo) -> Result<u64, String> {
+    // A broken archive configuration must not turn archiving into plain deletion
+    let archive = archive_service::config()?;
+    let now = Utc::now();
+    let mut orgs: HashMap<ObjectId, Option<Organization>> = HashMap::new();
+    let mut purged = 0;
+
+    let apps = data
+        .list_applications(None)
+        .await
+        .map_err(|e| e.to_string())?;
+    for app in apps {
+        let (Some(app_id), Some(org_id)) = (app.id, app.organization_id) else {
+            continue;
+        };
+        if let Some(archive) = &archive {
+            let before = now - Duration::days(archive.restore_days);
+            purged += data
+                .purge_restored_logs(app_id, before)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        let org = match orgs.entry(org_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                data.get_organization_by_id(org_id)
+                    .await
+                    .map_err(|e| e.to_string())?,
+            ),
+        };
+        let Some((days, _)) = effective_retention(&app, org.as_ref()) else {
+            continue;
+        };
+        let cutoff = now - Duration::days(days);
+        let Some(before) = log_search::id_at(cutoff.timestamp()) else {
+            continue;
+        };
+
+        let deleted = match &archive {
+            Some(archive) => {
+                match archive_service::archive_expired(data, archive, org_id, app_id, before).await
+                {
+                    Ok(archived) => archived,
+                    Err(e) => {
+                        // The logs stay until the next run; other applications go on
+                        log::error!(
+                            "Failed to archive expired logs for App ID {}: {}",
+                            app_id,
+                            e
+                        );
+                        continue;
+                    }
+                }
+            }
+            None => data
+                .purge_logs_before(app_id, before)
+                .await
+                .map_err(|e| e.to_string())?,
+        };
+        // Inference jobs and versions, their feedback and the signatures go with their logs;
+        // archives keep the version the log showed
+        data.purge_inference_jobs_before(app_id, before)
+            .await
+            .map_err(|e| e.to_string())?;
+        data.purge_rag_inferences_before(app_id, before)
+            .await
+            .map_err(|e| e.to_string())?;
+        data.purge_inference_feedback_before(app_id, before)
+            .await
+            .map_err(|e| e.to_string())?;
+        data.purge_log_signatures_before(app_id, before)
+            .await
+            .map_err(|e| e.to_string())?;
+        // Whole hours only, so minute and hour counters keep adding up to the same totals
+        let hour = RollupGranularity::Hour.bucket(cutoff.timestamp_millis());
+        if let Some(hour) = Utc.timestamp_millis_opt(hour).single() {
+            data.delete_rollups(Some(app_id), hour)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        if deleted > 0 {
+            log::info!(
+                "Purged {} logs older than {} days for App ID: {}",
+                deleted,
+                days,
+                app_id
+            );
+        }
+        purged += deleted;
+    }
+    Ok(purged)
+}
+
+/// Purges expired logs every `RETENTION_PURGE_INTERVAL_SECS` in the background.
+pub fn spawn_purge_job(data: MongoRepo) {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(purge_interval_secs()));
+        loop {
+            interval.tick().await;
+            if let Err(e) = purge_expired(&data).await {
+                log::error!("Failed to purge expired logs: {}", e);
+            }
+        }
+    });
+}
+
+/// Storage and expiry of every application of an organization.
+pub async fn storage_report(
+    data: &MongoRepo,
+    org: &Organization,
+) -> Result<Vec<AppStorage>, mongodb::error::Error> {
+    let average_size = data.average_log_size().await?.unwrap_or(0.0);
+
+    let mut report = Vec::new();
+    for app in data.list_applications(org.id).await? {
+        let Some(app_id) = app.id else {
+            continue;
+        };
+        let (log_count, oldest) = data.application_log_stats(app_id).await?;
+        let retention = effective_retention(&app, Some(org));
+        let oldest_log_at: Option<DateTime<Utc>> = oldest.and_then(|id| {
+            Utc.timestamp_millis_opt(id.timestamp().timestamp_millis())
+                .single()
+        });
+
+        report.push(AppStorage {
+            application_id: app_id.to_hex(),
+            application_name: app.application_name,
+            retention_days: retention.map(|(days, _)| days),
+            retention_source: retention.map(|(_, source)| source),
+            log_count,
+            estimated_bytes: (log_count as f64 * average_size) as u64,
+            oldest_log_at: oldest_log_at.map(|at| at.to_rfc3339()),
+            next_expiry_at: oldest_log_at
+                .zip(retention)
+                .map(|(at, (days, _))| (at + Duration::days(days)).to_rfc3339()),
+        });
+    }
+    Ok(report)
+}
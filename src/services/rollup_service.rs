@@ -0,0 +1,253 @@
+use crate::db::MongoRepo;
+use crate::models::log::LogPayload;
+use crate::models::rollup::{RollupCount, RollupGranularity, RollupKey};
+use crate::services::fingerprint_service;
+use crate::services::log_search;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use futures_util::stream::TryStreamExt;
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const DEFAULT_FLUSH_INTERVAL_SECS: u64 = 10;
+/// Pending counters a rebuild accumulates before writing them.
+const REBUILD_CHUNK_KEYS: usize = 50_000;
+
+/// Seconds between two flushes of the rollup buffer, from `ROLLUP_FLUSH_INTERVAL_SECS`.
+pub fn flush_interval_secs() -> u64 {
+    env::var("ROLLUP_FLUSH_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_FLUSH_INTERVAL_SECS)
+}
+
+/// Per-minute counters of the logs stored since the last flush. Ingest only touches memory;
+/// the flusher adds the counters to the minute and hour rollups in Mongo.
+#[derive(Clone, Default)]
+pub struct RollupBuffer {
+    pending: Arc<Mutex<HashMap<RollupKey, RollupCount>>>,
+}
+
+impl RollupBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a stored log in the minute it was received.
+    pub async fn record(&self, log: &LogPayload) {
+        let (Some(id), Some(org_id), Some(app_id)) =
+            (log.id, log.organization_id, log.application_id)
+        else {
+            return;
+        };
+        let entry = rollup_entry(
+            id,
+            org_id,
+            app_id,
+            &log.error,
+            log.level.map(|level| level.as_str().to_string()),
+            log.release.clone(),
+        );
+        add(&mut *self.pending.lock().await, entry);
+    }
+
+    /// Writes the pending counters to the minute and hour rollups. Counters that could not be
+    /// written are kept for the next flush.
+    pub async fn flush(&self, data: &MongoRepo) -> Result<(), mongodb::error::Error> {
+        let pending = std::mem::take(&mut *self.pending.lock().await);
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut hours: HashMap<RollupKey, RollupCount> = HashMap::new();
+        for (key, count) in &pending {
+            let hour = RollupKey {
+                bucket: RollupGranularity::Hour.bucket(key.bucket),
+                ..key.clone()
+            };
+            add(&mut hours, (hour, count.clone()));
+        }
+
+        let mut failed = Vec::new();
+        let mut last_error = None;
+        for (key, count) in pending {
+            if let Err(e) = data
+                .increment_rollup(RollupGranularity::Minute, &key, &count)
+                .await
+            {
+                last_error = Some(e);
+                failed.push((key, count));
+            }
+        }
+        // Hour counters are only written for minutes that made it, so both stay in step
+        for (key, count) in &failed {
+            let hour = RollupKey {
+                bucket: RollupGranularity::Hour.bucket(key.bucket),
+                ..key.clone()
+            };
+            if let Some(total) = hours.get_mut(&hour) {
+                total.count -= count.count;
+            }
+        }
+        for (key, count) in hours.into_iter().filter(|(_, count)| count.count > 0) {
+            if let Err(e) = data
+                .increment_rollup(RollupGranularity::Hour, &key, &count)
+                .await
+            {
+                log::error!("Failed to update hourly rollup {:?}: {}", key, e);
+                last_error = Some(e);
+            }
+        }
+
+        if !failed.is_empty() {
+            let mut pending = self.pending.lock().await;
+            for entry in failed {
+                add(&mut pending, entry);
+            }
+        }
+        last_error.map_or(Ok(()), Err)
+    }
+
+    /// Flushes the buffer every `ROLLUP_FLUSH_INTERVAL_SECS` in the background. Rollups count
+    /// every log received from the next minute on, unless they already did.
+    pub async fn spawn_flusher(&self, data: MongoRepo) {
+        let start = Utc::now() + Duration::minutes(1);
+        let start = minute_start(start);
+        if let Err(e) = data.set_rollup_coverage(None, start, false).await {
+            log::error!("Failed to record rollup coverage: {}", e);
+        }
+
+        let buffer = self.clone();
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(std::time::Duration::from_secs(flush_interval_secs()));
+            loop {
+                interval.tick().await;
+                if let Err(e) = buffer.flush(&data).await {
+                    log::error!("Failed to flush log rollups: {}", e);
+                }
+            }
+        });
+    }
+}
+
+fn rollup_entry(
+    id: ObjectId,
+    org_id: ObjectId,
+    app_id: ObjectId,
+    error: &str,
+    level: Option<String>,
+    release: Option<String>,
+) -> (RollupKey, RollupCount) {
+    // Ids are generated on ingest, so their timestamp is the time the log was received
+    let received = id.timestamp().timestamp_millis();
+    let key = RollupKey {
+        application_id: app_id,
+        bucket: RollupGranularity::Minute.bucket(received),
+        message_hash: fingerprint_service::message_hash(error),
+        level,
+        release,
+    };
+    let count = RollupCount {
+        organization_id: org_id,
+        message: fingerprint_service::issue_title(error),
+        count: 1,
+    };
+    (key, count)
+}
+
+fn add(pending: &mut HashMap<RollupKey, RollupCount>, (key, count): (RollupKey, RollupCount)) {
+    pending
+        .entry(key)
+        .and_modify(|total| total.count += count.count)
+        .or_insert(count);
+}
+
+fn minute_start(time: DateTime<Utc>) -> DateTime<Utc> {
+    let ms = RollupGranularity::Minute.bucket(time.timestamp_millis());
+    Utc.timestamp_millis_opt(ms).single().unwrap_or(time)
+}
+
+/// What a rebuild recomputed.
+#[derive(Debug)]
+pub struct RebuildSummary {
+    pub logs: u64,
+    pub until: DateTime<Utc>,
+    pub complete: bool, // Whether live counting covers everything since `until`
+}
+
+/// Recomputes the rollups of one application, or of all of them, from the raw logs.
+///
+/// Only the hours before the current one (less a margin for the buffers of running servers)
+/// are rebuilt; later logs are left to the live counters. The rollups are marked complete
+/// when live counting started before that point, otherwise a later rebuild is needed.
+pub async fn rebuild(
+    data: &MongoRepo,
+    app_id: Option<ObjectId>,
+) -> Result<RebuildSummary, mongodb::error::Error> {
+    let margin = Duration::minutes(5).max(Duration::seconds(3 * flush_interval_secs() as i64));
+    let until = Utc::now() - margin;
+    let until = Utc
+        .timestamp_millis_opt(RollupGranularity::Hour.bucket(until.timestamp_millis()))
+        .single()
+        .unwrap_or(until);
+
+    data.delete_rollups(app_id, until).await?;
+
+    let mut filter = Document::new();
+    if let Some(app_id) = app_id {
+        filter.insert("application_id", app_id);
+    }
+    if let Some(until_id) = log_search::id_at(until.timestamp()) {
+        filter.insert("_id", doc! { "$lt": until_id });
+    }
+    let mut cursor = data.find_rollup_sources(filter).await?;
+
+    let buffer = RollupBuffer::new();
+    let mut logs = 0;
+    while let Some(log) = cursor.try_next().await? {
+        let (Ok(id), Ok(org_id), Ok(log_app_id)) = (
+            log.get_object_id("_id"),
+            log.get_object_id("organization_id"),
+            log.get_object_id("application_id"),
+        ) else {
+            continue;
+        };
+        let entry = rollup_entry(
+            id,
+            org_id,
+            log_app_id,
+            log.get_str("error").unwrap_or_default(),
+            log.get_str("level").ok().map(str::to_string),
+            log.get_str("release").ok().map(str::to_string),
+        );
+        let pending = {
+            let mut pending = buffer.pending.lock().await;
+            add(&mut pending, entry);
+            pending.len()
+        };
+        logs += 1;
+
+        if pending >= REBUILD_CHUNK_KEYS {
+            buffer.flush(data).await?;
+        }
+    }
+    buffer.flush(data).await?;
+
+    // Live counting must cover everything since `until` for the rollups to be complete
+    let live_since = data.rollup_coverage(app_id).await?;
+    let complete = live_since.is_some_and(|since| since <= until);
+    if complete {
+        data.set_rollup_coverage(app_id, DateTime::<Utc>::default(), true)
+            .await?;
+    }
+
+    Ok(RebuildSummary {
+        logs,
+        until,
+        complete,
+    })
+}
@@ -0,0 +1,223 @@
+use crate::db::{self, MongoRepo};
+use crate::extractors::tenant::AuthenticatedApp;
+use crate::models::log::LogListQuery;
+use crate::models::membership::OrgRole;
+use crate::models::saved_search::{
+    CreateSavedSearchPayload, SavedSearch, UpdateSavedSearchPayload,
+};
+use crate::services::log_search::{self, LogSearchError};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use chrono::Utc;
+use mongodb::bson::{self, doc, oid::ObjectId};
+use std::fmt;
+
+const NAME_MAX_CHARS: usize = 100;
+
+/// Why a saved search operation was rejected.
+#[derive(Debug)]
+pub enum SavedSearchError {
+    SignInRequired,
+    InvalidName,
+    InvalidFilters(LogSearchError),
+    NotFound,
+    NotOwner,
+    DuplicateName,
+    Database,
+}
+
+impl fmt::Display for SavedSearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SavedSearchError::SignInRequired => {
+                write!(
+                    f,
+                    "Saved searches belong to users; sign in to the dashboard"
+                )
+            }
+            SavedSearchError::InvalidName => write!(
+                f,
+                "Name must be between 1 and {} characters",
+                NAME_MAX_CHARS
+            ),
+            SavedSearchError::InvalidFilters(e) => write!(f, "{}", e),
+            SavedSearchError::NotFound => write!(f, "Saved search not found"),
+            SavedSearchError::NotOwner => {
+                write!(f, "Only the owner of a saved search can change it")
+            }
+            SavedSearchError::DuplicateName => {
+                write!(f, "You already have a saved search with this name")
+            }
+            SavedSearchError::Database => write!(f, "Failed to access saved searches"),
+        }
+    }
+}
+
+impl ResponseError for SavedSearchError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SavedSearchError::SignInRequired | SavedSearchError::NotOwner => StatusCode::FORBIDDEN,
+            SavedSearchError::InvalidName => StatusCode::BAD_REQUEST,
+            SavedSearchError::InvalidFilters(e) => e.status_code(),
+            SavedSearchError::NotFound => StatusCode::NOT_FOUND,
+            SavedSearchError::DuplicateName => StatusCode::CONFLICT,
+            SavedSearchError::Database => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            // Keeps the `position` of query errors
+            SavedSearchError::InvalidFilters(e) => e.error_response(),
+            _ => HttpResponse::build(self.status_code()).json(serde_json::json!({
+                "error": self.to_string()
+            })),
+        }
+    }
+}
+
+fn database_error(error: mongodb::error::Error) -> SavedSearchError {
+    if db::is_duplicate_key(&error) {
+        SavedSearchError::DuplicateName
+    } else {
+        SavedSearchError::Database
+    }
+}
+
+/// Saved searches are per user, so only dashboard sessions can use them.
+pub fn user_id(auth: &AuthenticatedApp) -> Result<ObjectId, SavedSearchError> {
+    auth.access
+        .user_id()
+        .ok_or(SavedSearchError::SignInRequired)
+}
+
+fn validate_name(name: &str) -> Result<String, SavedSearchError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > NAME_MAX_CHARS {
+        return Err(SavedSearchError::InvalidName);
+    }
+    Ok(name.to_string())
+}
+
+/// Checks that the filters compile, so a saved search always runs. Cursors are page state and
+/// are not saved.
+fn validate_filters(
+    app_id: ObjectId,
+    mut filters: LogListQuery,
+) -> Result<LogListQuery, SavedSearchError> {
+    filters.cursor = None;
+    log_search::build_filter(app_id, &filters).map_err(SavedSearchError::InvalidFilters)?;
+    Ok(filters)
+}
+
+pub async fn list(
+    data: &MongoRepo,
+    auth: &AuthenticatedApp,
+) -> Result<Vec<SavedSearch>, SavedSearchError> {
+    let user_id = user_id(auth)?;
+    data.list_saved_searches(auth.app_id(), user_id)
+        .await
+        .map_err(database_error)
+}
+
+pub async fn get(
+    data: &MongoRepo,
+    auth: &AuthenticatedApp,
+    search_id: ObjectId,
+) -> Result<SavedSearch, SavedSearchError> {
+    let user_id = user_id(auth)?;
+    data.get_saved_search(auth.app_id(), user_id, search_id)
+        .await
+        .map_err(database_error)?
+        .ok_or(SavedSearchError::NotFound)
+}
+
+pub async fn create(
+    data: &MongoRepo,
+    auth: &AuthenticatedApp,
+    payload: CreateSavedSearchPayload,
+) -> Result<SavedSearch, SavedSearchError> {
+    let user_id = user_id(auth)?;
+    let now = Utc::now();
+    let search = SavedSearch {
+        id: Some(ObjectId::new()),
+        organization_id: auth.org_id(),
+        application_id: auth.app_id(),
+        owner_id: user_id,
+        name: validate_name(&payload.name)?,
+        filters: validate_filters(auth.app_id(), payload.filters)?,
+        shared: payload.shared,
+        pinned_by: Vec::new(),
+        created_at: now,
+        updated_at: now,
+    };
+    data.create_saved_search(search.clone())
+        .await
+        .map_err(database_error)?;
+    Ok(search)
+}
+
+/// Updates a search. Only its owner can, shared or not.
+pub async fn update(
+    data: &MongoRepo,
+    auth: &AuthenticatedApp,
+    search_id: ObjectId,
+    payload: UpdateSavedSearchPayload,
+) -> Result<SavedSearch, SavedSearchError> {
+    let search = get(data, auth, search_id).await?;
+    let user_id = user_id(auth)?;
+    if search.owner_id != user_id {
+        return Err(SavedSearchError::NotOwner);
+    }
+
+    let mut fields = doc! {};
+    if let Some(name) = payload.name {
+        fields.insert("name", validate_name(&name)?);
+    }
+    if let Some(filters) = payload.filters {
+        let filters = validate_filters(auth.app_id(), filters)?;
+        let filters = bson::to_bson(&filters).map_err(|_| SavedSearchError::Database)?;
+        fields.insert("filters", filters);
+    }
+    if let Some(shared) = payload.shared {
+        fields.insert("shared", shared);
+    }
+
+    data.update_saved_search(auth.app_id(), user_id, search_id, fields)
+        .await
+        .map_err(database_error)?
+        .ok_or(SavedSearchError::NotFound)
+}
+
+/// Deletes a search. Admins can also delete searches shared with the organization.
+pub async fn delete(
+    data: &MongoRepo,
+    auth: &AuthenticatedApp,
+    search_id: ObjectId,
+) -> Result<(), SavedSearchError> {
+    let search = get(data, auth, search_id).await?;
+    let user_id = user_id(auth)?;
+    if search.owner_id != user_id && auth.require_role(OrgRole::Admin).is_err() {
+        return Err(SavedSearchError::NotOwner);
+    }
+
+    match data.delete_saved_search(auth.app_id(), search_id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(SavedSearchError::NotFound),
+        Err(e) => Err(database_error(e)),
+    }
+}
+
+/// Pins a visible search as the user's default view of the application, or unpins it.
+pub async fn set_default(
+    data: &MongoRepo,
+    auth: &AuthenticatedApp,
+    search_id: ObjectId,
+    pinned: bool,
+) -> Result<SavedSearch, SavedSearchError> {
+    get(data, auth, search_id).await?;
+    let user_id = user_id(auth)?;
+    data.pin_saved_search(auth.app_id(), user_id, search_id, pinned)
+        .await
+        .map_err(database_error)?;
+    get(data, auth, search_id).await
+}
@@ -0,0 +1,306 @@
+use crate::db::MongoRepo;
+use crate::models::log::LogPayload;
+use crate::models::log_signature::{LogSignature, SimilarLogsQuery};
+use crate::services::{fingerprint_service, traceback_parser};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use mongodb::bson::oid::ObjectId;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Hash functions in a signature.
+const NUM_HASHES: usize = 64;
+/// Rows per band: two logs become candidates when all rows of one band agree, which is
+/// likely from a similarity of about 0.6 on.
+const BAND_ROWS: usize = 4;
+/// Signatures compared per lookup, the most recent candidates first.
+const MAX_CANDIDATES: i64 = 500;
+
+#[derive(Debug)]
+pub enum SimilarityError {
+    LogNotFound,
+    Database,
+}
+
+impl fmt::Display for SimilarityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimilarityError::LogNotFound => write!(f, "Log not found"),
+            SimilarityError::Database => write!(f, "Failed to look up similar logs"),
+        }
+    }
+}
+
+impl ResponseError for SimilarityError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SimilarityError::LogNotFound => StatusCode::NOT_FOUND,
+            SimilarityError::Database => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+impl From<mongodb::error::Error> for SimilarityError {
+    fn from(_: mongodb::error::Error) -> Self {
+        SimilarityError::Database
+    }
+}
+
+/// What a log is compared on: the words and word pairs of its normalized error, its top
+/// frames and the route template of its URL.
+fn features(log: &LogPayload) -> HashSet<String> {
+    let error = fingerprint_service::normalize_message(&log.error).to_lowercase();
+    let words: Vec<&str> = error
+        .split(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '<' | '>')))
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let mut features: HashSet<String> = words.iter().map(|w| format!("w:{}", w)).collect();
+    features.extend(
+        words
+            .windows(2)
+            .map(|pair| format!("p:{} {}", pair[0], pair[1])),
+    );
+    let frames = match &log.frames {
+        Some(frames) => fingerprint_service::top_frames(frames),
+        None => fingerprint_service::top_frames(&traceback_parser::parse(&log.traceback)),
+    };
+    features.extend(frames.iter().map(|frame| format!("f:{}", frame)));
+    if !features.is_empty() {
+        features.insert(format!(
+            "r:{}",
+            fingerprint_service::route_template(&log.url)
+        ));
+    }
+    features
+}
+
+/// FNV-1a, stable across processes and releases unlike `std`'s hasher.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Signature of a stored log, `None` if it has nothing to compare on.
+pub fn signature(log: &LogPayload) -> Option<LogSignature> {
+    let features = features(log);
+    if features.is_empty() {
+        return None;
+    }
+
+    let mut minhash = [u64::MAX; NUM_HASHES];
+    for feature in &features {
+        let hash = fnv1a(feature.as_bytes());
+        for (i, min) in minhash.iter_mut().enumerate() {
+            *min = (*min).min(splitmix64(hash ^ splitmix64(i as u64)));
+        }
+    }
+    let bands = minhash
+        .chunks(BAND_ROWS)
+        .enumerate()
+        .map(|(band, rows)| {
+            let key = rows
+                .iter()
+                .fold(splitmix64(band as u64), |key, row| splitmix64(key ^ row));
+            key as i64
+        })
+        .collect();
+
+    Some(LogSignature {
+        log_id: log.id?,
+        organization_id: log.organization_id?,
+        application_id: log.application_id?,
+        minhash: minhash.iter().map(|&h| h as i64).collect(),
+        bands,
+    })
+}
+
+/// Estimated Jaccard similarity of two signatures: the share of hash functions that agree.
+fn similarity(a: &[i64], b: &[i64]) -> f64 {
+    let agreeing = a.iter().zip(b).filter(|(x, y)| x == y).count();
+    agreeing as f64 / NUM_HASHES as f64
+}
+
+/// Stores the signatures of newly stored logs. Failures are logged: logs without one are
+/// only left out of similar log lookups.
+pub async fn store(data: &MongoRepo, logs: &[&LogPayload]) {
+    let signatures: Vec<LogSignature> = logs.iter().filter_map(|log| signature(log)).collect();
+    let count = signatures.len();
+    if let Err(e) = data.insert_log_signatures(signatures).await {
+        log::error!("Failed to store {} log signatures: {}", count, e);
+    }
+}
+
+/// Logs of the application received before `log_id` that look most like it, most similar
+/// first. Logs stored before signatures existed are not found, though the log looked up gets
+/// its signature on the way.
+pub async fn find_similar(
+    data: &MongoRepo,
+    app_id: ObjectId,
+    log_id: ObjectId,
+    query: &SimilarLogsQuery,
+) -> Result<Vec<(f64, LogPayload)>, SimilarityError> {
+    let target = match data.get_log_signature(app_id, log_id).await? {
+        Some(target) => target,
+        None => {
+            let log = data
+                .get_log(app_id, log_id)
+                .await?
+                .ok_or(SimilarityError::LogNotFound)?;
+            let Some(target) = signature(&log) else {
+                return Ok(Vec::new());
+            };
+            data.insert_log_signatures(vec![target.clone()]).await?;
+            target
+        }
+    };
+
+    let candidates = data
+        .find_signature_candidates(app_id, log_id, &target.bands, MAX_CANDIDATES)
+        .await?;
+    let min_similarity = query.min_similarity();
+    let mut scored: Vec<(f64, ObjectId)> = candidates
+        .iter()
+        .map(|c| (similarity(&target.minhash, &c.minhash), c.log_id))
+        .filter(|(score, _)| *score >= min_similarity)
+        .collect();
+    if query.resolved() {
+        let ids: Vec<ObjectId> = scored.iter().map(|(_, id)| *id).collect();
+        let resolved: HashSet<ObjectId> = data
+            .resolved_log_ids(app_id, &ids)
+            .await?
+            .into_iter()
+            .collect();
+        scored.retain(|(_, id)| resolved.contains(id));
+    }
+    // Most similar first, the most recent of equally similar ones first
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0).then(b.1.cmp(&a.1)));
+    scored.truncate(query.limit());
+
+    let ids: Vec<ObjectId> = scored.iter().map(|(_, id)| *id).collect();
+    let mut logs: HashMap<ObjectId, LogPayload> = data
+        .get_logs_by_ids(app_id, &ids)
+        .await?
+        .into_iter()
+        .filter_map(|log| Some((log.id?, log)))
+        .collect();
+    Ok(scored
+        .into_iter()
+        .filter_map(|(score, id)| Some((score, logs.remove(&id)?)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACEBACK: &str = "Traceback (most recent call last):
+  File \"/usr/lib/python3.11/site-packages/flask/app.py\", line 1484, in dispatch_request
+  File \"/app/views.py\", line 42, in get_user
+KeyError: 42";
+
+    fn log(error: &str, traceback: &str, url: &str) -> LogPayload {
+        let mut log: LogPayload = serde_json::from_value(serde_json::json!({
+            "error": error,
+            "traceback": traceback,
+            "url": url,
+            "method": "GET",
+        }))
+        .unwrap();
+        log.id = Some(ObjectId::new());
+        log.organization_id = Some(ObjectId::new());
+        log.application_id = Some(ObjectId::new());
+        log
+    }
+
+    fn shares_band(a: &LogSignature, b: &LogSignature) -> bool {
+        a.bands.iter().zip(&b.bands).any(|(x, y)| x == y)
+    }
+
+    #[test]
+    fn hashes_are_stable() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(splitmix64(0), 0xe220_a839_7b1d_cdaf);
+    }
+
+    #[test]
+    fn features_cover_words_frames_and_route() {
+        let log = log(
+            "KeyError: user 42 not found",
+            TRACEBACK,
+            "https://example.com/users/42?tab=1",
+        );
+        let features = features(&log);
+
+        for feature in [
+            "w:keyerror",
+            "w:<n>",
+            "p:keyerror user",
+            "p:user <n>",
+            "f:/app/views.py:get_user",
+            "r:/users/{id}",
+        ] {
+            assert!(features.contains(feature), "missing {}", feature);
+        }
+        // Library frames are left out once the traceback has frames of the application
+        assert!(!features.iter().any(|f| f.contains("flask")));
+    }
+
+    #[test]
+    fn similar_logs_share_a_band() {
+        let a = signature(&log(
+            "KeyError: user 42 not found in cache",
+            TRACEBACK,
+            "/users/42",
+        ))
+        .unwrap();
+        let b = signature(&log("KeyError: user 7 not found", TRACEBACK, "/users/7")).unwrap();
+        let other = signature(&log(
+            "ConnectionResetError: peer closed the connection",
+            "",
+            "/health",
+        ))
+        .unwrap();
+
+        assert_eq!(a.minhash.len(), NUM_HASHES);
+        assert_eq!(a.bands.len(), NUM_HASHES / BAND_ROWS);
+        assert!(shares_band(&a, &b));
+        assert!(similarity(&a.minhash, &b.minhash) >= 0.5);
+        assert!(similarity(&a.minhash, &other.minhash) < 0.2);
+    }
+
+    #[test]
+    fn same_error_gives_the_same_signature() {
+        let a = signature(&log("KeyError: user 42 not found", TRACEBACK, "/users/42")).unwrap();
+        let b = signature(&log("KeyError: user 7 not found", TRACEBACK, "/users/7")).unwrap();
+
+        assert_eq!(a.minhash, b.minhash);
+        assert_eq!(a.bands, b.bands);
+        assert_eq!(similarity(&a.minhash, &b.minhash), 1.0);
+    }
+
+    #[test]
+    fn nothing_to_compare_gives_no_signature() {
+        assert!(features(&log("", "", "/users/42")).is_empty());
+        assert!(signature(&log("", "", "/users/42")).is_none());
+        assert!(signature(&log("  ...  ", "", "")).is_none());
+
+        let mut unsaved = log("KeyError: user 42 not found", "", "/users/42");
+        unsaved.id = None;
+        assert!(signature(&unsaved).is_none());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::models::application::Application;
+use crate::models::organization::Organization;
+use mongodb::bson::oid::ObjectId;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+const DEFAULT_TTL_SECS: u64 = 30;
+const MAX_ENTRIES: usize = 10_000;
+
+/// A resolved tenant lookup: the organization, the application if one was requested,
+/// and the access the credentials grant.
+#[derive(Debug, Clone)]
+pub struct CachedTenant {
+    pub org: Organization,
+    pub app: Option<Application>,
+    pub access: crate::extractors::tenant::Access,
+}
+
+struct CacheEntry {
+    tenant: CachedTenant,
+    expires_at: Instant,
+}
+
+/// Short-lived cache of authenticated tenant lookups, so the ingest path does not hit
+/// Mongo for the organization and application on every request.
+/// Entries are keyed by a hash of the credentials, never the credentials themselves.
+#[derive(Clone)]
+pub struct TenantCache {
+    entries: Arc<RwLock<HashMap<[u8; 32], CacheEntry>>>,
+    ttl: Duration,
+}
+
+impl Default for TenantCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TenantCache {
+    /// Creates a new cache. The TTL comes from `TENANT_CACHE_TTL_SECS` (default 30s, 0 disables caching).
+    pub fn new() -> Self {
+        let ttl = env::var("TENANT_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_TTL_SECS);
+        Self {
+            entries: Arc::new(RwLock::new(HashMap::new())),
+            ttl: Duration::from_secs(ttl),
+        }
+    }
+
+    /// Builds a cache key from the parts of a credential set.
+    pub fn key(parts: &[&str]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update(part.as_bytes());
+            hasher.update([0u8]);
+        }
+        hasher.finalize().into()
+    }
+
+    pub async fn get(&self, key: &[u8; 32]) -> Option<CachedTenant> {
+        let entries = self.entries.read().await;
+        entries
+            .get(key)
+            .filter(|entry| entry.expires_at > Instant::now())
+            .map(|entry| entry.tenant.clone())
+    }
+
+    pub async fn insert(&self, key: [u8; 32], tenant: CachedTenant) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        let mut entries = self.entries.write().await;
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, entry| entry.expires_at > now);
+        }
+        if entries.len() < MAX_ENTRIES {
+            entries.insert(
+                key,
+                CacheEntry {
+                    tenant,
+                    expires_at: now + self.ttl,
+                },
+            );
+        }
+    }
+
+    /// Drops every cached lookup of an organization, e.g. after its secret was rotated.
+    pub async fn invalidate_org(&self, org_id: ObjectId) {
+        let mut entries = self.entries.write().await;
+        entries.retain(|_, entry| entry.tenant.org.id != Some(org_id));
+    }
+
+    /// Drops every cached lookup of an application, e.g. after one of its keys was revoked.
+    pub async fn invalidate_app(&self, app_id: ObjectId) {
+        let mut entries = self.entries.write().await;
+        entries.retain(|_, entry| {
+            entry.tenant.app.as_ref().and_then(|app| app.id) != Some(app_id)
+        });
+    }
+}
@@ -0,0 +1,456 @@
+use crate::models::log::StackFrame;
+
+/// Traceback formats the parser understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TracebackFormat {
+    Python,
+    V8,
+    Jvm,
+    Rust,
+}
+
+/// Parses a raw traceback into frames, innermost first. Unknown formats yield no frames.
+pub fn parse(traceback: &str) -> Vec<StackFrame> {
+    match detect_format(traceback) {
+        Some(TracebackFormat::Python) => parse_python(traceback),
+        Some(TracebackFormat::V8) => parse_at_lines(traceback, parse_v8_frame),
+        Some(TracebackFormat::Jvm) => parse_at_lines(traceback, parse_jvm_frame),
+        Some(TracebackFormat::Rust) => parse_rust(traceback),
+        None => Vec::new(),
+    }
+}
+
+pub fn detect_format(traceback: &str) -> Option<TracebackFormat> {
+    let mut at_lines = traceback
+        .lines()
+        .map(str::trim)
+        .filter_map(|l| l.strip_prefix("at "));
+
+    if traceback.contains("Traceback (most recent call last)")
+        || traceback
+            .lines()
+            .any(|l| l.trim_start().starts_with("File \""))
+    {
+        Some(TracebackFormat::Python)
+    } else if traceback.contains("stack backtrace:") || traceback.contains("' panicked at ") {
+        Some(TracebackFormat::Rust)
+    } else if let Some(first) = at_lines.next() {
+        // JVM frames are `pkg.Class.method(File.java:12)`, V8 puts a space before the parens
+        let jvm =
+            first.ends_with(')') && first.find('(').is_some_and(|p| !first[..p].contains(' '));
+        Some(if jvm {
+            TracebackFormat::Jvm
+        } else {
+            TracebackFormat::V8
+        })
+    } else {
+        None
+    }
+}
+
+/// `  File "/app/main.py", line 10, in handler`. Python prints the innermost frame last, and
+/// for chained exceptions only the frames of the last traceback are kept.
+fn parse_python(traceback: &str) -> Vec<StackFrame> {
+    let mut frames = Vec::new();
+    for line in traceback.lines().map(str::trim) {
+        if line.starts_with("Traceback (most recent call last)") {
+            frames.clear();
+            continue;
+        }
+        let Some(rest) = line.strip_prefix("File \"") else {
+            continue;
+        };
+        let Some(end) = rest.find('"') else {
+            continue;
+        };
+        let file = &rest[..end];
+        let mut line_no = None;
+        let mut function = None;
+        for part in rest[end + 1..].split(", ") {
+            let part = part.trim().trim_start_matches(',').trim();
+            if let Some(n) = part.strip_prefix("line ") {
+                line_no = n.trim().parse().ok();
+            } else if let Some(f) = part.strip_prefix("in ") {
+                function = Some(f.trim().to_string());
+            }
+        }
+        frames.push(StackFrame {
+            file: Some(file.to_string()),
+            function,
+            line: line_no,
+            column: None,
+            in_app: python_in_app(file),
+        });
+    }
+    frames.reverse();
+    frames
+}
+
+fn python_in_app(file: &str) -> bool {
+    !(file.contains("site-packages")
+        || file.contains("dist-packages")
+        || file.starts_with("<frozen")
+        || file.contains("/lib/python"))
+}
+
+/// Collects the `at ...` lines of V8 and JVM traces, which print the innermost frame first.
+/// JVM traces stop at the first `Caused by:` so the frames belong to the thrown exception.
+fn parse_at_lines(traceback: &str, parse_frame: fn(&str) -> StackFrame) -> Vec<StackFrame> {
+    let mut frames = Vec::new();
+    for line in traceback.lines().map(str::trim) {
+        if line.starts_with("Caused by:") && !frames.is_empty() {
+            break;
+        }
+        if let Some(rest) = line.strip_prefix("at ") {
+            frames.push(parse_frame(rest.trim()));
+        }
+    }
+    frames
+}
+
+/// `handler (/app/server.js:10:15)`, `/app/server.js:10:15` or `async Promise.all (index 0)`.
+fn parse_v8_frame(frame: &str) -> StackFrame {
+    let (function, location) = match frame.strip_suffix(')').and_then(|f| f.rsplit_once(" (")) {
+        Some((function, location)) => (Some(function.trim().to_string()), location),
+        None => (None, frame),
+    };
+    let (mut file, line, column) = split_location(location);
+    // `native`, `<anonymous>` and `index 0` are not files
+    if line.is_none() && !location.contains(['/', '\\']) {
+        file = None;
+    }
+    let in_app = file.as_deref().is_some_and(|f| {
+        !f.contains("node_modules")
+            && !f.starts_with("node:")
+            && !f.starts_with("internal/")
+            && f.contains(['/', '\\'])
+    });
+    StackFrame {
+        file,
+        function,
+        line,
+        column,
+        in_app,
+    }
+}
+
+/// `com.example.Service.handle(Service.java:42)`, also `(Native Method)` and `(Unknown Source)`.
+fn parse_jvm_frame(frame: &str) -> StackFrame {
+    let (function, location) = match frame.strip_suffix(')').and_then(|f| f.split_once('(')) {
+        Some((function, location)) => (function, location),
+        None => (frame, ""),
+    };
+    // Drop the module prefix of `java.base/java.lang.Thread.run`
+    let function = function.rsplit('/').next().unwrap_or(function);
+    let (file, line, _) = match location {
+        "" | "Native Method" | "Unknown Source" => (None, None, None),
+        location => split_location(location),
+    };
+    const RUNTIME_PACKAGES: [&str; 7] = [
+        "java.", "javax.", "jdk.", "sun.", "com.sun.", "kotlin.", "scala.",
+    ];
+    StackFrame {
+        file,
+        function: Some(function.to_string()),
+        line,
+        column: None,
+        in_app: !RUNTIME_PACKAGES.iter().any(|p| function.starts_with(p)),
+    }
+}
+
+/// `   2: myapp::handler::h3f2a...` optionally followed by `at ./src/handler.rs:10:5`.
+/// Without a backtrace the `panicked at` location becomes the only frame.
+fn parse_rust(traceback: &str) -> Vec<StackFrame> {
+    let mut frames: Vec<StackFrame> = Vec::new();
+    let mut in_backtrace = false;
+
+    for line in traceback.lines().map(str::trim) {
+        if line.starts_with("stack backtrace:") {
+            in_backtrace = true;
+            continue;
+        }
+        if !in_backtrace {
+            continue;
+        }
+        if let Some(location) = line.strip_prefix("at ") {
+            if let Some(frame) = frames.last_mut() {
+                let (file, line, column) = split_location(location);
+                frame.in_app = file.as_deref().is_some_and(rust_in_app);
+                frame.file = file;
+                frame.line = line;
+                frame.column = column;
+            }
+        } else if let Some((index, function)) = line.split_once(": ") {
+            if index.chars().all(|c| c.is_ascii_digit()) && !index.is_empty() {
+                frames.push(StackFrame {
+                    file: None,
+                    function: Some(strip_rust_hash(function.trim()).to_string()),
+                    line: None,
+                    column: None,
+                    in_app: false,
+                });
+            }
+        }
+    }
+
+    if frames.is_empty() {
+        if let Some(location) = traceback
+            .lines()
+            .find_map(|l| l.split_once("' panicked at ").map(|(_, loc)| loc))
+        {
+            // Older toolchains print `panicked at 'message', src/main.rs:10:5`
+            let location = match location.rsplit_once("', ") {
+                Some((_, path)) if location.starts_with('\'') => path,
+                _ => location,
+            };
+            let (file, line, column) = split_location(location.trim().trim_end_matches(':'));
+            frames.push(StackFrame {
+                file,
+                function: None,
+                line,
+                column,
+                in_app: true,
+            });
+        }
+    }
+    frames
+}
+
+fn rust_in_app(file: &str) -> bool {
+    !(file.starts_with("/rustc/") || file.contains("/.cargo/registry/"))
+}
+
+/// `myapp::main::h1a2b3c4d5e6f7a8b` -> `myapp::main`
+fn strip_rust_hash(function: &str) -> &str {
+    match function.rsplit_once("::h") {
+        Some((name, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            name
+        }
+        _ => function,
+    }
+}
+
+/// Splits `file:line:column` (line and column optional) from the right, so paths and
+/// `file://` URLs containing colons stay intact.
+fn split_location(location: &str) -> (Option<String>, Option<u32>, Option<u32>) {
+    let location = location.trim();
+    if location.is_empty() {
+        return (None, None, None);
+    }
+
+    let mut numbers = Vec::new();
+    let mut rest = location;
+    while numbers.len() < 2 {
+        match rest.rsplit_once(':') {
+            Some((head, tail)) if !tail.is_empty() && tail.chars().all(|c| c.is_ascii_digit()) => {
+                numbers.push(tail.parse().ok());
+                rest = head;
+            }
+            _ => break,
+        }
+    }
+
+    let (line, column) = match numbers.as_slice() {
+        [column, line] => (*line, *column),
+        [line] => (*line, None),
+        _ => (None, None),
+    };
+    (Some(rest.to_string()), line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(file: &str, function: Option<&str>, line: u32, in_app: bool) -> StackFrame {
+        StackFrame {
+            file: Some(file.to_string()),
+            function: function.map(str::to_string),
+            line: Some(line),
+            column: None,
+            in_app,
+        }
+    }
+
+    #[test]
+    fn parses_python_innermost_first() {
+        let traceback = r#"Traceback (most recent call last):
+  File "/usr/lib/python3.11/site-packages/flask/app.py", line 1484, in full_dispatch_request
+    rv = self.dispatch_request()
+  File "/app/views.py", line 42, in get_user
+    return users[user_id]
+KeyError: 7"#;
+
+        assert_eq!(detect_format(traceback), Some(TracebackFormat::Python));
+        assert_eq!(
+            parse(traceback),
+            vec![
+                frame("/app/views.py", Some("get_user"), 42, true),
+                frame(
+                    "/usr/lib/python3.11/site-packages/flask/app.py",
+                    Some("full_dispatch_request"),
+                    1484,
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_last_python_traceback_of_a_chain() {
+        let traceback = r#"Traceback (most recent call last):
+  File "/app/db.py", line 5, in load
+    raise IOError()
+OSError
+
+During handling of the above exception, another exception occurred:
+
+Traceback (most recent call last):
+  File "/app/views.py", line 12, in show
+    load()
+RuntimeError: failed"#;
+
+        assert_eq!(
+            parse(traceback),
+            vec![frame("/app/views.py", Some("show"), 12, true)]
+        );
+    }
+
+    #[test]
+    fn parses_v8() {
+        let traceback = "TypeError: Cannot read properties of undefined (reading 'id')
+    at getUser (/app/src/users.js:10:15)
+    at Layer.handle [as handle_request] (/app/node_modules/express/lib/router/layer.js:95:5)
+    at node:internal/process/task_queues:95:5
+    at async Promise.all (index 0)";
+
+        assert_eq!(detect_format(traceback), Some(TracebackFormat::V8));
+        let frames = parse(traceback);
+        assert_eq!(frames.len(), 4);
+        assert_eq!(
+            frames[0],
+            StackFrame {
+                file: Some("/app/src/users.js".to_string()),
+                function: Some("getUser".to_string()),
+                line: Some(10),
+                column: Some(15),
+                in_app: true,
+            }
+        );
+        assert_eq!(
+            frames[1].function.as_deref(),
+            Some("Layer.handle [as handle_request]")
+        );
+        assert!(!frames[1].in_app);
+        assert_eq!(frames[2].function, None);
+        assert!(!frames[2].in_app);
+        assert_eq!(frames[3].file, None);
+        assert!(!frames[3].in_app);
+    }
+
+    #[test]
+    fn parses_jvm_up_to_the_cause() {
+        let traceback = "java.lang.IllegalStateException: boom
+	at com.example.Service.handle(Service.java:42)
+	at java.base/java.lang.Thread.run(Thread.java:833)
+	at jdk.internal.reflect.NativeMethodAccessorImpl.invoke0(Native Method)
+Caused by: java.io.IOException: closed
+	at com.example.Store.read(Store.java:7)";
+
+        assert_eq!(detect_format(traceback), Some(TracebackFormat::Jvm));
+        let frames = parse(traceback);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            frames[0],
+            frame("Service.java", Some("com.example.Service.handle"), 42, true)
+        );
+        assert_eq!(
+            frames[1],
+            frame("Thread.java", Some("java.lang.Thread.run"), 833, false)
+        );
+        assert_eq!(frames[2].file, None);
+        assert_eq!(frames[2].line, None);
+        assert!(!frames[2].in_app);
+    }
+
+    #[test]
+    fn parses_rust_backtrace() {
+        let traceback = "thread 'main' panicked at src/main.rs:10:5:
+index out of bounds
+stack backtrace:
+   0: rust_begin_unwind
+             at /rustc/90b35a6239c3d8bdabc530a6a0816f7ff89a0aaf/library/std/src/panicking.rs:645:5
+   1: myapp::handler::h3f2a4b5c6d7e8f90
+             at ./src/handler.rs:10:5
+   2: tokio::runtime::task::harness::poll
+             at /home/dev/.cargo/registry/src/index.crates.io-6f17d22bba15001f/tokio-1.37.0/src/runtime/task/harness.rs:473:13
+   3: main";
+
+        assert_eq!(detect_format(traceback), Some(TracebackFormat::Rust));
+        let frames = parse(traceback);
+        assert_eq!(frames.len(), 4);
+        assert!(!frames[0].in_app);
+        assert_eq!(
+            frames[1],
+            StackFrame {
+                file: Some("./src/handler.rs".to_string()),
+                function: Some("myapp::handler".to_string()),
+                line: Some(10),
+                column: Some(5),
+                in_app: true,
+            }
+        );
+        assert!(!frames[2].in_app);
+        assert_eq!(frames[3].function.as_deref(), Some("main"));
+        assert_eq!(frames[3].file, None);
+    }
+
+    #[test]
+    fn uses_the_rust_panic_location_without_a_backtrace() {
+        let current = "thread 'main' panicked at src/main.rs:10:5:\nboom";
+        let legacy = "thread 'main' panicked at 'boom', src/main.rs:10:5";
+
+        for traceback in [current, legacy] {
+            assert_eq!(
+                parse(traceback),
+                vec![StackFrame {
+                    file: Some("src/main.rs".to_string()),
+                    function: None,
+                    line: Some(10),
+                    column: Some(5),
+                    in_app: true,
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn known_format_without_frames() {
+        let traceback = "Traceback (most recent call last):\nKeyError: 'id'";
+
+        assert_eq!(detect_format(traceback), Some(TracebackFormat::Python));
+        assert!(parse(traceback).is_empty());
+    }
+
+    #[test]
+    fn unknown_format() {
+        let traceback = "Segmentation fault (core dumped)";
+
+        assert_eq!(detect_format(traceback), None);
+        assert!(parse(traceback).is_empty());
+        assert!(parse("").is_empty());
+    }
+
+    #[test]
+    fn splits_locations_from_the_right() {
+        assert_eq!(
+            split_location("file:///C:/app/main.js:3:7"),
+            (Some("file:///C:/app/main.js".to_string()), Some(3), Some(7))
+        );
+        assert_eq!(
+            split_location("Main.java:12"),
+            (Some("Main.java".to_string()), Some(12), None)
+        );
+        assert_eq!(split_location("  "), (None, None, None));
+    }
+}
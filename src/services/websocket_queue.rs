@@ -19,6 +19,12 @@ pub struct WebSocketQueue {
     queue: Arc<RwLock<VecDeque<RetryQueueEntry>>>,
 }
 
+impl Default for WebSocketQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl WebSocketQueue {
     /// Creates a new WebSocketQueue instance.
     pub fn new() -> Self {
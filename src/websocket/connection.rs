@@ -11,6 +11,22 @@ pub struct SendLogId {
     pub app_id: ObjectId,
 }
 
+/// Several logs stored by one batch request, delivered as a single message.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SendLogBatch {
+    pub log_ids: Vec<ObjectId>,
+    pub app_id: ObjectId,
+}
+
+/// Sent when an event reopens a resolved issue.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct IssueRegressed {
+    pub issue_id: ObjectId,
+    pub app_id: ObjectId,
+}
+
 /// Represents a WebSocket connection.
 pub struct WebSocketActor {
     pub organization_id: ObjectId,
@@ -36,6 +52,32 @@ impl Handler<SendLogId> for WebSocketActor {
     }
 }
 
+impl Handler<SendLogBatch> for WebSocketActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendLogBatch, ctx: &mut Self::Context) {
+        let log_ids: Vec<String> = msg.log_ids.iter().map(|id| id.to_string()).collect();
+        let message = format!(
+            "New log IDs: {} and App ID: {}",
+            log_ids.join(","),
+            msg.app_id
+        );
+        ctx.text(message);
+    }
+}
+
+impl Handler<IssueRegressed> for WebSocketActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: IssueRegressed, ctx: &mut Self::Context) {
+        let message = format!(
+            "Issue regressed ID: {} and App ID: {}",
+            msg.issue_id, msg.app_id
+        );
+        ctx.text(message);
+    }
+}
+
 
 
 
@@ -52,7 +94,7 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketActor {
 
                 // Get the current actor's address
                 let conn = ctx.address();
-                let org_id = self.organization_id.clone();
+                let org_id = self.organization_id;
 
                 // Call remove_connection asynchronously
                 let websocket_server = crate::websocket::server::WebSocketServer::new(); 
@@ -11,6 +11,12 @@ pub struct WebSocketServer {
     connections: Arc<RwLock<HashMap<String, Vec<Addr<WebSocketActor>>>>>,
 }
 
+impl Default for WebSocketServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl WebSocketServer {
     /// Creates a new WebSocketServer instance.
     pub fn new() -> Self {
@@ -46,7 +52,7 @@ impl WebSocketServer {
         let connections = self.connections.read().await;
         connections
             .get(&org_id_str)
-            .and_then(|conn_list| conn_list.get(0).cloned())
+            .and_then(|conn_list| conn_list.first().cloned())
     }
     
 
@@ -69,5 +75,53 @@ impl WebSocketServer {
         log::warn!("No WebSocket connection found for Org ID: {}", org_id);
         false
     }
-    
-}
\ No newline at end of file
+
+    /// Pushes the log IDs of a batch to every connection of the organization in one message.
+    pub async fn push_log_ids(
+        &self,
+        org_id: ObjectId,
+        app_id: ObjectId,
+        log_ids: Vec<ObjectId>,
+    ) -> bool {
+        let connections = self.connections.read().await;
+
+        if let Some(conn_list) = connections.get(&org_id.to_string()) {
+            log::info!(
+                "Pushing {} log IDs to WebSocket connections for Org ID: {} and App ID: {}",
+                log_ids.len(),
+                org_id,
+                app_id
+            );
+            for conn in conn_list {
+                conn.do_send(crate::websocket::connection::SendLogBatch {
+                    log_ids: log_ids.clone(),
+                    app_id,
+                });
+            }
+            return true;
+        }
+
+        log::warn!("No WebSocket connection found for Org ID: {}", org_id);
+        false
+    }
+
+    /// Notifies every connection of the organization that an issue regressed.
+    pub async fn push_issue_regression(
+        &self,
+        org_id: ObjectId,
+        app_id: ObjectId,
+        issue_id: ObjectId,
+    ) -> bool {
+        let connections = self.connections.read().await;
+
+        if let Some(conn_list) = connections.get(&org_id.to_string()) {
+            for conn in conn_list {
+                conn.do_send(crate::websocket::connection::IssueRegressed { issue_id, app_id });
+            }
+            return true;
+        }
+
+        log::warn!("No WebSocket connection found for Org ID: {}", org_id);
+        false
+    }
+}